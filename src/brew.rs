@@ -1,7 +1,30 @@
 use anyhow::Result;
-use std::process::Command;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `verify_installation` failed; `run` maps this to its own exit code.
+#[derive(Debug)]
+pub struct BrewUnavailable(pub anyhow::Error);
+
+impl std::fmt::Display for BrewUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for BrewUnavailable {}
+
+/// Casks are macOS-only; Homebrew on Linux has no cask commands, so the cask
+/// queries are skipped there rather than spawning brew just to fail.
+pub const SUPPORTS_CASKS: bool = !cfg!(target_os = "linux");
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OutdatedPackage {
     pub name: String,
     pub current_version: String,
@@ -9,37 +32,175 @@ pub struct OutdatedPackage {
     pub package_type: PackageType,
 }
 
-#[derive(Debug, Clone)]
+impl OutdatedPackage {
+    /// Whether brew is offering a version that isn't newer than the installed one.
+    pub fn is_downgrade(&self) -> bool {
+        crate::version::is_downgrade(&self.current_version, &self.available_version)
+    }
+}
+
+/// A package name without its tap (`homebrew/core/git` → `git`), which is how
+/// the settings file lists packages.
+pub fn short_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// Whether two names refer to the same package. A tap prefix is ignored when
+/// only one side has it; two tap-qualified names must match in full, since
+/// different taps can ship formulae with the same name.
+pub fn same_package(a: &str, b: &str) -> bool {
+    if a.contains('/') && b.contains('/') {
+        return a == b;
+    }
+    short_name(a) == short_name(b)
+}
+
+/// Whether `names` includes `name`, by the same rules as `same_package`.
+pub fn lists_package(names: &[String], name: &str) -> bool {
+    names.iter().any(|listed| same_package(listed, name))
+}
+
+/// Ordered so formulae sort ahead of casks.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PackageType {
     Formula,
     Cask,
 }
 
+/// Metadata about a package from `brew info`.
+#[derive(Debug, Clone, Default)]
+pub struct PackageInfo {
+    pub homepage: Option<String>,
+    pub description: Option<String>,
+}
+
 pub trait BrewExecutor {
     fn verify_installation(&self) -> Result<()>;
     fn get_manually_installed_formulae(&self) -> Result<Vec<String>>;
     fn get_manually_installed_casks(&self) -> Result<Vec<String>>;
     fn get_outdated_packages(&self) -> Result<Vec<OutdatedPackage>>;
-    fn upgrade_package(&self, package: &OutdatedPackage) -> Result<()>;
+    /// Like `get_outdated_packages`, but only checks the named formulae and casks.
+    fn get_outdated_packages_for(
+        &self,
+        formulae: &[String],
+        casks: &[String],
+    ) -> Result<Vec<OutdatedPackage>>;
+    /// Upgrade one package, returning brew's output (caveats included). With
+    /// `stream`, brew writes straight to the terminal and nothing is returned.
+    /// `extra_args` come from the package's `{args: ...}` in the settings.
+    fn upgrade_package(
+        &self,
+        package: &OutdatedPackage,
+        extra_args: &[String],
+        stream: bool,
+    ) -> Result<String>;
     fn get_version(&self) -> Result<String>;
     fn get_system_info(&self) -> Result<crate::stats::SystemInfo>;
+    fn get_running_services(&self) -> Result<Vec<String>>;
+    fn get_pinned_formulae(&self) -> Result<Vec<String>>;
+    /// Installed casks marked `auto_updates true`, i.e. apps that update themselves.
+    fn get_auto_updating_casks(&self) -> Result<Vec<String>>;
+    /// Installed version of every formula and cask, from `brew list --versions`.
+    fn get_installed_versions(&self) -> Result<Vec<(String, String, PackageType)>>;
+    fn get_deps(&self, name: &str) -> Result<Vec<String>>;
+    /// How many installed dependencies each formula in `names` has, from a
+    /// single `brew deps --installed --for-each` call.
+    fn get_installed_dep_counts(&self, names: &[String]) -> Result<HashMap<String, usize>>;
+    fn get_package_info(&self, name: &str, package_type: &PackageType) -> Result<PackageInfo>;
+    /// Disk space in bytes of the installed (old) version, if known.
+    fn get_package_size(&self, pkg: &OutdatedPackage) -> Result<Option<u64>>;
+    /// One-line description from `brew desc`, if brew has one.
+    fn get_description(&self, name: &str) -> Result<Option<String>>;
+    /// Run `brew cleanup`, returning its output.
+    fn cleanup(&self) -> Result<String>;
+    /// Whether another brew process is running, judged by the locks Homebrew
+    /// holds under `$(brew --prefix)/var/homebrew/locks`.
+    fn is_brew_busy(&self) -> Result<bool>;
+    /// Download the package's new version into brew's cache without installing it.
+    fn fetch_package(&self, package: &OutdatedPackage) -> Result<()>;
+}
+
+pub struct SystemBrewExecutor {
+    brew_path: PathBuf,
+    no_auto_update: bool,
+    upgrade_timeout: Option<Duration>,
+    no_sudo: bool,
+    greedy: bool,
+}
+
+impl Default for SystemBrewExecutor {
+    fn default() -> Self {
+        Self::new(None)
+    }
 }
 
-pub struct SystemBrewExecutor;
+impl SystemBrewExecutor {
+    /// Use `brew_path` as the brew executable, or look `brew` up on `PATH`.
+    pub fn new(brew_path: Option<PathBuf>) -> Self {
+        Self {
+            brew_path: brew_path.unwrap_or_else(|| PathBuf::from("brew")),
+            no_auto_update: false,
+            upgrade_timeout: None,
+            no_sudo: false,
+            greedy: true,
+        }
+    }
+
+    /// Count casks that update themselves as outdated (`brew outdated --greedy`).
+    pub fn with_greedy(mut self, greedy: bool) -> Self {
+        self.greedy = greedy;
+        self
+    }
+
+    /// Make upgrades that need `sudo` fail straight away instead of waiting for a password.
+    pub fn with_no_sudo(mut self, no_sudo: bool) -> Self {
+        self.no_sudo = no_sudo;
+        self
+    }
+
+    /// Kill a single `brew upgrade` that runs longer than `timeout`.
+    pub fn with_upgrade_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.upgrade_timeout = timeout;
+        self
+    }
+
+    /// Stop brew from running its implicit `brew update` before commands.
+    pub fn with_no_auto_update(mut self, no_auto_update: bool) -> Self {
+        self.no_auto_update = no_auto_update;
+        self
+    }
+
+    pub fn brew_command(&self) -> Command {
+        let mut command = Command::new(&self.brew_path);
+        if self.no_auto_update {
+            command.env("HOMEBREW_NO_AUTO_UPDATE", "1");
+        }
+        if self.no_sudo {
+            // brew runs `sudo -A` when an askpass helper is set; this one always fails
+            command.env("SUDO_ASKPASS", FAILING_ASKPASS);
+        }
+        command
+    }
+}
 
 impl BrewExecutor for SystemBrewExecutor {
     fn verify_installation(&self) -> Result<()> {
-        let output = Command::new("brew").arg("--version").output();
+        let output = self.brew_command().arg("--version").output();
         match output {
             Ok(_) => Ok(()),
             Err(_) => {
-                anyhow::bail!("Homebrew is not installed or not in PATH. Please install Homebrew first: https://brew.sh/");
+                anyhow::bail!(
+                    "Homebrew is not installed or not in PATH (tried {}). Please install Homebrew first: https://brew.sh/ or pass --brew-path",
+                    self.brew_path.display()
+                );
             }
         }
     }
 
     fn get_manually_installed_formulae(&self) -> Result<Vec<String>> {
-        let output = Command::new("brew")
+        let output = self
+            .brew_command()
             .args(["leaves", "--installed-on-request"])
             .output()?;
 
@@ -50,17 +211,15 @@ impl BrewExecutor for SystemBrewExecutor {
             );
         }
 
-        let packages = String::from_utf8(output.stdout)?
-            .lines()
-            .map(|line| line.trim().to_string())
-            .filter(|line| !line.is_empty())
-            .collect();
-
-        Ok(packages)
+        Ok(parse_package_list(&output.stdout))
     }
 
     fn get_manually_installed_casks(&self) -> Result<Vec<String>> {
-        let all_casks_output = Command::new("brew").args(["list", "--cask"]).output()?;
+        if !SUPPORTS_CASKS {
+            return Ok(Vec::new());
+        }
+
+        let all_casks_output = self.brew_command().args(["list", "--cask"]).output()?;
 
         if !all_casks_output.status.success() {
             anyhow::bail!(
@@ -69,68 +228,135 @@ impl BrewExecutor for SystemBrewExecutor {
             );
         }
 
-        let all_casks: Vec<String> = String::from_utf8(all_casks_output.stdout)?
-            .lines()
-            .map(|line| line.trim().to_string())
-            .filter(|line| !line.is_empty())
-            .collect();
-
-        Ok(all_casks)
+        Ok(parse_package_list(&all_casks_output.stdout))
     }
 
     fn get_outdated_packages(&self) -> Result<Vec<OutdatedPackage>> {
         let mut outdated = Vec::new();
 
         // Get outdated formulae
-        let formulae_output = Command::new("brew")
+        let formulae_output = self
+            .brew_command()
             .args(["outdated", "--formula", "--verbose"])
             .output()?;
 
         if formulae_output.status.success() {
-            let formulae_text = String::from_utf8(formulae_output.stdout)?;
-            for line in formulae_text.lines() {
-                if let Some(package) = parse_outdated_line(line, PackageType::Formula) {
-                    outdated.push(package);
-                }
-            }
+            outdated.extend(parse_outdated_output(
+                &formulae_output.stdout,
+                PackageType::Formula,
+            ));
+        }
+
+        if !SUPPORTS_CASKS {
+            return Ok(outdated);
         }
 
         // Get outdated casks
-        let casks_output = Command::new("brew")
-            .args(["outdated", "--cask", "--greedy", "--verbose"])
+        let casks_output = self
+            .brew_command()
+            .args(outdated_cask_args(self.greedy))
             .output()?;
 
         if casks_output.status.success() {
-            let casks_text = String::from_utf8(casks_output.stdout)?;
-            for line in casks_text.lines() {
-                if let Some(package) = parse_outdated_line(line, PackageType::Cask) {
-                    outdated.push(package);
-                }
-            }
+            outdated.extend(parse_outdated_output(
+                &casks_output.stdout,
+                PackageType::Cask,
+            ));
         }
 
         Ok(outdated)
     }
 
-    fn upgrade_package(&self, package: &OutdatedPackage) -> Result<()> {
-        let cmd = "upgrade";
-        let args = match package.package_type {
-            PackageType::Formula => vec![cmd, &package.name],
-            PackageType::Cask => vec![cmd, "--cask", &package.name],
-        };
+    fn get_outdated_packages_for(
+        &self,
+        formulae: &[String],
+        casks: &[String],
+    ) -> Result<Vec<OutdatedPackage>> {
+        let mut outdated = Vec::new();
+
+        // An empty name list would make brew scan everything, so skip the call
+        if !formulae.is_empty() {
+            let formulae_output = self
+                .brew_command()
+                .args(["outdated", "--formula", "--verbose"])
+                .args(formulae)
+                .output()?;
+
+            outdated.extend(parse_scoped_outdated_output(
+                formulae_output.status.code(),
+                &formulae_output.stdout,
+                &formulae_output.stderr,
+                PackageType::Formula,
+            )?);
+        }
 
-        let output = Command::new("brew").args(&args).output()?;
+        if SUPPORTS_CASKS && !casks.is_empty() {
+            let casks_output = self
+                .brew_command()
+                .args(outdated_cask_args(self.greedy))
+                .args(casks)
+                .output()?;
+
+            outdated.extend(parse_scoped_outdated_output(
+                casks_output.status.code(),
+                &casks_output.stdout,
+                &casks_output.stderr,
+                PackageType::Cask,
+            )?);
+        }
+
+        Ok(outdated)
+    }
+
+    fn upgrade_package(
+        &self,
+        package: &OutdatedPackage,
+        extra_args: &[String],
+        stream: bool,
+    ) -> Result<String> {
+        let args = upgrade_args(package, extra_args);
+
+        if stream {
+            let status = status_with_timeout(self.brew_command().args(&args), self.upgrade_timeout)
+                .map_err(|e| upgrade_error(package, &e.to_string()))?;
+            if !status.success() {
+                anyhow::bail!(
+                    "Failed to upgrade {}: brew exited with {}",
+                    package.name,
+                    status
+                );
+            }
+            return Ok(String::new());
+        }
+
+        // Captured upgrades get no stdin, so a prompt fails instead of waiting forever
+        let output = output_with_timeout(
+            self.brew_command().args(&args).stdin(Stdio::null()),
+            self.upgrade_timeout,
+        )
+        .map_err(|e| upgrade_error(package, &e.to_string()))?;
 
         if !output.status.success() {
-            let error_msg = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("Failed to upgrade {}: {}", package.name, error_msg);
+            return Err(upgrade_error(
+                package,
+                &format!(
+                    "{}{}",
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            ));
         }
 
-        Ok(())
+        // Caveats may land on either stream depending on the brew version
+        Ok(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))
     }
 
     fn get_version(&self) -> Result<String> {
-        let output = Command::new("brew").arg("--version").output()?;
+        let output = self.brew_command().arg("--version").output()?;
 
         if !output.status.success() {
             anyhow::bail!(
@@ -145,111 +371,682 @@ impl BrewExecutor for SystemBrewExecutor {
     }
 
     fn get_system_info(&self) -> Result<crate::stats::SystemInfo> {
-        // Get Homebrew prefix
-        let homebrew_prefix = {
-            let output = Command::new("brew").arg("--prefix").output()?;
-            if output.status.success() {
-                String::from_utf8_lossy(&output.stdout).trim().to_string()
-            } else {
-                "/usr/local".to_string() // fallback
+        // Each detail falls back to "Unknown" on its own, so minimal systems
+        // without sw_vers, uname or a working brew still get stats
+        Ok(crate::stats::SystemInfo {
+            os_version: get_os_version_safe(),
+            architecture: get_architecture_safe(),
+            homebrew_prefix: command_stdout(self.brew_command().arg("--prefix"))
+                .unwrap_or_else(|| "Unknown".to_string()),
+        })
+    }
+
+    fn get_running_services(&self) -> Result<Vec<String>> {
+        let output = self.brew_command().args(["services", "list"]).output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to list brew services: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(parse_services_list(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+
+    fn get_pinned_formulae(&self) -> Result<Vec<String>> {
+        let output = self.brew_command().args(["list", "--pinned"]).output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to list pinned formulae: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(parse_package_list(&output.stdout))
+    }
+
+    fn get_auto_updating_casks(&self) -> Result<Vec<String>> {
+        if !SUPPORTS_CASKS {
+            return Ok(Vec::new());
+        }
+
+        let output = self
+            .brew_command()
+            .args(["info", "--json=v2", "--installed", "--cask"])
+            .output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to get cask info: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        parse_auto_updating_casks(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn get_installed_versions(&self) -> Result<Vec<(String, String, PackageType)>> {
+        let mut versions = Vec::new();
+        let lists = [
+            ("--formula", PackageType::Formula),
+            ("--cask", PackageType::Cask),
+        ];
+        for (flag, package_type) in lists
+            .into_iter()
+            .filter(|(_, package_type)| SUPPORTS_CASKS || *package_type != PackageType::Cask)
+        {
+            let output = self
+                .brew_command()
+                .args(["list", flag, "--versions"])
+                .output()?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "Failed to list installed versions: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
             }
+            let mut listed: Vec<_> = parse_versions_list(&output.stdout).into_iter().collect();
+            listed.sort();
+            versions.extend(
+                listed
+                    .into_iter()
+                    .map(|(name, version)| (name, version, package_type.clone())),
+            );
+        }
+
+        Ok(versions)
+    }
+
+    fn get_deps(&self, name: &str) -> Result<Vec<String>> {
+        let output = self.brew_command().args(["deps", name]).output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to get dependencies of {}: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(parse_package_list(&output.stdout))
+    }
+
+    fn get_installed_dep_counts(&self, names: &[String]) -> Result<HashMap<String, usize>> {
+        if names.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let output = self
+            .brew_command()
+            .args(["deps", "--installed", "--for-each"])
+            .args(names)
+            .output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to get installed dependencies: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(parse_deps_for_each(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+
+    fn get_package_info(&self, name: &str, package_type: &PackageType) -> Result<PackageInfo> {
+        let type_flag = match package_type {
+            PackageType::Formula => "--formula",
+            PackageType::Cask => "--cask",
         };
+        let output = self
+            .brew_command()
+            .args(["info", "--json=v2", type_flag, name])
+            .output()?;
 
-        // Get OS version with cross-platform support
-        let os_version = {
-            #[cfg(target_os = "macos")]
-            {
-                let output = Command::new("sw_vers").arg("-productVersion").output();
-                match output {
-                    Ok(out) if out.status.success() => {
-                        format!("macOS {}", String::from_utf8_lossy(&out.stdout).trim())
-                    }
-                    _ => "macOS Unknown".to_string(),
-                }
-            }
-            #[cfg(target_os = "linux")]
-            {
-                // Try to read from /etc/os-release
-                if let Ok(content) = std::fs::read_to_string("/etc/os-release") {
-                    if let Some(line) = content.lines().find(|l| l.starts_with("PRETTY_NAME=")) {
-                        if let Some(name) = line
-                            .strip_prefix("PRETTY_NAME=")
-                            .map(|s| s.trim_matches('"'))
-                        {
-                            return Ok(crate::stats::SystemInfo {
-                                os_version: name.to_string(),
-                                architecture: get_architecture_safe(),
-                                homebrew_prefix,
-                            });
-                        }
-                    }
-                }
-                "Linux".to_string()
-            }
-            #[cfg(not(any(target_os = "macos", target_os = "linux")))]
-            {
-                "Unknown OS".to_string()
-            }
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to get info for {}: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        parse_package_info(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn get_package_size(&self, pkg: &OutdatedPackage) -> Result<Option<u64>> {
+        // `brew info --json=v2` carries no bottle sizes, so estimate from the
+        // installed keg; casks are left unknown
+        if pkg.package_type != PackageType::Formula {
+            return Ok(None);
+        }
+
+        let output = self.brew_command().args(["--cellar", &pkg.name]).output()?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        let cellar = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        // Several installed versions are listed as "1.0, 1.1"; the last is the newest
+        let version = pkg
+            .current_version
+            .rsplit(", ")
+            .next()
+            .unwrap_or(&pkg.current_version);
+        let keg = cellar.join(version);
+        if !keg.is_dir() {
+            return Ok(None);
+        }
+
+        Ok(Some(dir_size(&keg)?))
+    }
+
+    fn get_description(&self, name: &str) -> Result<Option<String>> {
+        let output = self.brew_command().args(["desc", name]).output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to get description of {}: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(parse_description(&output.stdout, name))
+    }
+
+    fn cleanup(&self) -> Result<String> {
+        let output = self.brew_command().arg("cleanup").output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "brew cleanup failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+
+    fn is_brew_busy(&self) -> Result<bool> {
+        let output = self.brew_command().arg("--prefix").output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to get the Homebrew prefix: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        let prefix = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        any_lock_held(&prefix.join("var").join("homebrew").join("locks"))
+    }
+
+    fn fetch_package(&self, package: &OutdatedPackage) -> Result<()> {
+        let type_flag = match package.package_type {
+            PackageType::Formula => "--formula",
+            PackageType::Cask => "--cask",
         };
+        let output = self
+            .brew_command()
+            .args(["fetch", type_flag, &package.name])
+            .stdin(Stdio::null())
+            .output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to fetch {}: {}",
+                package.name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+}
 
-        // Get architecture
-        let architecture = get_architecture_safe();
+/// Whether another process holds one of the `*.lock` files in `dir`.
+///
+/// Homebrew leaves its lock files behind and only `flock`s them while it runs,
+/// so a file's presence means nothing; try the lock instead.
+pub fn any_lock_held(dir: &Path) -> Result<bool> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "lock") {
+            continue;
+        }
+        // A lock we can't open can't tell us anything
+        let Ok(file) = std::fs::File::open(&path) else {
+            continue;
+        };
+        match file.try_lock() {
+            Ok(()) => file.unlock()?,
+            Err(std::fs::TryLockError::WouldBlock) => return Ok(true),
+            Err(std::fs::TryLockError::Error(_)) => {}
+        }
+    }
+    Ok(false)
+}
 
-        Ok(crate::stats::SystemInfo {
-            os_version,
-            architecture,
-            homebrew_prefix,
+/// Run `command` to completion like `Command::output`, killing it if it
+/// outlives `timeout`.
+pub fn output_with_timeout(command: &mut Command, timeout: Option<Duration>) -> Result<Output> {
+    let Some(timeout) = timeout else {
+        return Ok(command.output()?);
+    };
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Drain the pipes on their own threads so a chatty child can't block on a full pipe
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
         })
+    };
+    let stdout = drain(
+        child
+            .stdout
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+    let stderr = drain(
+        child
+            .stderr
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+
+    let status = wait_with_timeout(&mut child, timeout)?;
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Like `output_with_timeout`, but the child writes straight to our terminal.
+pub fn status_with_timeout(command: &mut Command, timeout: Option<Duration>) -> Result<ExitStatus> {
+    let Some(timeout) = timeout else {
+        return Ok(command.status()?);
+    };
+
+    let mut child = command.spawn()?;
+    wait_with_timeout(&mut child, timeout)
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<ExitStatus> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("timed out after {}s", timeout.as_secs());
+        }
+        thread::sleep(Duration::from_millis(100));
     }
 }
 
-fn get_architecture_safe() -> String {
-    let output = Command::new("uname").arg("-m").output();
-    match output {
-        Ok(out) if out.status.success() => {
-            let arch = String::from_utf8_lossy(&out.stdout).trim().to_string();
-            match arch.as_str() {
-                "arm64" => "Apple Silicon".to_string(),
-                "x86_64" => "Intel".to_string(),
-                _ => arch,
-            }
+/// Total size of the files under `dir`, without following symlinks.
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = std::fs::symlink_metadata(entry.path())?;
+        total += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(total)
+}
+
+/// Arguments for `brew outdated` on casks; `greedy` includes casks that update themselves.
+fn outdated_cask_args(greedy: bool) -> Vec<&'static str> {
+    if greedy {
+        vec!["outdated", "--cask", "--greedy", "--verbose"]
+    } else {
+        vec!["outdated", "--cask", "--verbose"]
+    }
+}
+
+/// Trimmed stdout of `command`, or `None` if it can't be run, fails or prints nothing.
+fn command_stdout(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!stdout.is_empty()).then_some(stdout)
+}
+
+fn get_os_version_safe() -> String {
+    #[cfg(target_os = "macos")]
+    {
+        match command_stdout(Command::new("sw_vers").arg("-productVersion")) {
+            Some(version) => format!("macOS {}", version),
+            None => "macOS Unknown".to_string(),
         }
-        _ => "Unknown".to_string(),
+    }
+    #[cfg(target_os = "linux")]
+    {
+        std::fs::read_to_string("/etc/os-release")
+            .ok()
+            .and_then(|content| {
+                content
+                    .lines()
+                    .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+                    .map(|name| name.trim_matches('"').to_string())
+            })
+            .unwrap_or_else(|| "Linux".to_string())
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    {
+        "Unknown OS".to_string()
     }
 }
 
+fn get_architecture_safe() -> String {
+    match command_stdout(Command::new("uname").arg("-m")).as_deref() {
+        Some("arm64") => "Apple Silicon".to_string(),
+        Some("x86_64") => "Intel".to_string(),
+        Some(arch) => arch.to_string(),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// One package name per line, as printed by `brew leaves`, `brew list` and
+/// `brew deps`. Invalid UTF-8 is replaced rather than failing the whole list.
+pub fn parse_package_list(stdout: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Parse `brew deps --for-each` lines (`name: dep1 dep2`) into dependency counts.
+pub fn parse_deps_for_each(text: &str) -> HashMap<String, usize> {
+    text.lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, deps)| (name.trim().to_string(), deps.split_whitespace().count()))
+        .collect()
+}
+
+/// Parse `brew list --versions` lines (`name v1 v2 ...`), keeping the last,
+/// most recently installed, version of each package. Like `parse_package_list`,
+/// invalid UTF-8 is replaced rather than dropping the whole list.
+pub fn parse_versions_list(stdout: &[u8]) -> HashMap<String, String> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            let version = fields.last()?;
+            Some((name.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+/// Arguments for `brew upgrade` of one package, with the settings' extra
+/// arguments placed before the package name.
+pub fn upgrade_args(package: &OutdatedPackage, extra_args: &[String]) -> Vec<String> {
+    let mut args = vec!["upgrade".to_string()];
+    if package.package_type == PackageType::Cask {
+        args.push("--cask".to_string());
+    }
+    args.extend(extra_args.iter().cloned());
+    args.push(package.name.clone());
+    args
+}
+
+/// Pick `name`'s description out of `brew desc` output (`name: description`).
+pub fn parse_description(stdout: &[u8], name: &str) -> Option<String> {
+    let prefix = format!("{}: ", name);
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix).map(str::trim))
+        .filter(|desc| !desc.is_empty())
+        .map(str::to_string)
+}
+
+pub fn parse_outdated_output(stdout: &[u8], package_type: PackageType) -> Vec<OutdatedPackage> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|line| parse_outdated_line(line, package_type.clone()))
+        .collect()
+}
+
+/// Output of `brew outdated <names>`, which exits 1 whenever a named package
+/// is outdated. Only other exit codes, or a 1 with an error on stderr (e.g. an
+/// unknown name), mean the query failed.
+pub fn parse_scoped_outdated_output(
+    code: Option<i32>,
+    stdout: &[u8],
+    stderr: &[u8],
+    package_type: PackageType,
+) -> Result<Vec<OutdatedPackage>> {
+    let stderr = String::from_utf8_lossy(stderr);
+    match code {
+        Some(0) => {}
+        Some(1) if stderr.trim().is_empty() => {}
+        _ => anyhow::bail!("brew outdated failed: {}", stderr.trim()),
+    }
+    Ok(parse_outdated_output(stdout, package_type))
+}
+
 pub fn parse_outdated_line(line: &str, package_type: PackageType) -> Option<OutdatedPackage> {
-    // Format: "package (current_version) < available_version" or "package (current_version) != available_version"
-    if let Some(pos) = line.find(" (") {
-        let name = line[..pos].trim().to_string();
+    // Format: "package (current_version) < available_version" or "package (current_version) != available_version",
+    // and the same without parentheses around the current version
+    let (name, current_version, remainder) = if let Some(pos) = line.find(" (") {
         let rest = &line[pos + 2..];
+        let end_paren = rest.find(") ")?;
+        (
+            line[..pos].trim(),
+            &rest[..end_paren],
+            rest[end_paren + 2..].trim(),
+        )
+    } else {
+        let mut parts = line.trim().splitn(3, ' ');
+        (parts.next()?, parts.next()?, parts.next()?.trim())
+    };
+
+    if name.is_empty() || name.contains(char::is_whitespace) || current_version.is_empty() {
+        return None;
+    }
 
-        if let Some(end_paren) = rest.find(") ") {
-            let current_version = rest[..end_paren].to_string();
-            let remainder = &rest[end_paren + 2..].trim();
+    // Skip the comparison operator (< or !=) and get the available version
+    let (operator, available_version) = remainder.split_once(' ')?;
+    // Pinned formulae carry a trailing "[pinned at x]" note
+    let available_version = available_version
+        .split(" [")
+        .next()
+        .unwrap_or_default()
+        .trim();
+    if !matches!(operator, "<" | "!=") || available_version.contains(char::is_whitespace) {
+        return None;
+    }
+    // `--greedy` occasionally lists a cask as outdated against its own version;
+    // upgrading it would be a no-op
+    if current_version.trim() == available_version {
+        return None;
+    }
 
-            // Skip the comparison operator (< or !=) and get the available version
-            if let Some(space_pos) = remainder.find(' ') {
-                let available_version = remainder[space_pos + 1..].trim().to_string();
+    Some(OutdatedPackage {
+        name: name.to_string(),
+        current_version: current_version.to_string(),
+        available_version: available_version.to_string(),
+        package_type,
+    })
+}
 
-                return Some(OutdatedPackage {
-                    name,
-                    current_version,
-                    available_version,
-                    package_type,
-                });
-            }
+/// Extract the first package's metadata from `brew info --json=v2` output.
+pub fn parse_package_info(json: &str) -> Result<PackageInfo> {
+    let value: serde_json::Value = serde_json::from_str(json)?;
+    let entry = ["formulae", "casks"]
+        .iter()
+        .filter_map(|key| value.get(key)?.as_array()?.first())
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("brew info returned no packages"))?;
+
+    let text = |key: &str| {
+        entry
+            .get(key)
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    Ok(PackageInfo {
+        homepage: text("homepage"),
+        description: text("desc"),
+    })
+}
+
+/// Tokens of the casks in `brew info --json=v2` output with `auto_updates: true`.
+pub fn parse_auto_updating_casks(json: &str) -> Result<Vec<String>> {
+    let value: serde_json::Value = serde_json::from_str(json)?;
+    let casks = value
+        .get("casks")
+        .and_then(|casks| casks.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    Ok(casks
+        .iter()
+        .filter(|cask| cask.get("auto_updates").and_then(|v| v.as_bool()) == Some(true))
+        .filter_map(|cask| cask.get("token")?.as_str().map(str::to_string))
+        .collect())
+}
+
+/// Askpass helper for `--no-sudo`: exits non-zero without printing a password.
+const FAILING_ASKPASS: &str = "/usr/bin/false";
+
+/// What sudo prints when it can't get a password without a terminal.
+const SUDO_FAILURE_PATTERNS: [&str; 5] = [
+    "sudo: a terminal is required",
+    "sudo: a password is required",
+    "sudo: no tty present",
+    "sudo: no askpass program",
+    "incorrect password attempt",
+];
+
+/// Whether a failed upgrade's output shows it stopped at a sudo prompt.
+pub fn needs_sudo(output: &str) -> bool {
+    let output = output.to_lowercase();
+    SUDO_FAILURE_PATTERNS
+        .iter()
+        .any(|pattern| output.contains(pattern))
+}
+
+/// Build the error for a failed upgrade, explaining sudo failures in plain words.
+fn upgrade_error(package: &OutdatedPackage, detail: &str) -> anyhow::Error {
+    if needs_sudo(detail) {
+        let flag = match package.package_type {
+            PackageType::Formula => "",
+            PackageType::Cask => " --cask",
+        };
+        return anyhow::anyhow!(
+            "Failed to upgrade {}: it needs an administrator password (sudo). Run 'brew upgrade{} {}' yourself in a terminal",
+            package.name,
+            flag,
+            package.name
+        );
+    }
+    anyhow::anyhow!("Failed to upgrade {}: {}", package.name, detail)
+}
+
+/// Phrases brew caveats use when something needs doing by hand after an upgrade.
+const MANUAL_STEP_PATTERNS: [&str; 8] = [
+    "manually",
+    "you must",
+    "you need to",
+    "you will need to",
+    "restart your",
+    "reboot",
+    "log out",
+    "system settings",
+];
+
+/// Pick out the lines of upgrade output that ask the user to do something by hand.
+pub fn extract_manual_steps(output: &str) -> Vec<String> {
+    let mut steps: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim();
+        let lower = line.to_lowercase();
+        if MANUAL_STEP_PATTERNS
+            .iter()
+            .any(|pattern| lower.contains(pattern))
+            && !steps.iter().any(|step| step == line)
+        {
+            steps.push(line.to_string());
         }
     }
+    steps
+}
 
-    None
+/// Names of services reported as `started` by `brew services list`.
+pub fn parse_services_list(text: &str) -> Vec<String> {
+    // Format: "Name Status User File", one service per line after the header
+    text.lines()
+        .skip_while(|line| line.trim().is_empty())
+        .filter(|line| !line.starts_with("Name"))
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let name = columns.next()?;
+            let status = columns.next()?;
+            (status == "started").then(|| name.to_string())
+        })
+        .collect()
 }
 
 pub struct MockBrewExecutor {
     formulae: Vec<String>,
     casks: Vec<String>,
     outdated_packages: Vec<OutdatedPackage>,
+    running_services: Vec<String>,
+    pinned_formulae: Vec<String>,
+    deps: HashMap<String, Vec<String>>,
+    package_info: HashMap<String, PackageInfo>,
+    upgrade_output: HashMap<String, String>,
+    package_sizes: HashMap<String, u64>,
+    installed_versions: HashMap<String, String>,
+    descriptions: HashMap<String, String>,
+    failing_packages: Vec<String>,
+    /// Remaining failures before each flaky package's upgrade succeeds
+    flaky_packages: Mutex<HashMap<String, usize>>,
+    /// Packages passed to `fetch_package`, in call order
+    fetched: Mutex<Vec<String>>,
+    auto_updating_casks: Vec<String>,
     should_fail_verification: bool,
+    /// `get_version` and `get_system_info` fail, as without brew or sw_vers
+    missing_system_tools: bool,
+    busy: bool,
+    /// `get_auto_updating_casks` fails, as when `brew info` can't be parsed
+    failing_cask_info: bool,
 }
 
 impl Default for MockBrewExecutor {
@@ -281,7 +1078,81 @@ impl MockBrewExecutor {
                     package_type: PackageType::Cask,
                 },
             ],
+            running_services: vec!["postgresql@14".to_string()],
+            pinned_formulae: Vec::new(),
+            deps: HashMap::from([(
+                "git".to_string(),
+                vec!["gettext".to_string(), "pcre2".to_string()],
+            )]),
+            package_info: HashMap::from([
+                (
+                    "git".to_string(),
+                    PackageInfo {
+                        homepage: Some("https://git-scm.com".to_string()),
+                        description: Some("Distributed revision control system".to_string()),
+                    },
+                ),
+                (
+                    "docker".to_string(),
+                    PackageInfo {
+                        homepage: Some(
+                            "https://www.docker.com/products/docker-desktop".to_string(),
+                        ),
+                        description: Some(
+                            "App to build and share containerised applications and microservices"
+                                .to_string(),
+                        ),
+                    },
+                ),
+            ]),
+            upgrade_output: HashMap::new(),
+            package_sizes: HashMap::from([
+                ("git".to_string(), 12_897_485),
+                ("docker".to_string(), 1_610_612_736),
+            ]),
+            installed_versions: HashMap::from([
+                ("git".to_string(), "2.40.0".to_string()),
+                ("node".to_string(), "20.5.0".to_string()),
+                ("python".to_string(), "3.11.5".to_string()),
+                ("visual-studio-code".to_string(), "1.81.0".to_string()),
+                ("docker".to_string(), "4.18.0".to_string()),
+                ("firefox".to_string(), "116.0".to_string()),
+            ]),
+            descriptions: HashMap::from([
+                (
+                    "git".to_string(),
+                    "Distributed revision control system".to_string(),
+                ),
+                (
+                    "node".to_string(),
+                    "Platform built on V8 to build network applications".to_string(),
+                ),
+                (
+                    "python".to_string(),
+                    "Interpreted, interactive, object-oriented programming language".to_string(),
+                ),
+                (
+                    "visual-studio-code".to_string(),
+                    "(Microsoft Visual Studio Code) Open-source code editor".to_string(),
+                ),
+                (
+                    "docker".to_string(),
+                    "(Docker Desktop) App to build and share containerised applications"
+                        .to_string(),
+                ),
+                (
+                    "firefox".to_string(),
+                    "(Mozilla Firefox) Web browser".to_string(),
+                ),
+            ]),
+            failing_packages: Vec::new(),
+            flaky_packages: Mutex::new(HashMap::new()),
+            fetched: Mutex::new(Vec::new()),
+            auto_updating_casks: vec!["docker".to_string()],
             should_fail_verification: false,
+            missing_system_tools: false,
+            busy: false,
+            failing_cask_info: false,
         }
     }
 
@@ -290,6 +1161,24 @@ impl MockBrewExecutor {
         self
     }
 
+    /// Make the version and system-info lookups fail.
+    pub fn with_missing_system_tools(mut self) -> Self {
+        self.missing_system_tools = true;
+        self
+    }
+
+    /// Pretend another brew process is running.
+    pub fn with_busy_brew(mut self) -> Self {
+        self.busy = true;
+        self
+    }
+
+    /// Make the self-updating cask lookup fail.
+    pub fn with_failing_cask_info(mut self) -> Self {
+        self.failing_cask_info = true;
+        self
+    }
+
     pub fn with_formulae(mut self, formulae: Vec<String>) -> Self {
         self.formulae = formulae;
         self
@@ -300,6 +1189,74 @@ impl MockBrewExecutor {
         self
     }
 
+    pub fn with_running_services(mut self, services: Vec<String>) -> Self {
+        self.running_services = services;
+        self
+    }
+
+    pub fn with_pinned_formulae(mut self, pinned: Vec<String>) -> Self {
+        self.pinned_formulae = pinned;
+        self
+    }
+
+    /// Make `upgrade_package` fail the first `failures` attempts for `name`, then succeed.
+    pub fn with_flaky_package(self, name: &str, failures: usize) -> Self {
+        self.flaky_packages
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), failures);
+        self
+    }
+
+    /// Names passed to `fetch_package` so far.
+    pub fn fetched_packages(&self) -> Vec<String> {
+        self.fetched.lock().unwrap().clone()
+    }
+
+    pub fn with_auto_updating_casks(mut self, casks: Vec<String>) -> Self {
+        self.auto_updating_casks = casks;
+        self
+    }
+
+    /// Make `upgrade_package` fail for these names.
+    pub fn with_failing_packages(mut self, packages: Vec<String>) -> Self {
+        self.failing_packages = packages;
+        self
+    }
+
+    pub fn with_installed_version(mut self, name: &str, version: &str) -> Self {
+        self.installed_versions
+            .insert(name.to_string(), version.to_string());
+        self
+    }
+
+    pub fn with_deps(mut self, name: &str, deps: Vec<String>) -> Self {
+        self.deps.insert(name.to_string(), deps);
+        self
+    }
+
+    pub fn with_package_info(mut self, name: &str, info: PackageInfo) -> Self {
+        self.package_info.insert(name.to_string(), info);
+        self
+    }
+
+    pub fn with_description(mut self, name: &str, description: &str) -> Self {
+        self.descriptions
+            .insert(name.to_string(), description.to_string());
+        self
+    }
+
+    pub fn with_package_size(mut self, name: &str, size: u64) -> Self {
+        self.package_sizes.insert(name.to_string(), size);
+        self
+    }
+
+    pub fn with_upgrade_output(mut self, name: &str, output: &str) -> Self {
+        self.upgrade_output
+            .insert(name.to_string(), output.to_string());
+        self
+    }
+
     #[allow(dead_code)]
     pub fn with_outdated_packages(mut self, packages: Vec<OutdatedPackage>) -> Self {
         self.outdated_packages = packages;
@@ -327,27 +1284,227 @@ impl BrewExecutor for MockBrewExecutor {
         Ok(self.outdated_packages.clone())
     }
 
-    fn upgrade_package(&self, _package: &OutdatedPackage) -> Result<()> {
-        Ok(())
+    fn get_outdated_packages_for(
+        &self,
+        formulae: &[String],
+        casks: &[String],
+    ) -> Result<Vec<OutdatedPackage>> {
+        Ok(self
+            .outdated_packages
+            .iter()
+            .filter(|pkg| match pkg.package_type {
+                PackageType::Formula => formulae.contains(&pkg.name),
+                PackageType::Cask => casks.contains(&pkg.name),
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn upgrade_package(
+        &self,
+        package: &OutdatedPackage,
+        _extra_args: &[String],
+        _stream: bool,
+    ) -> Result<String> {
+        if self.failing_packages.contains(&package.name) {
+            anyhow::bail!("Error: mock upgrade of {} failed", package.name);
+        }
+        if let Some(remaining) = self.flaky_packages.lock().unwrap().get_mut(&package.name) {
+            if *remaining > 0 {
+                *remaining -= 1;
+                anyhow::bail!("Error: mock download of {} was interrupted", package.name);
+            }
+        }
+
+        Ok(self
+            .upgrade_output
+            .get(&package.name)
+            .cloned()
+            .unwrap_or_default())
     }
 
     fn get_version(&self) -> Result<String> {
+        if self.missing_system_tools {
+            anyhow::bail!("Failed to get Homebrew version: brew: command not found");
+        }
         Ok("Homebrew 4.1.5".to_string())
     }
 
     fn get_system_info(&self) -> Result<crate::stats::SystemInfo> {
+        if self.missing_system_tools {
+            anyhow::bail!("sw_vers: command not found");
+        }
         Ok(crate::stats::SystemInfo {
             os_version: "macOS 14.5".to_string(),
             architecture: "Apple Silicon".to_string(),
             homebrew_prefix: "/usr/local".to_string(),
         })
     }
+
+    fn get_running_services(&self) -> Result<Vec<String>> {
+        Ok(self.running_services.clone())
+    }
+
+    fn get_pinned_formulae(&self) -> Result<Vec<String>> {
+        Ok(self.pinned_formulae.clone())
+    }
+
+    fn get_auto_updating_casks(&self) -> Result<Vec<String>> {
+        if self.failing_cask_info {
+            anyhow::bail!("Failed to get cask info: mock failure");
+        }
+        Ok(self.auto_updating_casks.clone())
+    }
+
+    fn get_installed_versions(&self) -> Result<Vec<(String, String, PackageType)>> {
+        let mut versions: Vec<_> = self
+            .installed_versions
+            .iter()
+            .map(|(name, version)| {
+                let package_type = if self.casks.contains(name) {
+                    PackageType::Cask
+                } else {
+                    PackageType::Formula
+                };
+                (name.clone(), version.clone(), package_type)
+            })
+            .collect();
+        versions.sort_by(|a, b| (&a.2, &a.0).cmp(&(&b.2, &b.0)));
+        Ok(versions)
+    }
+
+    fn get_deps(&self, name: &str) -> Result<Vec<String>> {
+        Ok(self.deps.get(name).cloned().unwrap_or_default())
+    }
+
+    fn get_installed_dep_counts(&self, names: &[String]) -> Result<HashMap<String, usize>> {
+        Ok(names
+            .iter()
+            .map(|name| (name.clone(), self.deps.get(name).map_or(0, Vec::len)))
+            .collect())
+    }
+
+    fn get_package_info(&self, name: &str, _package_type: &PackageType) -> Result<PackageInfo> {
+        Ok(self.package_info.get(name).cloned().unwrap_or_default())
+    }
+
+    fn get_package_size(&self, pkg: &OutdatedPackage) -> Result<Option<u64>> {
+        Ok(self.package_sizes.get(&pkg.name).copied())
+    }
+
+    fn get_description(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.descriptions.get(name).cloned())
+    }
+
+    fn cleanup(&self) -> Result<String> {
+        Ok(
+            "Removing: /opt/homebrew/Cellar/git/2.40.0... (1,633 files, 48.2MB)\n\
+==> This operation has freed approximately 48.2MB of disk space.\n"
+                .to_string(),
+        )
+    }
+
+    fn is_brew_busy(&self) -> Result<bool> {
+        Ok(self.busy)
+    }
+
+    fn fetch_package(&self, package: &OutdatedPackage) -> Result<()> {
+        self.fetched.lock().unwrap().push(package.name.clone());
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_system_brew_executor_default_program() {
+        let executor = SystemBrewExecutor::default();
+        assert_eq!(executor.brew_command().get_program(), "brew");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_system_brew_executor_skips_casks_on_linux() -> Result<()> {
+        // A brew that doesn't exist proves no subprocess is spawned
+        let executor = SystemBrewExecutor::new(Some(PathBuf::from("/nonexistent/brew")));
+        assert!(executor.get_manually_installed_casks()?.is_empty());
+        assert!(executor.get_auto_updating_casks()?.is_empty());
+        assert!(executor
+            .get_outdated_packages_for(&[], &["firefox".to_string()])?
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_system_brew_executor_brew_path_override() {
+        let executor = SystemBrewExecutor::new(Some(PathBuf::from("/opt/custom/bin/brew")));
+        assert_eq!(
+            executor.brew_command().get_program(),
+            "/opt/custom/bin/brew"
+        );
+    }
+
+    #[test]
+    fn test_system_brew_executor_no_auto_update() {
+        let has_env = |executor: &SystemBrewExecutor| {
+            executor
+                .brew_command()
+                .get_envs()
+                .any(|(key, value)| key == "HOMEBREW_NO_AUTO_UPDATE" && value == Some("1".as_ref()))
+        };
+
+        assert!(!has_env(&SystemBrewExecutor::default()));
+        assert!(has_env(
+            &SystemBrewExecutor::default().with_no_auto_update(true)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_with_timeout_kills_slow_command() {
+        let started = Instant::now();
+        let result = output_with_timeout(
+            Command::new("sleep").arg("5"),
+            Some(Duration::from_millis(200)),
+        );
+
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_status_with_timeout() -> Result<()> {
+        assert!(
+            status_with_timeout(&mut Command::new("true"), Some(Duration::from_secs(5)))?.success()
+        );
+        assert!(!status_with_timeout(&mut Command::new("false"), None)?.success());
+        assert!(status_with_timeout(
+            Command::new("sleep").arg("5"),
+            Some(Duration::from_millis(200))
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_with_timeout_collects_output() -> Result<()> {
+        let output = output_with_timeout(
+            Command::new("sh").args(["-c", "echo out; echo err >&2"]),
+            Some(Duration::from_secs(5)),
+        )?;
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+
+        Ok(())
+    }
+
     #[test]
     fn test_parse_outdated_line_formula() {
         let line = "git (2.40.0) < 2.41.0";
@@ -374,6 +1531,251 @@ mod tests {
         assert!(matches!(package.package_type, PackageType::Cask));
     }
 
+    #[test]
+    fn test_parse_outdated_line_cask_latest() {
+        let line = "google-chrome (1.0) != latest";
+        let package = parse_outdated_line(line, PackageType::Cask).unwrap();
+        assert_eq!(package.name, "google-chrome");
+        assert_eq!(package.current_version, "1.0");
+        assert_eq!(package.available_version, "latest");
+    }
+
+    #[test]
+    fn test_parse_package_list_with_invalid_utf8() {
+        let stdout = b"git\nbad-\xff\xfe-name\n\n  node  \n";
+        let packages = parse_package_list(stdout);
+        assert_eq!(packages.len(), 3);
+        assert_eq!(packages[0], "git");
+        assert!(packages[1].starts_with("bad-"));
+        assert_eq!(packages[2], "node");
+    }
+
+    #[test]
+    fn test_parse_versions_list_keeps_latest() {
+        let versions = parse_versions_list(b"git 2.40.0\npython@3.11 3.11.4 3.11.5\n\nbare\n");
+        assert_eq!(versions.get("git").map(String::as_str), Some("2.40.0"));
+        assert_eq!(
+            versions.get("python@3.11").map(String::as_str),
+            Some("3.11.5")
+        );
+        assert!(!versions.contains_key("bare"));
+    }
+
+    #[test]
+    fn test_outdated_package_json_round_trip() -> Result<()> {
+        assert_eq!(PackageType::Formula, PackageType::Formula);
+        assert_ne!(PackageType::Formula, PackageType::Cask);
+
+        let pkg = OutdatedPackage {
+            name: "docker".to_string(),
+            current_version: "4.18.0".to_string(),
+            available_version: "4.19.0".to_string(),
+            package_type: PackageType::Cask,
+        };
+        let json = serde_json::to_string(&pkg)?;
+        assert!(json.contains(r#""package_type":"cask""#));
+        assert_eq!(serde_json::from_str::<OutdatedPackage>(&json)?, pkg);
+        Ok(())
+    }
+
+    #[test]
+    fn test_any_lock_held() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let locks = temp_dir.path().join("locks");
+        assert!(!any_lock_held(&locks)?);
+
+        std::fs::create_dir(&locks)?;
+        let lock_path = locks.join("update.lock");
+        std::fs::write(&lock_path, "")?;
+        std::fs::write(locks.join("notes.txt"), "")?;
+        // A leftover lock file nobody holds is fine
+        assert!(!any_lock_held(&locks)?);
+
+        let held = std::fs::File::open(&lock_path)?;
+        held.lock()?;
+        assert!(any_lock_held(&locks)?);
+        held.unlock()?;
+        assert!(!any_lock_held(&locks)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_name_strips_tap() {
+        assert_eq!(short_name("homebrew/core/git"), "git");
+        assert_eq!(short_name("someone/tap/tool"), "tool");
+        assert_eq!(short_name("git"), "git");
+        assert!(same_package("homebrew/core/git", "git"));
+        assert!(!same_package("homebrew/core/git", "git-lfs"));
+        assert!(same_package("homebrew/core/git", "homebrew/core/git"));
+        assert!(!same_package("homebrew/core/tool", "someone/tap/tool"));
+        assert!(lists_package(&["git".to_string()], "homebrew/core/git"));
+    }
+
+    #[test]
+    fn test_upgrade_args() {
+        let docker = OutdatedPackage {
+            name: "docker".to_string(),
+            current_version: "4.18.0".to_string(),
+            available_version: "4.19.0".to_string(),
+            package_type: PackageType::Cask,
+        };
+        assert_eq!(
+            upgrade_args(&docker, &["--no-quarantine".to_string()]),
+            vec!["upgrade", "--cask", "--no-quarantine", "docker"]
+        );
+
+        let git = OutdatedPackage {
+            package_type: PackageType::Formula,
+            name: "git".to_string(),
+            ..docker
+        };
+        assert_eq!(upgrade_args(&git, &[]), vec!["upgrade", "git"]);
+    }
+
+    #[test]
+    fn test_parse_description() {
+        let stdout = b"git: Distributed revision control system\ngit-lfs: Git extension for versioning large files\n";
+        assert_eq!(
+            parse_description(stdout, "git").as_deref(),
+            Some("Distributed revision control system")
+        );
+        assert_eq!(
+            parse_description(b"firefox: (Mozilla Firefox) Web browser\n", "firefox").as_deref(),
+            Some("(Mozilla Firefox) Web browser")
+        );
+        assert_eq!(parse_description(b"vim: \n", "vim"), None);
+        assert_eq!(parse_description(b"", "vim"), None);
+    }
+
+    #[test]
+    fn test_parse_versions_list_with_invalid_utf8() {
+        let versions = parse_versions_list(b"git 2.40.0\nbad-\xff 1.0\nnode 20.5.0\xfe\n");
+        assert_eq!(versions.len(), 3);
+        assert_eq!(versions["git"], "2.40.0");
+        assert!(versions["node"].starts_with("20.5.0"));
+    }
+
+    #[test]
+    fn test_parse_outdated_output_with_invalid_utf8() {
+        let stdout = b"git (2.40.0) < 2.41.0\nwe\xffird (1.0) < 1.1\n";
+        let packages = parse_outdated_output(stdout, PackageType::Formula);
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "git");
+        assert_eq!(packages[1].available_version, "1.1");
+    }
+
+    #[test]
+    fn test_parse_outdated_line_without_parentheses() {
+        let package = parse_outdated_line("git 2.40.0 < 2.41.0", PackageType::Formula).unwrap();
+        assert_eq!(package.name, "git");
+        assert_eq!(package.current_version, "2.40.0");
+        assert_eq!(package.available_version, "2.41.0");
+
+        let package = parse_outdated_line("firefox 115.0 != 116.0", PackageType::Cask).unwrap();
+        assert_eq!(package.name, "firefox");
+        assert_eq!(package.current_version, "115.0");
+        assert_eq!(package.available_version, "116.0");
+    }
+
+    #[test]
+    fn test_parse_outdated_line_pinned() {
+        let package = parse_outdated_line(
+            "postgresql@14 (14.8) < 14.9 [pinned at 14.8]",
+            PackageType::Formula,
+        )
+        .unwrap();
+        assert_eq!(package.name, "postgresql@14");
+        assert_eq!(package.current_version, "14.8");
+        assert_eq!(package.available_version, "14.9");
+    }
+
+    #[test]
+    fn test_parse_outdated_line_multiple_installed_versions() {
+        let package =
+            parse_outdated_line("node (18.16.0, 18.17.0) < 20.5.0", PackageType::Formula).unwrap();
+        assert_eq!(package.current_version, "18.16.0, 18.17.0");
+        assert_eq!(package.available_version, "20.5.0");
+    }
+
+    #[test]
+    fn test_outdated_cask_args() {
+        assert_eq!(
+            outdated_cask_args(true),
+            vec!["outdated", "--cask", "--greedy", "--verbose"]
+        );
+        assert_eq!(
+            outdated_cask_args(false),
+            vec!["outdated", "--cask", "--verbose"]
+        );
+    }
+
+    #[test]
+    fn test_system_info_without_brew_or_tools() -> Result<()> {
+        assert_eq!(
+            command_stdout(&mut Command::new("buh-no-such-binary")),
+            None
+        );
+        assert_eq!(command_stdout(&mut Command::new("false")), None);
+
+        let executor = SystemBrewExecutor::new(Some(PathBuf::from("/nonexistent/brew")));
+        let info = executor.get_system_info()?;
+        assert_eq!(info.homebrew_prefix, "Unknown");
+        assert!(!info.os_version.is_empty());
+        assert!(!info.architecture.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_deps_for_each() {
+        let counts = parse_deps_for_each("git: gettext pcre2\nnode:\nwget: libidn2 openssl@3\n");
+        assert_eq!(counts["git"], 2);
+        assert_eq!(counts["node"], 0);
+        assert_eq!(counts["wget"], 2);
+        assert_eq!(counts.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_scoped_outdated_output() -> Result<()> {
+        let stdout = b"git (2.40.0) < 2.41.0\n";
+
+        // brew exits 1 because a named package is outdated
+        let packages = parse_scoped_outdated_output(Some(1), stdout, b"", PackageType::Formula)?;
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name, "git");
+        assert!(parse_scoped_outdated_output(Some(0), b"", b"", PackageType::Formula)?.is_empty());
+
+        let error = parse_scoped_outdated_output(
+            Some(1),
+            b"",
+            b"Error: No such keg: /opt/homebrew/Cellar/nope\n",
+            PackageType::Formula,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("No such keg"));
+        assert!(parse_scoped_outdated_output(Some(2), stdout, b"", PackageType::Formula).is_err());
+        assert!(parse_scoped_outdated_output(None, stdout, b"", PackageType::Formula).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_outdated_line_skips_equal_versions() {
+        assert!(parse_outdated_line("foo (1.2.3) != 1.2.3", PackageType::Cask).is_none());
+        assert!(parse_outdated_line("foo ( 1.2.3 ) != 1.2.3", PackageType::Cask).is_none());
+
+        let packages = parse_outdated_output(
+            b"foo (1.2.3) != 1.2.3\nbar (1.0) != 1.1\n",
+            PackageType::Cask,
+        );
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name, "bar");
+    }
+
+    #[test]
+    fn test_parse_outdated_line_rejects_names_with_spaces() {
+        assert!(parse_outdated_line("my package (1.0) < 2.0", PackageType::Formula).is_none());
+        assert!(parse_outdated_line("my package 1.0 < 2.0", PackageType::Formula).is_none());
+    }
+
     #[test]
     fn test_parse_outdated_line_invalid() {
         let line = "invalid line format";
@@ -381,6 +1783,135 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_parse_package_info_formula() -> Result<()> {
+        let json = r#"{"formulae":[{"name":"git","desc":"Distributed revision control system","homepage":"https://git-scm.com"}],"casks":[]}"#;
+        let info = parse_package_info(json)?;
+        assert_eq!(info.homepage.as_deref(), Some("https://git-scm.com"));
+        assert_eq!(
+            info.description.as_deref(),
+            Some("Distributed revision control system")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_package_info_cask() -> Result<()> {
+        let json = r#"{"formulae":[],"casks":[{"token":"firefox","desc":null,"homepage":"https://www.mozilla.org/firefox/"}]}"#;
+        let info = parse_package_info(json)?;
+        assert_eq!(
+            info.homepage.as_deref(),
+            Some("https://www.mozilla.org/firefox/")
+        );
+        assert_eq!(info.description, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_needs_sudo() {
+        assert!(needs_sudo(
+            "==> Running uninstall process for zoom\nsudo: a terminal is required to read the password"
+        ));
+        assert!(needs_sudo(
+            "sudo: no askpass program specified, try setting SUDO_ASKPASS"
+        ));
+        assert!(!needs_sudo("Error: Download failed on Cask 'docker'"));
+    }
+
+    #[test]
+    fn test_upgrade_error_explains_sudo() {
+        let cask = OutdatedPackage {
+            name: "zoom".to_string(),
+            current_version: "5.0".to_string(),
+            available_version: "5.1".to_string(),
+            package_type: PackageType::Cask,
+        };
+        let error = upgrade_error(&cask, "sudo: a password is required").to_string();
+        assert!(error.contains("needs an administrator password"));
+        assert!(error.contains("'brew upgrade --cask zoom'"));
+
+        let error = upgrade_error(&cask, "Error: Download failed").to_string();
+        assert_eq!(error, "Failed to upgrade zoom: Error: Download failed");
+    }
+
+    #[test]
+    fn test_parse_auto_updating_casks() -> Result<()> {
+        let json = r#"{"formulae":[],"casks":[{"token":"docker","auto_updates":true},{"token":"firefox","auto_updates":null},{"token":"zoom","auto_updates":false}]}"#;
+        assert_eq!(parse_auto_updating_casks(json)?, vec!["docker"]);
+        assert!(parse_auto_updating_casks(r#"{"formulae":[]}"#)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_services_list() {
+        let text = "Name          Status  User File
+postgresql@14 started anas ~/Library/LaunchAgents/homebrew.mxcl.postgresql@14.plist
+redis         none
+mysql         error   256  root ~/Library/LaunchAgents/homebrew.mxcl.mysql.plist
+nginx         started root /Library/LaunchDaemons/homebrew.mxcl.nginx.plist
+";
+        assert_eq!(
+            parse_services_list(text),
+            vec!["postgresql@14".to_string(), "nginx".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_services_list_empty() {
+        assert!(parse_services_list("").is_empty());
+        assert!(parse_services_list("Name Status User File\n").is_empty());
+    }
+
+    #[test]
+    fn test_extract_manual_steps_from_cask_caveats() {
+        let output = "\
+==> Upgrading 1 outdated package:
+virtualbox 7.0.8 -> 7.0.10
+==> Caveats
+You must approve the kernel extension in System Settings → Privacy & Security.
+You will need to restart your computer for this update to take effect.
+==> Purging files for version 7.0.8 of Cask virtualbox
+🍺  virtualbox was successfully upgraded!
+";
+
+        assert_eq!(
+            extract_manual_steps(output),
+            vec![
+                "You must approve the kernel extension in System Settings → Privacy & Security."
+                    .to_string(),
+                "You will need to restart your computer for this update to take effect."
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_manual_steps_move_app_notice() {
+        let output = "\
+==> Caveats
+  To finish the upgrade you must manually move the app to /Applications.
+  To finish the upgrade you must manually move the app to /Applications.
+";
+
+        assert_eq!(
+            extract_manual_steps(output),
+            vec![
+                "To finish the upgrade you must manually move the app to /Applications."
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_manual_steps_none() {
+        let output = "\
+==> Upgrading git
+==> Pouring git--2.41.0.arm64_sonoma.bottle.tar.gz
+🍺  /opt/homebrew/Cellar/git/2.41.0: 1,636 files, 49.4MB
+";
+        assert!(extract_manual_steps(output).is_empty());
+    }
+
     #[test]
     fn test_mock_brew_executor() -> Result<()> {
         let executor = MockBrewExecutor::new();
@@ -405,6 +1936,35 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_mock_get_outdated_packages_for_only_returns_named() -> Result<()> {
+        let executor = MockBrewExecutor::new();
+
+        let outdated = executor.get_outdated_packages_for(&["git".to_string()], &[])?;
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].name, "git");
+
+        // A cask name in the formula list doesn't match
+        let outdated = executor.get_outdated_packages_for(&["docker".to_string()], &[])?;
+        assert!(outdated.is_empty());
+
+        assert!(executor.get_outdated_packages_for(&[], &[])?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dir_size_sums_nested_files() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        std::fs::write(temp_dir.path().join("a"), [0u8; 100])?;
+        std::fs::create_dir(temp_dir.path().join("bin"))?;
+        std::fs::write(temp_dir.path().join("bin").join("b"), [0u8; 23])?;
+
+        assert_eq!(dir_size(temp_dir.path())?, 123);
+
+        Ok(())
+    }
+
     #[test]
     fn test_mock_brew_executor_with_failed_verification() {
         let executor = MockBrewExecutor::new().with_failed_verification();
@@ -1,4 +1,15 @@
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use std::time::Duration;
+
+use crate::brew::PackageType;
+use crate::config::{ConfigFormat, ExportFormat};
+use crate::order::{SelectionSort, UpgradeOrder};
+use crate::stats::StatsFormat;
+use crate::ui::SelectionMode;
+use crate::utils::{parse_since, LogFormat, LogLevel, DEFAULT_PROFILE};
+use crate::version::MinBump;
+use crate::warnings::WarningId;
 
 #[derive(Parser)]
 #[command(name = "brew-update-helper")]
@@ -9,18 +20,336 @@ pub struct Cli {
     pub command: Commands,
 
     /// Show what would be done without executing
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub dry_run: bool,
 
     /// Specify custom config file path
     #[arg(long)]
     pub config: Option<String>,
+
+    /// Settings file format; picks the default file name, and must match --config's extension
+    #[arg(long, global = true, value_enum)]
+    pub config_format: Option<ConfigFormat>,
+
+    /// Directory for settings, logs and other state (--config still overrides the settings file)
+    #[arg(long, global = true, value_name = "DIR")]
+    pub config_dir: Option<String>,
+
+    /// Use a separate settings file and log, e.g. for work and personal machines
+    #[arg(long, global = true, value_name = "NAME", default_value = "default")]
+    pub profile: String,
+
+    /// Least severe entries written to the upgrade log
+    #[arg(long, global = true, value_enum, default_value_t = LogLevel::Info)]
+    pub log_level: LogLevel,
+
+    /// How upgrade log entries are written
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Path to the brew executable, for installs that aren't on PATH
+    #[arg(long, global = true, env = "BUH_BREW_PATH", value_name = "PATH")]
+    pub brew_path: Option<String>,
+
+    /// Skip brew's implicit `brew update` (sets HOMEBREW_NO_AUTO_UPDATE=1): much faster,
+    /// but outdated checks only see versions from the last update
+    #[arg(long, global = true)]
+    pub no_update: bool,
+
+    /// Leave casks that update themselves out of outdated checks (drops brew's `--greedy`);
+    /// overrides the settings file's `Greedy casks:` line
+    #[arg(long, global = true)]
+    pub no_greedy: bool,
+
+    /// Silence a warning by id (repeatable)
+    #[arg(long, global = true, value_name = "WARNING_ID")]
+    pub suppress: Vec<WarningId>,
+
+    /// Only print warnings, errors and results (no progress chatter)
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Print JSON on stdout: dump's selections (instead of writing the settings file), upgrade's per-package results or stats
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+impl Cli {
+    /// `command` with every global flag at its default, for running a command
+    /// from code (the self-test and unit tests) rather than the command line.
+    pub fn with_command(command: Commands) -> Self {
+        Self {
+            command,
+            dry_run: false,
+            config: None,
+            config_format: None,
+            config_dir: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            log_level: LogLevel::Info,
+            log_format: LogFormat::Text,
+            brew_path: None,
+            no_update: false,
+            no_greedy: false,
+            suppress: Vec::new(),
+            quiet: false,
+            json: false,
+        }
+    }
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Generate/update package selection settings
-    Dump,
+    Dump(DumpArgs),
     /// Upgrade selected packages interactively
-    Upgrade,
+    Upgrade(UpgradeArgs),
+    /// Show package statistics without regenerating settings
+    Stats(StatsArgs),
+    /// Print the packages in the settings file and whether each is enabled
+    List(ListArgs),
+    /// Check the settings file for duplicate, misplaced or uninstalled entries
+    Validate,
+    /// Remove settings entries for packages that are no longer installed
+    Clean,
+    /// Show which packages were added or removed since the last dump
+    Diff,
+    /// Show packages whose installed version changed since it was recorded by `dump --record-versions`
+    Drift,
+    /// Print the brew commands that revert the most recent upgrade session
+    Rollback,
+    /// Summarise past upgrade sessions from the upgrade log
+    History(HistoryArgs),
+    /// Export the enabled packages in another format
+    Export(ExportArgs),
+    /// Write every installed package and its exact version to a JSON file
+    Snapshot(SnapshotArgs),
+    /// Check for common setup problems
+    Doctor,
+    /// Print where the settings file and upgrade log live
+    Paths,
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Run a smoke test against the mock executor (never touches brew or your config)
+    Selftest,
+}
+
+#[derive(Args, Default)]
+pub struct DumpArgs {
+    /// Also write the settings as JSON to this path
+    #[arg(long, value_name = "PATH")]
+    pub also_json: Option<String>,
+
+    /// Record each package's installed version (`- [x] git @2.40.0`) for `drift`
+    #[arg(long)]
+    pub record_versions: bool,
+
+    /// Add newly found packages unchecked, so nothing is upgraded until you opt in
+    #[arg(long)]
+    pub default_disabled: bool,
+
+    /// Copy the current settings file to <file>.bak before overwriting it
+    #[arg(long)]
+    pub backup: bool,
+
+    /// Open the settings file in $EDITOR (or vi) once it's written
+    #[arg(long)]
+    pub edit: bool,
+}
+
+#[derive(Args, Default)]
+pub struct StatsArgs {
+    /// How to print the statistics (--json implies json)
+    #[arg(long, value_enum, default_value_t = StatsFormat::Markdown)]
+    pub output_format: StatsFormat,
+}
+
+#[derive(Args, Default)]
+pub struct ListArgs {
+    /// Only show packages enabled for upgrades
+    #[arg(long, conflicts_with = "disabled_only")]
+    pub enabled_only: bool,
+
+    /// Only show packages disabled for upgrades
+    #[arg(long)]
+    pub disabled_only: bool,
+}
+
+#[derive(Args, Default)]
+pub struct HistoryArgs {
+    /// Only show the N most recent sessions
+    #[arg(long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// Only show sessions since a duration ago (7d, 24h) or a date (2024-01-01)
+    #[arg(long, value_name = "WHEN", value_parser = parse_since)]
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ExportFormat::Brewfile)]
+    pub format: ExportFormat,
+
+    /// Write to this path instead of stdout
+    #[arg(long, value_name = "PATH")]
+    pub output: Option<String>,
+}
+
+#[derive(Args, Default)]
+pub struct SnapshotArgs {
+    /// Write to this path instead of ./brew-snapshot-<timestamp>.json
+    #[arg(long, value_name = "PATH")]
+    pub output: Option<String>,
+}
+
+#[derive(Args, Default)]
+pub struct UpgradeArgs {
+    /// Explain why each package will or won't be upgraded
+    #[arg(long)]
+    pub explain: bool,
+
+    /// Print a one-line JSON summary as the very last line of output
+    #[arg(long)]
+    pub final_json: bool,
+
+    /// Skip formulae whose `brew services` entry is currently started
+    #[arg(long)]
+    pub skip_running_services: bool,
+
+    /// Skip casks that update themselves (`auto_updates true`)
+    #[arg(long)]
+    pub skip_auto_updates: bool,
+
+    /// Selection UI: the full-screen list, the plain-text prompt, or auto-detect
+    #[arg(long, value_enum, default_value_t = SelectionMode::Auto)]
+    pub interactive: SelectionMode,
+
+    /// How the selection list is ordered
+    #[arg(long, value_enum, default_value_t = SelectionSort::Name)]
+    pub sort: SelectionSort,
+
+    /// Order in which the selected packages are upgraded
+    #[arg(long, value_enum, default_value_t = UpgradeOrder::Listed)]
+    pub upgrade_order: UpgradeOrder,
+
+    /// With --dry-run, list the outdated dependencies each formula would pull along
+    #[arg(long)]
+    pub show_deps: bool,
+
+    /// Print each selected package's homepage before upgrading
+    #[arg(long)]
+    pub show_homepage: bool,
+
+    /// Open the homepages with the system opener (implies --show-homepage)
+    #[arg(long)]
+    pub open: bool,
+
+    /// Upgrade in chunks of N packages with a summary after each chunk
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub batch_size: Option<u64>,
+
+    /// Offer entries whose available version is the same or older than installed
+    #[arg(long)]
+    pub allow_downgrade: bool,
+
+    /// Don't ask again before upgrades that cross a major version
+    #[arg(long)]
+    pub allow_major: bool,
+
+    /// Only offer upgrades with at least this big a version jump
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    pub min_bump: Option<MinBump>,
+
+    /// Only upgrade formulae, leaving casks alone
+    #[arg(long, conflicts_with = "cask_only")]
+    pub formula_only: bool,
+
+    /// Only upgrade casks, leaving formulae alone
+    #[arg(long)]
+    pub cask_only: bool,
+
+    /// Upgrade just these packages, ignoring the settings file (repeatable or comma-separated)
+    #[arg(long, value_name = "PKG", value_delimiter = ',')]
+    pub only: Vec<String>,
+
+    /// Download all selected packages concurrently before upgrading them one by one
+    #[arg(long)]
+    pub parallel_fetch: bool,
+
+    /// Show brew's live output while upgrading instead of capturing it
+    #[arg(long)]
+    pub verbose: bool,
+
+    /// Give up on a single package's upgrade after this many seconds (counted as a failure)
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: Option<u64>,
+
+    /// Fail upgrades that ask for a sudo password right away instead of hanging
+    #[arg(long)]
+    pub no_sudo: bool,
+
+    /// Retry a failed upgrade up to N (at most 5) more times, waiting longer before each attempt
+    #[arg(long, value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(u32).range(..=5))]
+    pub retries: u32,
+
+    /// Run `brew cleanup` after upgrading and report how much space it freed
+    #[arg(long)]
+    pub cleanup: bool,
+
+    /// Reuse `brew outdated` results younger than this many seconds (0 disables)
+    #[arg(long, value_name = "SECONDS", conflicts_with = "no_cache")]
+    pub cache_ttl: Option<u64>,
+
+    /// Always ask brew, ignoring any cached outdated results
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Skip these packages this run, on top of the settings (repeatable or comma-separated)
+    #[arg(long, value_name = "PKG", value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Only upgrade enabled packages that are still installed
+    #[arg(long)]
+    pub filter_installed: bool,
+
+    /// Warn when the settings file was generated more than this many days ago (0 disables)
+    #[arg(long, value_name = "DAYS", default_value_t = 30)]
+    pub settings_max_age: u32,
+
+    /// Fail when the settings file is missing instead of offering to create it
+    #[arg(long)]
+    pub no_wizard: bool,
+
+    /// Print a table of the outdated enabled packages and exit without upgrading
+    #[arg(long, conflicts_with = "yes")]
+    pub list_outdated: bool,
+
+    /// Upgrade every candidate without prompting (safe without a TTY)
+    #[arg(short = 'y', long)]
+    pub yes: bool,
+}
+
+impl UpgradeArgs {
+    /// How long cached `brew outdated` results stay usable, if caching is on.
+    pub fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl
+            .filter(|&ttl| ttl > 0 && !self.no_cache)
+            .map(Duration::from_secs)
+    }
+
+    /// The package type the upgrade is restricted to, if any.
+    pub fn only_type(&self) -> Option<PackageType> {
+        if self.formula_only {
+            Some(PackageType::Formula)
+        } else if self.cask_only {
+            Some(PackageType::Cask)
+        } else {
+            None
+        }
+    }
 }
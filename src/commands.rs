@@ -1,79 +1,421 @@
 use anyhow::Result;
-use std::fs;
+use chrono::{DateTime, Utc};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use crate::brew::{BrewExecutor, OutdatedPackage};
-use crate::cli::Cli;
+use crate::brew::{
+    extract_manual_steps, lists_package, same_package, short_name, BrewExecutor, OutdatedPackage,
+    PackageType,
+};
+use crate::cli::{
+    Cli, Commands, DumpArgs, ExportArgs, HistoryArgs, ListArgs, SnapshotArgs, StatsArgs,
+    UpgradeArgs,
+};
 use crate::config::{
-    generate_settings_content, get_config_path, read_existing_settings, read_previous_packages,
+    backup_settings, check_settings_content, check_settings_toml, find_settings_problems,
+    generate_brewfile, generate_settings_content, generate_settings_json, generate_settings_toml,
+    generate_snapshot_json, get_config_path, merge_settings_extras, parse_generated_timestamp,
+    parse_greedy_setting, prune_settings, read_existing_settings, read_package_settings,
+    read_previous_packages, read_recorded_versions, read_settings_entries, read_settings_extras,
+    set_package_enabled, settings_with_default, update_recorded_versions, ConfigFormat,
+    ExportFormat,
+};
+use crate::order::{order_by_dependencies, sort_for_selection, UpgradeOrder};
+use crate::output::{CapturingSink, OutputLevel, OutputSink, ProgressSink};
+use crate::stats::{diff_packages, PackageStats, StatsFormat};
+use crate::ui::{
+    confirm, format_size, is_test_environment, outdated_table, show_interactive_selection,
+    show_simple_selection, SelectionMode, SelectionOutcome,
+};
+use crate::utils::{
+    get_last_session, outdated_cache_path, parse_cleanup_freed, parse_log_sessions,
+    read_log_with_rotations, read_outdated_cache, write_files_atomically, write_outdated_cache,
+    AppContext, LogLevel, Session,
 };
-use crate::stats::PackageStats;
-use crate::ui::{show_interactive_selection, show_simple_selection};
-use crate::utils::log_operation;
+use crate::version::{version_jump_kind, MinBump, VersionJump};
+use crate::warnings::{WarningId, Warnings};
 
-pub fn dump_command(cli: &Cli, executor: &dyn BrewExecutor) -> Result<()> {
-    let config_path = get_config_path(&cli.config)?;
+/// Counts and timings for one upgrade run, emitted by `upgrade --final-json`.
+#[derive(Debug, Default, Serialize)]
+pub struct UpgradeSummary {
+    pub candidates: usize,
+    pub selected: usize,
+    pub successful: usize,
+    pub failed: usize,
+    pub dry_run: bool,
+    pub upgrade_duration_ms: u128,
+    pub total_duration_ms: u128,
+    /// Per-package outcomes, printed by `upgrade --json`
+    #[serde(skip)]
+    pub results: Vec<PackageResult>,
+}
 
-    if cli.dry_run {
-        println!("Would write settings to: {}", config_path.display());
+/// What happened to one selected package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpgradeStatus {
+    Upgraded,
+    Failed,
+    DryRun,
+    /// Selected, but left alone at a later prompt
+    Skipped,
+}
+
+/// One entry of the `upgrade --json` array.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackageResult {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    pub status: UpgradeStatus,
+    /// Why a package was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl PackageResult {
+    fn new(pkg: &OutdatedPackage, status: UpgradeStatus) -> Self {
+        Self {
+            name: pkg.name.clone(),
+            from: pkg.current_version.clone(),
+            to: pkg.available_version.clone(),
+            status,
+            reason: None,
+        }
     }
 
-    // Get manually installed formulae
-    let formulae = executor.get_manually_installed_formulae()?;
-    println!("Found {} manually installed formulae", formulae.len());
+    fn skipped(pkg: &OutdatedPackage, reason: &str) -> Self {
+        Self {
+            reason: Some(reason.to_string()),
+            ..Self::new(pkg, UpgradeStatus::Skipped)
+        }
+    }
+}
 
-    // Get manually installed casks
-    let casks = executor.get_manually_installed_casks()?;
-    println!("Found {} manually installed casks", casks.len());
+/// Some upgrades failed; `run` maps this to its own exit code.
+#[derive(Debug)]
+pub struct UpgradesFailed(pub usize);
 
-    // Read existing settings to preserve user selections
-    let existing_settings = read_existing_settings(&config_path)?;
+impl std::fmt::Display for UpgradesFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} upgrade(s) failed", self.0)
+    }
+}
 
-    // Read previous packages for change tracking
-    let (previous_formulae, previous_casks) = read_previous_packages(&config_path)?;
+impl std::error::Error for UpgradesFailed {}
+
+/// What `dump --json` prints instead of writing the settings file.
+#[derive(Debug, Serialize)]
+pub struct DumpReport {
+    pub formulae: Vec<String>,
+    pub casks: Vec<String>,
+    /// Enabled state per package, with existing choices merged in
+    pub enabled: BTreeMap<String, bool>,
+}
+
+impl DumpReport {
+    fn new(
+        formulae: &[String],
+        casks: &[String],
+        existing_settings: &HashMap<String, bool>,
+    ) -> Self {
+        let mut formulae = formulae.to_vec();
+        formulae.sort();
+        let mut casks = casks.to_vec();
+        casks.sort();
+
+        let enabled = formulae
+            .iter()
+            .chain(casks.iter())
+            .map(|pkg| {
+                (
+                    pkg.clone(),
+                    existing_settings.get(pkg).copied().unwrap_or(true),
+                )
+            })
+            .collect();
+
+        Self {
+            formulae,
+            casks,
+            enabled,
+        }
+    }
+}
+
+/// The manually installed packages a settings file is generated from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpResult {
+    pub formulae: Vec<String>,
+    pub casks: Vec<String>,
+}
+
+/// Gather what `dump` records without printing or writing anything, for
+/// callers that present it themselves.
+pub fn compute_dump(executor: &dyn BrewExecutor) -> Result<DumpResult> {
+    Ok(DumpResult {
+        formulae: executor.get_manually_installed_formulae()?,
+        casks: executor.get_manually_installed_casks()?,
+    })
+}
+
+/// The enabled packages in `settings` that `upgrade` would offer with its
+/// default options, without printing anything. It runs the same checks as
+/// `upgrade`, so pinned formulae are left out.
+pub fn compute_upgradeable(
+    executor: &dyn BrewExecutor,
+    settings: &HashMap<String, bool>,
+) -> Result<Vec<OutdatedPackage>> {
+    let enabled_packages: Vec<String> = settings
+        .iter()
+        .filter(|(_, &enabled)| enabled)
+        .map(|(package, _)| package.clone())
+        .collect();
+    if enabled_packages.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let candidates = find_candidates(
+        executor,
+        settings,
+        &enabled_packages,
+        &UpgradeArgs::default(),
+        None,
+        &CapturingSink::new(),
+    )?;
+    Ok(candidates.upgradeable)
+}
+
+/// Whether outdated checks include casks that update themselves: `--no-greedy`
+/// wins, then the settings header's `Greedy casks:` line, and greedy otherwise.
+pub fn greedy_casks(context: &AppContext, cli: &Cli) -> bool {
+    !cli.no_greedy
+        && get_config_path(context, &cli.config, cli.config_format)
+            .ok()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| parse_greedy_setting(&content))
+            .unwrap_or(true)
+}
+
+pub fn dump_command(
+    context: &AppContext,
+    cli: &Cli,
+    args: &DumpArgs,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    write_settings(&config_path, args, cli.dry_run, cli.json, executor, out)?;
+
+    // --json prints the selections instead of writing them, so there's nothing to edit
+    if args.edit
+        && !cli.dry_run
+        && !cli.json
+        && std::io::stdin().is_terminal()
+        && std::io::stdout().is_terminal()
+    {
+        edit_file(&config_path, out);
+    }
+    Ok(())
+}
+
+/// The editor to launch and its arguments, from `$EDITOR` or else `vi`.
+pub fn editor_command(editor: Option<&str>) -> (String, Vec<String>) {
+    let mut words = editor
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or("vi")
+        .split_whitespace()
+        .map(str::to_string);
+    let program = words.next().unwrap_or_else(|| "vi".to_string());
+    (program, words.collect())
+}
+
+fn edit_file(path: &Path, out: &dyn OutputSink) {
+    let editor = std::env::var("EDITOR").ok();
+    let (program, args) = editor_command(editor.as_deref());
+    match std::process::Command::new(&program)
+        .args(&args)
+        .arg(path)
+        .status()
+    {
+        Ok(status) if !status.success() => {
+            out.warn(&format!("{} exited with {}", program, status));
+        }
+        Ok(_) => {}
+        Err(e) => out.warn(&format!("Couldn't launch {}: {}", program, e)),
+    }
+}
+
+/// The body of `dump`, shared with the first-run setup in `upgrade`.
+fn write_settings(
+    config_path: &PathBuf,
+    args: &DumpArgs,
+    dry_run: bool,
+    json: bool,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    if dry_run && !json {
+        out.info(&format!(
+            "Would write settings to: {}",
+            config_path.display()
+        ));
+        if let Some(json_path) = &args.also_json {
+            out.info(&format!("Would write JSON settings to: {}", json_path));
+        }
+    }
+
+    let DumpResult { formulae, casks } = compute_dump(executor)?;
+    out.info(&format!(
+        "Found {} manually installed formulae",
+        formulae.len()
+    ));
+    out.info(&format!("Found {} manually installed casks", casks.len()));
+
+    // Read existing settings to preserve user selections; new packages get the chosen default
+    let existing_settings = settings_with_default(
+        &formulae,
+        &casks,
+        &read_existing_settings(config_path)?,
+        !args.default_disabled,
+    );
+
+    // JSON output replaces the settings file, so nothing touches disk
+    if json {
+        let report = DumpReport::new(&formulae, &casks, &existing_settings);
+        out.result(&serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    // Fetch outdated packages once for both the stats and the inline annotations
+    let outdated_packages = executor.get_outdated_packages().unwrap_or_default();
 
     // Collect package statistics
-    let stats = PackageStats::collect(
+    let stats = collect_package_stats(
         executor,
+        config_path,
         &formulae,
         &casks,
         &existing_settings,
-        if previous_formulae.is_empty() {
-            None
-        } else {
-            Some(&previous_formulae)
-        },
-        if previous_casks.is_empty() {
-            None
-        } else {
-            Some(&previous_casks)
-        },
+        &outdated_packages,
     )?;
 
-    // Generate new settings content with stats
-    let settings_content =
-        generate_settings_content(&formulae, &casks, &existing_settings, Some(&stats));
+    let versions: Option<HashMap<String, String>> = if args.record_versions {
+        Some(
+            executor
+                .get_installed_versions()?
+                .into_iter()
+                .map(|(name, version, _)| (name, version))
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    // Generate new settings content with stats, keeping the user's own notes
+    let settings_content = match ConfigFormat::from_path(config_path) {
+        ConfigFormat::Toml => generate_settings_toml(
+            &formulae,
+            &casks,
+            &existing_settings,
+            read_settings_extras(config_path)?.greedy,
+        )?,
+        ConfigFormat::Markdown => merge_settings_extras(
+            &generate_settings_content(
+                &formulae,
+                &casks,
+                &existing_settings,
+                &outdated_packages,
+                Some(&stats),
+                versions.as_ref(),
+            ),
+            &read_settings_extras(config_path)?,
+        ),
+    };
 
-    if cli.dry_run {
-        println!("\nSettings content would be:");
-        println!("{}", settings_content);
+    // Generate the JSON up front so a failure can't leave a half-written pair
+    let json_output = match &args.also_json {
+        Some(json_path) => Some((
+            Path::new(json_path),
+            generate_settings_json(&formulae, &casks, &existing_settings)?,
+        )),
+        None => None,
+    };
+
+    if dry_run {
+        out.info("\nSettings content would be:");
+        out.result(&settings_content);
     } else {
-        // Ensure config directory exists
-        if let Some(parent) = config_path.parent() {
-            fs::create_dir_all(parent)?;
+        let mut files: Vec<(&Path, &str)> = vec![(config_path.as_path(), &settings_content)];
+        if let Some((json_path, json_content)) = &json_output {
+            files.push((json_path, json_content));
         }
 
-        // Write settings file
-        fs::write(&config_path, settings_content)?;
-        println!("Settings written to: {}", config_path.display());
+        if args.backup {
+            if let Some(backup) = backup_settings(config_path)? {
+                out.info(&format!(
+                    "Previous settings backed up to: {}",
+                    backup.display()
+                ));
+            }
+        }
+        write_files_atomically(&files)?;
+        out.result(&format!("Settings written to: {}", config_path.display()));
+        if let Some((json_path, _)) = &json_output {
+            out.result(&format!(
+                "JSON settings written to: {}",
+                json_path.display()
+            ));
+        }
     }
 
     Ok(())
 }
 
-pub fn upgrade_command(cli: &Cli, executor: &dyn BrewExecutor) -> Result<()> {
-    let config_path = get_config_path(&cli.config)?;
+pub fn stats_command(
+    context: &AppContext,
+    cli: &Cli,
+    args: &StatsArgs,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+
+    let formulae = executor.get_manually_installed_formulae()?;
+    let casks = executor.get_manually_installed_casks()?;
+    let existing_settings = read_existing_settings(&config_path)?;
+    let outdated_packages = executor.get_outdated_packages().unwrap_or_default();
+
+    let stats = collect_package_stats(
+        executor,
+        &config_path,
+        &formulae,
+        &casks,
+        &existing_settings,
+        &outdated_packages,
+    )?;
+
+    let format = if cli.json {
+        StatsFormat::Json
+    } else {
+        args.output_format
+    };
+    out.result(stats.format(format)?.trim_end());
+
+    Ok(())
+}
 
-    // Read settings file
+pub fn list_command(
+    context: &AppContext,
+    cli: &Cli,
+    args: &ListArgs,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
     if !config_path.exists() {
         anyhow::bail!(
             "Settings file not found at {}. Run 'dump' command first.",
@@ -82,188 +424,3208 @@ pub fn upgrade_command(cli: &Cli, executor: &dyn BrewExecutor) -> Result<()> {
     }
 
     let settings = read_existing_settings(&config_path)?;
-    if settings.is_empty() {
-        anyhow::bail!("No packages found in settings file. Run 'dump' command first.");
+    let (formulae, casks) = read_previous_packages(&config_path)?;
+
+    for (index, (heading, packages)) in [("Formulae", formulae), ("Casks", casks)]
+        .into_iter()
+        .enumerate()
+    {
+        if index > 0 {
+            out.result("");
+        }
+        out.result(&format!("{}:", heading));
+        for name in packages {
+            let enabled = settings.get(&name).copied().unwrap_or(true);
+            if (args.enabled_only && !enabled) || (args.disabled_only && enabled) {
+                continue;
+            }
+            out.result(&format!("  [{}] {}", if enabled { "x" } else { " " }, name));
+        }
     }
 
-    // Get enabled packages
-    let enabled_packages: Vec<String> = settings
-        .iter()
-        .filter(|(_, &enabled)| enabled)
-        .map(|(package, _)| package.clone())
-        .collect();
+    Ok(())
+}
 
-    if enabled_packages.is_empty() {
-        println!("No packages are enabled for upgrade in settings.");
+pub fn validate_command(
+    context: &AppContext,
+    cli: &Cli,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    if !config_path.exists() {
+        anyhow::bail!(
+            "Settings file not found at {}. Run 'dump' command first.",
+            config_path.display()
+        );
+    }
+
+    let entries = read_settings_entries(&config_path)?;
+    let formulae = executor.get_manually_installed_formulae()?;
+    let casks = executor.get_manually_installed_casks()?;
+
+    let problems = find_settings_problems(&entries, &formulae, &casks);
+    if problems.is_empty() {
+        out.result(&format!(
+            "✅ {} looks good ({} entries)",
+            config_path.display(),
+            entries.len()
+        ));
         return Ok(());
     }
 
-    println!("Checking for outdated packages...");
+    for problem in &problems {
+        out.result(&format!("❌ {}", problem));
+    }
+    anyhow::bail!(
+        "{} has {} problem(s); fix them or run 'dump' to regenerate it",
+        config_path.display(),
+        problems.len()
+    );
+}
 
-    // Get outdated packages
-    let outdated_packages = executor.get_outdated_packages()?;
+pub fn clean_command(
+    context: &AppContext,
+    cli: &Cli,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    if !config_path.exists() {
+        anyhow::bail!(
+            "Settings file not found at {}. Run 'dump' command first.",
+            config_path.display()
+        );
+    }
 
-    // Filter to only enabled and outdated packages
-    let upgradeable_packages: Vec<&OutdatedPackage> = outdated_packages
-        .iter()
-        .filter(|pkg| enabled_packages.contains(&pkg.name))
-        .collect();
+    let content = std::fs::read_to_string(&config_path)?;
+    // Anything still installed stays, including packages brew now counts as dependencies
+    let (formulae, casks): (Vec<_>, Vec<_>) = executor
+        .get_installed_versions()?
+        .into_iter()
+        .partition(|(_, _, package_type)| *package_type == PackageType::Formula);
+    let names = |packages: Vec<(String, String, PackageType)>| -> Vec<String> {
+        packages.into_iter().map(|(name, _, _)| name).collect()
+    };
+    let (formulae, casks) = (names(formulae), names(casks));
+    let pruned = prune_settings(
+        &content,
+        ConfigFormat::from_path(&config_path),
+        &formulae,
+        &casks,
+    );
 
-    if upgradeable_packages.is_empty() {
-        println!("All enabled packages are up to date!");
+    if pruned.removed.is_empty() {
+        out.result("Nothing to clean: every settings entry is still installed.");
         return Ok(());
     }
 
-    // Show interactive selection (fallback to simple prompt if TUI fails)
-    let selected_packages = match show_interactive_selection(&upgradeable_packages) {
-        Ok(packages) => packages,
-        Err(_) => {
-            // Fallback to simple text-based selection
-            show_simple_selection(&upgradeable_packages)?
-        }
+    let verb = if cli.dry_run {
+        "Would remove"
+    } else {
+        "Removed"
     };
+    for name in &pruned.removed {
+        out.result(&format!("{} {}", verb, name));
+    }
 
-    if selected_packages.is_empty() {
-        println!("No packages selected for upgrade.");
+    if cli.dry_run {
+        out.info(&format!(
+            "\nDry run: {} entries would be removed from {}",
+            pruned.removed.len(),
+            config_path.display()
+        ));
         return Ok(());
     }
 
-    // Execute upgrades
-    execute_upgrades(&selected_packages, cli.dry_run, executor)?;
-
+    write_files_atomically(&[(&config_path, &pruned.content)])?;
+    out.info(&format!(
+        "\nRemoved {} entries from {}",
+        pruned.removed.len(),
+        config_path.display()
+    ));
     Ok(())
 }
 
-fn execute_upgrades(
-    packages: &[OutdatedPackage],
-    dry_run: bool,
+pub fn diff_command(
+    context: &AppContext,
+    cli: &Cli,
     executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
 ) -> Result<()> {
-    println!(
-        "\n{} upgrade for {} packages:",
-        if dry_run {
-            "Would execute"
-        } else {
-            "Executing"
-        },
-        packages.len()
-    );
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    if !config_path.exists() {
+        anyhow::bail!(
+            "Settings file not found at {}. Run 'dump' command first.",
+            config_path.display()
+        );
+    }
 
-    if !dry_run {
-        log_operation(&format!("Starting upgrade of {} packages", packages.len()))?;
+    let (previous_formulae, previous_casks) = read_previous_packages(&config_path)?;
+    let formulae = executor.get_manually_installed_formulae()?;
+    let casks = executor.get_manually_installed_casks()?;
+
+    let diff = diff_packages(&formulae, &casks, &previous_formulae, &previous_casks);
+    if diff.is_empty() {
+        out.result("No changes since last dump");
+        return Ok(());
     }
 
-    let mut successful_upgrades = 0;
-    let mut failed_upgrades = 0;
+    out.result("Changes since last dump:");
+    for (heading, added, removed) in [
+        ("Formulae", &diff.added_formulae, &diff.removed_formulae),
+        ("Casks", &diff.added_casks, &diff.removed_casks),
+    ] {
+        if added.is_empty() && removed.is_empty() {
+            continue;
+        }
+        out.result(&format!("\n{}:", heading));
+        for name in added {
+            out.result(&format!("  + {}", name));
+        }
+        for name in removed {
+            out.result(&format!("  - {}", name));
+        }
+    }
 
-    for pkg in packages {
-        println!(
-            "  {} {} {} → {}",
-            if dry_run {
-                "Would upgrade"
-            } else {
-                "Upgrading"
-            },
-            pkg.name,
-            pkg.current_version,
-            pkg.available_version
+    Ok(())
+}
+
+pub fn drift_command(
+    context: &AppContext,
+    cli: &Cli,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    let recorded = read_recorded_versions(&config_path)?;
+    if recorded.is_empty() {
+        anyhow::bail!(
+            "No versions recorded in {}. Run 'dump --record-versions' first.",
+            config_path.display()
         );
+    }
 
-        if !dry_run {
-            match executor.upgrade_package(pkg) {
-                Ok(_) => {
-                    println!("    ✅ Successfully upgraded {}", pkg.name);
-                    log_operation(&format!(
-                        "SUCCESS: {} {} → {}",
-                        pkg.name, pkg.current_version, pkg.available_version
-                    ))?;
-                    successful_upgrades += 1;
-                }
-                Err(e) => {
-                    eprintln!("    ❌ Failed to upgrade {}: {}", pkg.name, e);
-                    log_operation(&format!(
-                        "FAILED: {} {} → {} - {}",
-                        pkg.name, pkg.current_version, pkg.available_version, e
-                    ))?;
-                    failed_upgrades += 1;
-                }
+    let installed: HashMap<String, String> = executor
+        .get_installed_versions()?
+        .into_iter()
+        .map(|(name, version, _)| (name, version))
+        .collect();
+    let mut names: Vec<&String> = recorded.keys().collect();
+    names.sort();
+
+    let mut drifted = 0;
+    for name in names {
+        let recorded_version = &recorded[name];
+        match installed.get(name) {
+            Some(version) if version == recorded_version => {}
+            Some(version) => {
+                out.result(&format!("{}: {} → {}", name, recorded_version, version));
+                drifted += 1;
+            }
+            None => {
+                out.result(&format!("{}: {} → not installed", name, recorded_version));
+                drifted += 1;
             }
         }
     }
 
-    if dry_run {
-        println!("\nDry run completed. Use without --dry-run to execute upgrades.");
+    if drifted == 0 {
+        out.result("No drift: every recorded version is still installed");
     } else {
-        println!(
-            "\nUpgrade completed! {} successful, {} failed",
-            successful_upgrades, failed_upgrades
-        );
-        log_operation(&format!(
-            "Upgrade session completed: {} successful, {} failed",
-            successful_upgrades, failed_upgrades
-        ))?;
+        out.info(&format!(
+            "{} package(s) changed outside brew-update-helper since the last dump",
+            drifted
+        ));
     }
 
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::brew::MockBrewExecutor;
-    use crate::cli::Commands;
-    use tempfile::TempDir;
-
-    #[test]
-    fn test_dump_command_with_mock() -> Result<()> {
-        let temp_dir = TempDir::new()?;
-        let config_path = temp_dir.path().join("settings.md");
+/// Local tap that `brew extract` copies old formula versions into.
+const ROLLBACK_TAP: &str = "brew-update-helper/rollback";
 
-        let executor = MockBrewExecutor::new();
-        let cli = Cli {
-            command: Commands::Dump,
-            dry_run: false,
-            config: Some(config_path.to_string_lossy().to_string()),
-        };
+pub fn rollback_command(
+    context: &AppContext,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let log_path = context.log_path()?;
+    if !log_path.exists() {
+        anyhow::bail!("No upgrade log found at {}", log_path.display());
+    }
 
-        dump_command(&cli, &executor)?;
+    let session = get_last_session(&read_log_with_rotations(&log_path)?);
+    if session.is_empty() {
+        out.result("The most recent upgrade session has no successful upgrades to roll back");
+        return Ok(());
+    }
 
-        assert!(config_path.exists());
-        let content = std::fs::read_to_string(&config_path)?;
-        assert!(content.contains("# Brew Auto-Update Settings"));
-        assert!(content.contains("git"));
-        assert!(content.contains("docker"));
+    // The log doesn't say which entries were casks, so ask brew
+    let casks: Vec<String> = executor
+        .get_installed_versions()?
+        .into_iter()
+        .filter(|(_, _, package_type)| *package_type == PackageType::Cask)
+        .map(|(name, _, _)| name)
+        .collect();
+    let is_cask = |name: &str| casks.iter().any(|cask| same_package(cask, name));
 
-        Ok(())
+    // Homebrew only ships the latest version, so old ones come from the formula history
+    out.info(&format!(
+        "Run these commands to restore the versions from before the last session ({} package(s)):",
+        session.len()
+    ));
+    if session.iter().any(|(name, _, _)| !is_cask(name)) {
+        out.result(&format!("brew tap-new --no-git {}", ROLLBACK_TAP));
+    }
+    for (name, old_version, new_version) in &session {
+        out.result(&format!("# {}: {} → {}", name, new_version, old_version));
+        if is_cask(name) {
+            // Casks have no version history to extract from
+            out.result(&format!(
+                "# {} is a cask: install {} from the vendor's old release, then uncheck it in the settings",
+                name, old_version
+            ));
+            continue;
+        }
+        out.result(&format!(
+            "brew extract --version={} {} {}",
+            old_version, name, ROLLBACK_TAP
+        ));
+        out.result(&format!("brew unlink {}", name));
+        out.result(&format!(
+            "brew install {}/{}@{}",
+            ROLLBACK_TAP, name, old_version
+        ));
     }
 
-    #[test]
-    fn test_dump_command_includes_stats() -> Result<()> {
-        let temp_dir = TempDir::new()?;
-        let config_path = temp_dir.path().join("settings.md");
+    Ok(())
+}
 
-        let executor = MockBrewExecutor::new();
-        let cli = Cli {
-            command: Commands::Dump,
-            dry_run: false,
-            config: Some(config_path.to_string_lossy().to_string()),
-        };
+pub fn history_command(
+    context: &AppContext,
+    args: &HistoryArgs,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let log_path = context.log_path()?;
+    if !log_path.exists() {
+        anyhow::bail!("No upgrade log found at {}", log_path.display());
+    }
 
-        dump_command(&cli, &executor)?;
+    let mut sessions = parse_log_sessions(&read_log_with_rotations(&log_path)?);
+    if let Some(since) = args.since {
+        sessions.retain(|session| session.started_at().is_some_and(|started| started >= since));
+    }
+    if sessions.is_empty() {
+        out.result(if args.since.is_some() {
+            "No upgrade sessions in that period"
+        } else {
+            "No upgrade sessions recorded yet"
+        });
+        return Ok(());
+    }
+    let skipped = args
+        .limit
+        .map_or(0, |limit| sessions.len().saturating_sub(limit));
+    let shown = &sessions[skipped..];
+
+    out.result(&format!(
+        "Upgrade sessions ({} of {}):",
+        shown.len(),
+        sessions.len()
+    ));
+    for session in shown {
+        out.result(&format!(
+            "  {}  {} planned, {} succeeded, {} failed{}",
+            session.started,
+            session.planned,
+            session.upgraded.len(),
+            session.failed,
+            if session.completed {
+                ""
+            } else {
+                " (interrupted)"
+            }
+        ));
+    }
+
+    let tally = most_upgraded(shown);
+    if !tally.is_empty() {
+        out.result("\nMost upgraded:");
+        for (name, count) in tally.iter().take(MOST_UPGRADED_SHOWN) {
+            out.result(&format!("  {} ({}×)", name, count));
+        }
+    }
+
+    Ok(())
+}
+
+/// How many packages `history` lists under "Most upgraded".
+const MOST_UPGRADED_SHOWN: usize = 5;
+
+/// How often each package was upgraded successfully across `sessions`, most first.
+pub fn most_upgraded(sessions: &[Session]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for name in sessions.iter().flat_map(|session| &session.upgraded) {
+        *counts.entry(name).or_default() += 1;
+    }
+    let mut tally: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
+    tally.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    tally
+}
+
+pub fn export_command(
+    context: &AppContext,
+    cli: &Cli,
+    args: &ExportArgs,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    if !config_path.exists() {
+        anyhow::bail!(
+            "Settings file not found at {}. Run 'dump' command first.",
+            config_path.display()
+        );
+    }
+
+    let settings = read_existing_settings(&config_path)?;
+    let (formulae, casks) = read_previous_packages(&config_path)?;
+    let content = match args.format {
+        ExportFormat::Brewfile => generate_brewfile(&formulae, &casks, &settings),
+    };
+
+    match &args.output {
+        Some(path) if cli.dry_run => {
+            out.info(&format!("Would write export to: {}", path));
+            out.result(&content);
+        }
+        Some(path) => {
+            write_files_atomically(&[(Path::new(path), &content)])?;
+            out.result(&format!("Export written to: {}", path));
+        }
+        None => out.result(content.trim_end()),
+    }
+
+    Ok(())
+}
+
+pub fn snapshot_command(
+    cli: &Cli,
+    args: &SnapshotArgs,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let now = Utc::now();
+    let path = args
+        .output
+        .clone()
+        .unwrap_or_else(|| format!("brew-snapshot-{}.json", now.format("%Y%m%d-%H%M%S")));
+    let versions = executor.get_installed_versions()?;
+    let count = versions.len();
+    let content = generate_snapshot_json(versions, now)?;
+
+    if cli.dry_run {
+        out.info(&format!("Would write snapshot to: {}", path));
+        out.result(&content);
+    } else {
+        write_files_atomically(&[(Path::new(&path), &content)])?;
+        out.result(&format!(
+            "Snapshot of {} package(s) written to: {}",
+            count, path
+        ));
+    }
+
+    Ok(())
+}
+
+/// Build the statistics, comparing against the packages listed in the
+/// settings file; a missing file simply leaves out the changes.
+fn collect_package_stats(
+    executor: &dyn BrewExecutor,
+    config_path: &PathBuf,
+    formulae: &[String],
+    casks: &[String],
+    existing_settings: &HashMap<String, bool>,
+    outdated_packages: &[OutdatedPackage],
+) -> Result<PackageStats> {
+    let (previous_formulae, previous_casks) = read_previous_packages(config_path)?;
+
+    PackageStats::collect(
+        executor,
+        formulae,
+        casks,
+        existing_settings,
+        outdated_packages,
+        if previous_formulae.is_empty() {
+            None
+        } else {
+            Some(&previous_formulae)
+        },
+        if previous_casks.is_empty() {
+            None
+        } else {
+            Some(&previous_casks)
+        },
+    )
+}
+
+pub fn upgrade_command(
+    context: &AppContext,
+    cli: &Cli,
+    args: &UpgradeArgs,
+    executor: &(dyn BrewExecutor + Sync),
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let started = Instant::now();
+    // Two brews at once can leave the Cellar half-upgraded; listing installs nothing
+    if !cli.dry_run && !args.list_outdated && executor.is_brew_busy()? {
+        anyhow::bail!("Another Homebrew process is running; wait for it to finish and try again");
+    }
+    let mut summary = run_upgrade(context, cli, args, executor, out)?;
+    summary.total_duration_ms = started.elapsed().as_millis();
+
+    if cli.json {
+        out.result(&serde_json::to_string_pretty(&summary.results)?);
+    }
+
+    // Wrappers parse this as the very last line of output
+    if args.final_json {
+        out.result(&serde_json::to_string(&summary)?);
+    }
+
+    if summary.failed > 0 {
+        return Err(UpgradesFailed(summary.failed).into());
+    }
+
+    Ok(())
+}
+
+/// Only a person at a terminal can answer the setup questions; scripts, `--yes`
+/// and `--dry-run` keep failing fast.
+fn offer_setup_wizard(cli: &Cli, args: &UpgradeArgs) -> bool {
+    !args.no_wizard
+        && !args.yes
+        && !cli.dry_run
+        && !cli.json
+        && !is_test_environment()
+        && std::io::stdin().is_terminal()
+        && std::io::stdout().is_terminal()
+}
+
+/// Generate the settings file the way `dump` would, once the user agrees.
+/// Returns whether the file was written.
+fn run_setup_wizard(
+    config_path: &PathBuf,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<bool> {
+    out.info(&format!(
+        "No settings file found at {}.",
+        config_path.display()
+    ));
+    out.info("It lists your installed packages so you can choose which ones upgrade picks up.");
+    if !confirm("Create it now with every package enabled? (y/n): ")? {
+        return Ok(false);
+    }
+
+    write_settings(
+        config_path,
+        &DumpArgs::default(),
+        false,
+        false,
+        executor,
+        out,
+    )?;
+    out.info("Edit the file to uncheck packages you want to keep as they are.\n");
+    Ok(true)
+}
+
+/// Packages installed since the last dump are never offered, so point out an
+/// old settings file.
+fn warn_if_settings_old(
+    config_path: &Path,
+    max_age_days: u32,
+    now: DateTime<Utc>,
+    warnings: &Warnings,
+) -> Result<()> {
+    if max_age_days == 0 {
+        return Ok(());
+    }
+    let Some(generated_on) = parse_generated_timestamp(&std::fs::read_to_string(config_path)?)
+    else {
+        return Ok(());
+    };
+
+    let age_days = (now - generated_on).num_days();
+    if age_days > i64::from(max_age_days) {
+        warnings.emit(
+            WarningId::OldSettings,
+            &format!(
+                "settings were generated {} days ago; run 'dump' to pick up newly installed packages",
+                age_days
+            ),
+        );
+    }
+    Ok(())
+}
+
+fn run_upgrade(
+    context: &AppContext,
+    cli: &Cli,
+    args: &UpgradeArgs,
+    executor: &(dyn BrewExecutor + Sync),
+    out: &dyn OutputSink,
+) -> Result<UpgradeSummary> {
+    let mut summary = UpgradeSummary {
+        dry_run: cli.dry_run,
+        ..Default::default()
+    };
+
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    let warnings = Warnings::new(out, &cli.suppress);
+
+    let only = !args.only.is_empty();
+    let (settings, mut enabled_packages) = if only {
+        // --only bypasses the settings file, so it works before the first dump
+        let settings: HashMap<String, bool> =
+            args.only.iter().map(|name| (name.clone(), true)).collect();
+        (settings, args.only.clone())
+    } else {
+        // Read settings file, offering to create it on a first interactive run
+        if !config_path.exists() {
+            let created =
+                offer_setup_wizard(cli, args) && run_setup_wizard(&config_path, executor, out)?;
+            if !created {
+                anyhow::bail!(
+                    "Settings file not found at {}. Run 'dump' command first.",
+                    config_path.display()
+                );
+            }
+        }
+
+        let settings = read_existing_settings(&config_path)?;
+        if settings.is_empty() {
+            anyhow::bail!("No packages found in settings file. Run 'dump' command first.");
+        }
+        warn_if_settings_old(&config_path, args.settings_max_age, Utc::now(), &warnings)?;
+
+        // Get enabled packages
+        let enabled_packages: Vec<String> = settings
+            .iter()
+            .filter(|(_, &enabled)| enabled)
+            .map(|(package, _)| package.clone())
+            .collect();
+
+        if enabled_packages.is_empty() {
+            out.info("No packages are enabled for upgrade in settings.");
+            return Ok(summary);
+        }
+
+        (settings, enabled_packages)
+    };
+
+    // Drop stale settings entries for packages that are no longer installed
+    if args.filter_installed {
+        let enabled_count = enabled_packages.len();
+        retain_installed(&mut enabled_packages, executor)?;
+        let stale_count = enabled_count - enabled_packages.len();
+        if stale_count > 0 {
+            warnings.emit(
+                WarningId::StaleSettings,
+                &format!(
+                    "{} enabled package(s) in settings are no longer installed; run 'dump' to refresh",
+                    stale_count
+                ),
+            );
+        }
+        if enabled_packages.is_empty() {
+            out.info("None of the enabled packages are currently installed.");
+            return Ok(summary);
+        }
+    }
+
+    out.info("Checking for outdated packages...");
+
+    let candidates = find_candidates(
+        executor,
+        &settings,
+        &enabled_packages,
+        args,
+        Some(&config_path),
+        out,
+    )?;
+    let outdated_packages = &candidates.outdated;
+    let filter = candidates.filter(&settings, &enabled_packages, args);
+    let upgradeable_packages: Vec<&OutdatedPackage> = candidates.upgradeable.iter().collect();
+    if only {
+        for name in &enabled_packages {
+            if !outdated_packages
+                .iter()
+                .any(|pkg| same_package(&pkg.name, name))
+            {
+                warnings.emit(
+                    WarningId::NotOutdated,
+                    &format!("{} is not outdated, nothing to upgrade", name),
+                );
+            }
+        }
+    }
+
+    for pkg in outdated_packages {
+        if lists_package(&enabled_packages, &pkg.name) && lists_package(&args.exclude, &pkg.name) {
+            out.info(&format!("Excluding {} (--exclude)", pkg.name));
+        }
+    }
+
+    for pkg in outdated_packages {
+        if lists_package(&enabled_packages, &pkg.name) && filter.is_pinned(pkg) {
+            out.info(&format!(
+                "Skipping {}: it is pinned (run 'brew unpin {}' to allow upgrades)",
+                pkg.name, pkg.name
+            ));
+        }
+    }
+
+    for pkg in outdated_packages {
+        if lists_package(&enabled_packages, &pkg.name) && filter.is_running_service(pkg) {
+            out.info(&format!(
+                "Skipping {}: its brew service is running (stop it or drop --skip-running-services)",
+                pkg.name
+            ));
+        }
+    }
+
+    for pkg in outdated_packages {
+        let self_updating = matches!(pkg.package_type, PackageType::Cask)
+            && lists_package(&enabled_packages, &pkg.name)
+            && lists_package(&candidates.auto_updating_casks, &pkg.name);
+        if !self_updating {
+            continue;
+        }
+        if args.skip_auto_updates {
+            out.info(&format!(
+                "Skipping {}: the app updates itself (drop --skip-auto-updates to include it)",
+                pkg.name
+            ));
+        } else {
+            out.info(&format!(
+                "Note: {} updates itself; --skip-auto-updates leaves it to the app",
+                pkg.name
+            ));
+        }
+    }
+
+    // Only a full scan sees packages outside the settings; --only has no settings to compare
+    let untracked_count = outdated_packages
+        .iter()
+        .filter(|pkg| !settings.keys().any(|name| same_package(name, &pkg.name)))
+        .count();
+    if !only && untracked_count > 0 {
+        warnings.emit(
+            WarningId::UntrackedPackages,
+            &format!(
+                "{} outdated package(s) aren't in your settings; run 'dump' to add them",
+                untracked_count
+            ),
+        );
+    }
+
+    if greedy_casks(context, cli)
+        && upgradeable_packages
+            .iter()
+            .any(|pkg| matches!(pkg.package_type, PackageType::Cask))
+    {
+        warnings.emit(
+            WarningId::GreedyCasks,
+            "casks are checked with --greedy, so apps that update themselves are included",
+        );
+    }
+
+    if args.explain {
+        out.info("\nUpgrade decisions:");
+        for explanation in &candidates.explanations {
+            out.info(&format!("  {}", explanation));
+        }
+        out.info("");
+    }
+
+    summary.candidates = upgradeable_packages.len();
+
+    if upgradeable_packages.is_empty() {
+        // Point out packages held back only because they're unchecked; a
+        // scoped check only asked brew about enabled ones, so scan everything
+        let disabled: Vec<String> = settings
+            .iter()
+            .filter(|(_, &enabled)| !enabled)
+            .map(|(name, _)| name.clone())
+            .collect();
+        let count_disabled = |outdated: &[OutdatedPackage]| {
+            outdated
+                .iter()
+                .filter(|pkg| lists_package(&disabled, &pkg.name))
+                .count()
+        };
+        let count_disabled_outdated = || -> Result<usize> {
+            if only || disabled.is_empty() {
+                Ok(0)
+            } else if candidates.full_scan {
+                Ok(count_disabled(outdated_packages))
+            } else {
+                Ok(count_disabled(&scan_outdated(
+                    executor,
+                    args,
+                    Some(&config_path),
+                    out,
+                )?))
+            }
+        };
+        // Only a hint, so a failed lookup just leaves it out
+        let disabled_outdated = count_disabled_outdated().unwrap_or(0);
+        let message = match disabled_outdated {
+            0 => "All enabled packages are up to date!".to_string(),
+            1 => "All enabled packages are up to date! (1 disabled package is outdated — run 'list --disabled-only' to review)".to_string(),
+            count => format!(
+                "All enabled packages are up to date! ({} disabled packages are outdated — run 'list --disabled-only' to review)",
+                count
+            ),
+        };
+        // With --json, stdout is reserved for the (empty) results array
+        if cli.json {
+            out.info(&message);
+        } else {
+            out.result(&message);
+        }
+        return Ok(summary);
+    }
+
+    if args.list_outdated {
+        for row in outdated_table(&upgradeable_packages) {
+            out.result(&row);
+        }
+        return Ok(summary);
+    }
+
+    // Show interactive selection (fallback to simple prompt if TUI fails)
+    let selection = if args.yes {
+        // Never touch the terminal, so this works from cron without a TTY
+        SelectionOutcome {
+            selected: upgradeable_packages
+                .iter()
+                .map(|pkg| (*pkg).clone())
+                .collect(),
+            to_disable: Vec::new(),
+        }
+    } else {
+        // Casks have no dependency tree worth showing; a failed lookup just hides the counts
+        let formulae: Vec<String> = upgradeable_packages
+            .iter()
+            .filter(|pkg| pkg.package_type == PackageType::Formula)
+            .map(|pkg| pkg.name.clone())
+            .collect();
+        let installed_deps = executor
+            .get_installed_dep_counts(&formulae)
+            .unwrap_or_default();
+        let dep_counts: Vec<Option<usize>> = upgradeable_packages
+            .iter()
+            .map(|pkg| match pkg.package_type {
+                PackageType::Formula => installed_deps.get(&pkg.name).copied(),
+                PackageType::Cask => None,
+            })
+            .collect();
+        let use_tui = match args.interactive {
+            SelectionMode::Tui => true,
+            SelectionMode::Simple => false,
+            // Skip TUI in test environments to avoid terminal state issues, and
+            // with --json, whose stdout the TUI would draw into
+            SelectionMode::Auto => !is_test_environment() && !cli.json,
+        };
+        if !use_tui {
+            show_simple_selection(&upgradeable_packages, &dep_counts)?
+        } else {
+            match show_interactive_selection(executor, &upgradeable_packages, &dep_counts) {
+                Ok(selection) => selection,
+                Err(_) => {
+                    // Fallback to simple text-based selection
+                    show_simple_selection(&upgradeable_packages, &dep_counts)?
+                }
+            }
+        }
+    };
+
+    // Persist "never upgrade" choices made in the TUI
+    for package in &selection.to_disable {
+        if cli.dry_run {
+            out.info(&format!("Would disable {} in settings", package));
+        } else if !config_path.exists() {
+            out.info(&format!(
+                "No settings file yet; run 'dump' before disabling {}",
+                package
+            ));
+        } else if set_package_enabled(&config_path, package, false)? {
+            out.info(&format!("Disabled {} in settings", package));
+        }
+    }
+
+    let selected_packages = selection.selected;
+
+    // The decisions above stop at the filters; the prompt can still drop packages
+    if args.explain {
+        for pkg in &upgradeable_packages {
+            if !selected_packages
+                .iter()
+                .any(|selected| selected.name == pkg.name)
+            {
+                out.info(&format!(
+                    "  {}: enabled ✓, outdated ✓, selected ✗ → skipped (not selected)",
+                    pkg.name
+                ));
+            }
+        }
+    }
+
+    if selected_packages.is_empty() {
+        out.info("No packages selected for upgrade.");
+        return Ok(summary);
+    }
+
+    let selected_packages = match args.upgrade_order {
+        UpgradeOrder::Listed => selected_packages,
+        UpgradeOrder::DepsFirst => {
+            let mut deps_map = HashMap::new();
+            for pkg in &selected_packages {
+                if matches!(pkg.package_type, PackageType::Formula) {
+                    match executor.get_deps(&pkg.name) {
+                        Ok(deps) => {
+                            deps_map.insert(pkg.name.clone(), deps);
+                        }
+                        Err(e) => out.warn(&format!(
+                            "Could not read dependencies of {}: {}",
+                            pkg.name, e
+                        )),
+                    }
+                }
+            }
+            order_by_dependencies(&selected_packages, &deps_map)
+        }
+    };
+
+    if args.show_homepage || args.open {
+        let homepages = collect_homepages(&selected_packages, executor);
+        if !homepages.is_empty() {
+            out.info("\nHomepages:");
+            for (name, url) in &homepages {
+                out.info(&format!("  {}: {}", name, url));
+            }
+        }
+        if args.open {
+            for (name, url) in &homepages {
+                if let Err(e) = open_url(url) {
+                    out.warn(&format!("Could not open homepage for {}: {}", name, e));
+                }
+            }
+        }
+    }
+
+    // Execute upgrades
+    let options = ExecuteOptions {
+        dry_run: cli.dry_run,
+        batch_size: args.batch_size.map(|size| size as usize),
+        confirm_batches: !args.yes && std::io::stdin().is_terminal(),
+        confirm_major: !args.yes && !args.allow_major && std::io::stdin().is_terminal(),
+        stream_output: args.verbose,
+        retries: args.retries,
+        retry_delay: RETRY_BASE_DELAY,
+        json_results: cli.json,
+        show_deps: args.show_deps,
+        progress: !cli.dry_run
+            && !cli.quiet
+            && !cli.json
+            && !args.verbose
+            && !is_test_environment()
+            && std::io::stderr().is_terminal(),
+        package_args: read_package_settings(&config_path)?
+            .into_iter()
+            .filter(|(_, package)| !package.extra_args.is_empty())
+            .map(|(name, package)| (short_name(&name).to_string(), package.extra_args))
+            .collect(),
+        parallel_fetch: args.parallel_fetch,
+    };
+    let execution = execute_upgrades(context, &selected_packages, &options, executor, out)?;
+
+    if args.explain {
+        for result in &execution.results {
+            if let (UpgradeStatus::Skipped, Some(reason)) = (result.status, &result.reason) {
+                out.info(&format!(
+                    "  {}: enabled ✓, outdated ✓, selected ✓, confirmed ✗ → skipped ({})",
+                    result.name, reason
+                ));
+            }
+        }
+    }
+
+    // Upgraded packages are no longer outdated, so the cached list is wrong now
+    let cache_path = outdated_cache_path(&config_path);
+    if execution.successful > 0 && cache_path.exists() {
+        std::fs::remove_file(&cache_path)?;
+    }
+
+    // Keep `dump --record-versions` entries current, so drift only shows outside changes
+    if !only && execution.successful > 0 {
+        let upgraded: HashMap<String, String> = execution
+            .results
+            .iter()
+            .filter(|result| result.status == UpgradeStatus::Upgraded)
+            .map(|result| (result.name.clone(), result.to.clone()))
+            .collect();
+        update_recorded_versions(&config_path, &upgraded)?;
+    }
+
+    if args.cleanup && !cli.dry_run && execution.successful > 0 {
+        out.info("\nRunning brew cleanup...");
+        match executor.cleanup() {
+            Ok(output) => match parse_cleanup_freed(&output) {
+                Some(bytes) => out.info(&format!(
+                    "🧹 Cleanup freed approximately {}",
+                    format_size(Some(bytes))
+                )),
+                None => out.info("🧹 Cleanup found nothing to remove"),
+            },
+            Err(e) => out.warn(&format!("Cleanup failed: {}", e)),
+        }
+    }
+
+    Ok(UpgradeSummary {
+        candidates: summary.candidates,
+        ..execution
+    })
+}
+
+pub fn paths_command(context: &AppContext, cli: &Cli, out: &dyn OutputSink) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    out.result(&format!("Settings: {}", config_path.display()));
+    out.result(&format!("Log:      {}", context.log_path()?.display()));
+    Ok(())
+}
+
+/// Check that brew runs and that the settings file and log can be used,
+/// printing one line per check. Failing any check but the settings one is an error.
+pub fn doctor_command(
+    context: &AppContext,
+    cli: &Cli,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let config_path = get_config_path(context, &cli.config, cli.config_format)?;
+    let config_dir = config_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let log_path = context.log_path()?;
+
+    // (name, hard, result): a failed hard check makes the command fail
+    let checks = [
+        ("brew installed", true, check_brew_installed(executor)),
+        (
+            "config directory writable",
+            true,
+            check_dir_writable(&config_dir),
+        ),
+        (
+            "settings file parses",
+            false,
+            check_settings_file(&config_path),
+        ),
+        ("log file writable", true, check_log_writable(&log_path)),
+    ];
+
+    let mut hard_failures = 0;
+    for (name, hard, result) in &checks {
+        match result {
+            Ok(detail) => out.result(&format!("✅ {}: {}", name, detail)),
+            Err(e) => {
+                out.result(&format!("❌ {}: {}", name, e));
+                if *hard {
+                    hard_failures += 1;
+                }
+            }
+        }
+    }
+
+    if hard_failures > 0 {
+        anyhow::bail!("doctor found {} problem(s)", hard_failures);
+    }
+    Ok(())
+}
+
+fn check_brew_installed(executor: &dyn BrewExecutor) -> Result<String> {
+    executor.verify_installation()?;
+    executor.get_version()
+}
+
+/// Create `dir` if needed and prove a file can be written in it.
+fn check_dir_writable(dir: &Path) -> Result<String> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(format!(".brew-update-helper-doctor-{}", std::process::id()));
+    std::fs::write(&probe, "")?;
+    std::fs::remove_file(&probe)?;
+    Ok(dir.display().to_string())
+}
+
+fn check_settings_file(path: &Path) -> Result<String> {
+    if !path.exists() {
+        anyhow::bail!("{} not found, run 'dump' to create it", path.display());
+    }
+    let content = std::fs::read_to_string(path)?;
+    let entries = match ConfigFormat::from_path(path) {
+        ConfigFormat::Toml => check_settings_toml(&content)?,
+        ConfigFormat::Markdown => check_settings_content(&content)?,
+    };
+    Ok(format!("{} packages in {}", entries, path.display()))
+}
+
+fn check_log_writable(path: &Path) -> Result<String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    Ok(path.display().to_string())
+}
+
+/// Smoke-test dump and a dry-run upgrade end-to-end against a scratch settings
+/// file. Intended to be run with the mock executor.
+pub fn selftest_command(
+    context: &AppContext,
+    executor: &(dyn BrewExecutor + Sync),
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let scratch_dir = std::env::temp_dir().join(format!(
+        "brew-update-helper-selftest-{}",
+        std::process::id()
+    ));
+    let config_path = scratch_dir.join("settings.md");
+    let scratch_cli = |dry_run| Cli {
+        dry_run,
+        config: Some(config_path.to_string_lossy().to_string()),
+        ..Cli::with_command(Commands::Selftest)
+    };
+    let cli = scratch_cli(false);
+    let dry_run_cli = scratch_cli(true);
+
+    let steps: [(&str, &dyn Fn() -> Result<()>); 3] = [
+        ("dump settings", &|| {
+            dump_command(
+                context,
+                &cli,
+                &DumpArgs::default(),
+                executor,
+                &CapturingSink::new(),
+            )?;
+            let content = std::fs::read_to_string(&config_path)?;
+            if !content.contains("## Formulae") || !content.contains("## Casks") {
+                anyhow::bail!("settings file is missing package sections");
+            }
+            Ok(())
+        }),
+        ("parse settings", &|| {
+            if read_existing_settings(&config_path)?.is_empty() {
+                anyhow::bail!("no packages parsed from settings file");
+            }
+            Ok(())
+        }),
+        ("upgrade (dry run)", &|| {
+            // The same pipeline `upgrade --dry-run --yes` runs
+            let args = UpgradeArgs {
+                yes: true,
+                ..Default::default()
+            };
+            let sink = CapturingSink::new();
+            run_upgrade(context, &dry_run_cli, &args, executor, &sink)?;
+            if !sink.contains(OutputLevel::Result, "Dry run completed")
+                && !sink.contains(OutputLevel::Result, "All enabled packages are up to date!")
+            {
+                anyhow::bail!("dry run did not complete");
+            }
+            Ok(())
+        }),
+    ];
+
+    let mut failures = 0;
+    for (name, step) in steps {
+        match step() {
+            Ok(()) => out.result(&format!("PASS {}", name)),
+            Err(e) => {
+                out.error(&format!("FAIL {}: {}", name, e));
+                failures += 1;
+            }
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&scratch_dir);
+
+    if failures > 0 {
+        anyhow::bail!("Self-test failed: {} step(s) failed", failures);
+    }
+    out.result("Self-test passed");
+
+    Ok(())
+}
+
+/// Everything `upgrade` learns from brew before offering packages.
+struct UpgradeCandidates {
+    /// What brew reported as outdated; just the enabled packages unless `full_scan`
+    outdated: Vec<OutdatedPackage>,
+    full_scan: bool,
+    running_services: Vec<String>,
+    pinned_formulae: Vec<String>,
+    auto_updating_casks: Vec<String>,
+    /// The packages to offer, in selection order
+    upgradeable: Vec<OutdatedPackage>,
+    explanations: Vec<String>,
+}
+
+impl UpgradeCandidates {
+    /// The filter `upgradeable` was narrowed down with.
+    fn filter<'a>(
+        &'a self,
+        settings: &'a HashMap<String, bool>,
+        enabled_packages: &'a [String],
+        args: &'a UpgradeArgs,
+    ) -> CandidateFilter<'a> {
+        CandidateFilter {
+            running_services: &self.running_services,
+            pinned_formulae: &self.pinned_formulae,
+            auto_updating_casks: if args.skip_auto_updates {
+                &self.auto_updating_casks
+            } else {
+                &[]
+            },
+            allow_downgrade: args.allow_downgrade,
+            only_type: args.only_type(),
+            excluded: &args.exclude,
+            min_bump: args.min_bump,
+            ..CandidateFilter::new(settings, enabled_packages)
+        }
+    }
+}
+
+/// brew's full outdated list, from the cache next to the settings file when
+/// `--cache-ttl` allows it.
+fn scan_outdated(
+    executor: &dyn BrewExecutor,
+    args: &UpgradeArgs,
+    config_path: Option<&Path>,
+    out: &dyn OutputSink,
+) -> Result<Vec<OutdatedPackage>> {
+    // The cache holds brew's full outdated list, so it stays valid whatever is enabled
+    let (Some(ttl), Some(config_path)) = (args.cache_ttl(), config_path) else {
+        return executor.get_outdated_packages();
+    };
+    let cache_path = outdated_cache_path(config_path);
+    if let Some(packages) = read_outdated_cache(&cache_path, ttl, Utc::now()) {
+        out.info("Using cached outdated packages (--no-cache to refresh)");
+        return Ok(packages);
+    }
+    let packages = executor.get_outdated_packages()?;
+    write_outdated_cache(&cache_path, &packages, Utc::now())?;
+    Ok(packages)
+}
+
+/// Ask brew what's outdated and narrow it down to what `upgrade` offers.
+/// Without a settings file to split into sections, brew's full list is used.
+fn find_candidates(
+    executor: &dyn BrewExecutor,
+    settings: &HashMap<String, bool>,
+    enabled_packages: &[String],
+    args: &UpgradeArgs,
+    config_path: Option<&Path>,
+    out: &dyn OutputSink,
+) -> Result<UpgradeCandidates> {
+    // --only has no sections to scope by, and --explain and the cache want
+    // brew's full list (which is also what reveals untracked packages);
+    // otherwise only ask brew about the enabled packages
+    let scope_path =
+        config_path.filter(|_| args.only.is_empty() && !args.explain && args.cache_ttl().is_none());
+    let full_scan = scope_path.is_none();
+    let outdated = match scope_path {
+        None => scan_outdated(executor, args, config_path, out)?,
+        Some(path) => {
+            let (scoped_formulae, scoped_casks) = split_by_section(path, enabled_packages)?;
+            // brew rejects the whole query if one name is stale, so scan everything instead
+            executor
+                .get_outdated_packages_for(&scoped_formulae, &scoped_casks)
+                .or_else(|_| scan_outdated(executor, args, config_path, out))?
+        }
+    };
+
+    let running_services = if args.skip_running_services {
+        executor.get_running_services()?
+    } else {
+        Vec::new()
+    };
+
+    let pinned_formulae = executor.get_pinned_formulae()?;
+
+    // Only worth a `brew info` call when an enabled cask is actually outdated
+    let auto_updating_casks = if outdated.iter().any(|pkg| {
+        matches!(pkg.package_type, PackageType::Cask) && lists_package(enabled_packages, &pkg.name)
+    }) {
+        // Only an annotation, so a failed lookup shouldn't stop the upgrade
+        executor.get_auto_updating_casks().unwrap_or_else(|e| {
+            out.warn(&format!(
+                "Couldn't check which casks update themselves, so none are marked: {}",
+                e
+            ));
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+
+    let mut candidates = UpgradeCandidates {
+        outdated,
+        full_scan,
+        running_services,
+        pinned_formulae,
+        auto_updating_casks,
+        upgradeable: Vec::new(),
+        explanations: Vec::new(),
+    };
+    let filter = candidates.filter(settings, enabled_packages, args);
+    let mut explanations = Vec::new();
+    let mut upgradeable = filter.apply(&candidates.outdated, &mut explanations);
+    sort_for_selection(&mut upgradeable, args.sort);
+    let upgradeable: Vec<OutdatedPackage> = upgradeable.into_iter().cloned().collect();
+    candidates.upgradeable = upgradeable;
+    candidates.explanations = explanations;
+    Ok(candidates)
+}
+
+/// The rules deciding which outdated packages are offered for upgrade.
+struct CandidateFilter<'a> {
+    settings: &'a HashMap<String, bool>,
+    enabled_packages: &'a [String],
+    /// Formulae whose `brew services` entry is running; empty unless skipping them
+    running_services: &'a [String],
+    /// Formulae held back with `brew pin`
+    pinned_formulae: &'a [String],
+    /// Self-updating casks; empty unless skipping them
+    auto_updating_casks: &'a [String],
+    /// Let through entries whose available version isn't newer
+    allow_downgrade: bool,
+    /// Restrict upgrades to formulae or casks
+    only_type: Option<PackageType>,
+    /// Packages skipped for this run with `--exclude`
+    excluded: &'a [String],
+    /// Smallest version jump worth offering
+    min_bump: Option<MinBump>,
+}
+
+impl<'a> CandidateFilter<'a> {
+    fn new(settings: &'a HashMap<String, bool>, enabled_packages: &'a [String]) -> Self {
+        Self {
+            settings,
+            enabled_packages,
+            running_services: &[],
+            pinned_formulae: &[],
+            auto_updating_casks: &[],
+            allow_downgrade: false,
+            only_type: None,
+            excluded: &[],
+            min_bump: None,
+        }
+    }
+
+    /// The first check an enabled, outdated package fails, as
+    /// `(condition, reason it was skipped)`.
+    fn failed_check(&self, pkg: &OutdatedPackage) -> Option<(&'static str, &'static str)> {
+        match self.only_type {
+            Some(PackageType::Formula) if pkg.package_type != PackageType::Formula => {
+                return Some(("formula", "--formula-only"));
+            }
+            Some(PackageType::Cask) if pkg.package_type != PackageType::Cask => {
+                return Some(("cask", "--cask-only"));
+            }
+            _ => {}
+        }
+        if lists_package(self.excluded, &pkg.name) {
+            return Some(("not excluded", "--exclude"));
+        }
+        if self.is_pinned(pkg) {
+            return Some(("unpinned", "pinned with brew pin"));
+        }
+        if self.is_running_service(pkg) {
+            return Some(("service stopped", "service is running"));
+        }
+        if self.updates_itself(pkg) {
+            return Some(("not self-updating", "cask updates itself"));
+        }
+        if !self.allow_downgrade && pkg.is_downgrade() {
+            return Some(("newer", "available version isn't newer"));
+        }
+        if let Some(min_bump) = self.min_bump {
+            if !min_bump.allows(version_jump_kind(
+                &pkg.current_version,
+                &pkg.available_version,
+            )) {
+                return Some(("big enough bump", "below --min-bump"));
+            }
+        }
+        None
+    }
+
+    fn is_pinned(&self, pkg: &OutdatedPackage) -> bool {
+        matches!(pkg.package_type, PackageType::Formula)
+            && lists_package(self.pinned_formulae, &pkg.name)
+    }
+
+    fn updates_itself(&self, pkg: &OutdatedPackage) -> bool {
+        matches!(pkg.package_type, PackageType::Cask)
+            && lists_package(self.auto_updating_casks, &pkg.name)
+    }
+
+    fn is_running_service(&self, pkg: &OutdatedPackage) -> bool {
+        matches!(pkg.package_type, PackageType::Formula)
+            && lists_package(self.running_services, &pkg.name)
+    }
+
+    /// Narrow outdated packages down to the upgradeable ones, recording why
+    /// each candidate was kept or dropped.
+    fn apply<'p>(
+        &self,
+        outdated_packages: &'p [OutdatedPackage],
+        explanations: &mut Vec<String>,
+    ) -> Vec<&'p OutdatedPackage> {
+        let mut candidates: Vec<&String> = self.settings.keys().collect();
+        candidates.sort();
+
+        for name in candidates {
+            let outdated = outdated_packages
+                .iter()
+                .find(|pkg| same_package(&pkg.name, name));
+            let explanation = if !self.settings[name] {
+                format!("{}: enabled ✗ → skipped (disabled in settings)", name)
+            } else if !self.enabled_packages.contains(name) {
+                format!("{}: enabled ✓, installed ✗ → skipped (not installed)", name)
+            } else if let Some(pkg) = outdated {
+                match self.failed_check(pkg) {
+                    Some((condition, reason)) => format!(
+                        "{}: enabled ✓, outdated ✓, {} ✗ → skipped ({})",
+                        name, condition, reason
+                    ),
+                    None => format!("{}: enabled ✓, outdated ✓ → will upgrade", name),
+                }
+            } else {
+                format!("{}: enabled ✓, outdated ✗ → skipped (up to date)", name)
+            };
+            explanations.push(explanation);
+        }
+
+        for pkg in outdated_packages {
+            if !self
+                .settings
+                .keys()
+                .any(|name| same_package(name, &pkg.name))
+            {
+                explanations.push(format!(
+                    "{}: in settings ✗ → skipped (not tracked, run 'dump' to add it)",
+                    pkg.name
+                ));
+            }
+        }
+
+        outdated_packages
+            .iter()
+            .filter(|pkg| lists_package(self.enabled_packages, &pkg.name))
+            .filter(|pkg| self.failed_check(pkg).is_none())
+            .collect()
+    }
+}
+
+/// Split packages into formulae and casks by the settings section listing them.
+fn split_by_section(config_path: &Path, packages: &[String]) -> Result<(Vec<String>, Vec<String>)> {
+    let (formulae, casks) = read_previous_packages(&config_path.to_path_buf())?;
+    let keep = |section: Vec<String>| -> Vec<String> {
+        section
+            .into_iter()
+            .filter(|name| packages.contains(name))
+            .collect()
+    };
+    Ok((keep(formulae), keep(casks)))
+}
+
+/// Pair each package with its homepage, skipping any brew has no URL for.
+pub fn collect_homepages(
+    packages: &[OutdatedPackage],
+    executor: &dyn BrewExecutor,
+) -> Vec<(String, String)> {
+    packages
+        .iter()
+        .filter_map(|pkg| {
+            let info = executor
+                .get_package_info(&pkg.name, &pkg.package_type)
+                .ok()?;
+            Some((pkg.name.clone(), info.homepage?))
+        })
+        .collect()
+}
+
+fn open_url(url: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(opener).arg(url).spawn()?;
+    Ok(())
+}
+
+/// Keep the packages that are installed at all, including ones brew pulled in
+/// as a dependency, since those can be enabled in settings too.
+fn retain_installed(packages: &mut Vec<String>, executor: &dyn BrewExecutor) -> Result<()> {
+    let installed: Vec<String> = executor
+        .get_installed_versions()?
+        .into_iter()
+        .map(|(name, _, _)| name)
+        .collect();
+
+    packages.retain(|pkg| lists_package(&installed, pkg));
+
+    Ok(())
+}
+
+/// How `execute_upgrades` should run the selected packages.
+#[derive(Debug, Default)]
+struct ExecuteOptions {
+    dry_run: bool,
+    /// Upgrade in chunks of this many packages, summarising after each
+    batch_size: Option<usize>,
+    /// Ask before starting each chunk after the first
+    confirm_batches: bool,
+    /// Ask once more before upgrading anything across a major version
+    confirm_major: bool,
+    /// Let brew write to the terminal instead of capturing its output
+    stream_output: bool,
+    /// Extra attempts for a failed upgrade
+    retries: u32,
+    /// Wait before the first retry, doubled for each one after (up to `RETRY_MAX_DELAY`)
+    retry_delay: Duration,
+    /// Results are printed as JSON afterwards, so keep the human summary off stdout
+    json_results: bool,
+    /// In a dry run, list the outdated dependencies each formula would bring along
+    show_deps: bool,
+    /// Draw an `N/total` progress bar on stderr while upgrading
+    progress: bool,
+    /// Extra `brew upgrade` arguments per package, from the settings' `{args: ...}`
+    package_args: HashMap<String, Vec<String>>,
+    /// Download every package concurrently before the serial upgrades
+    parallel_fetch: bool,
+}
+
+/// How many `brew fetch` calls `--parallel-fetch` runs at once.
+const FETCH_CONCURRENCY: usize = 4;
+
+/// Download `packages` into brew's cache a few at a time. A failed fetch only
+/// warns: the upgrade itself will try the download again.
+fn prefetch_packages(
+    packages: &[OutdatedPackage],
+    executor: &(dyn BrewExecutor + Sync),
+    out: &dyn OutputSink,
+) {
+    out.info(&format!("Fetching {} packages...", packages.len()));
+    for chunk in packages.chunks(FETCH_CONCURRENCY) {
+        let results: Vec<Result<()>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|pkg| scope.spawn(move || executor.fetch_package(pkg)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("fetch thread panicked")))
+                })
+                .collect()
+        });
+        for (pkg, result) in chunk.iter().zip(results) {
+            if let Err(e) = result {
+                out.warn(&format!("Couldn't prefetch {}: {}", pkg.name, e));
+            }
+        }
+    }
+}
+
+/// First pause before retrying a failed upgrade.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+
+/// Longest pause between retries, however many are allowed.
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// The pause before the retry after one that waited `delay`.
+fn next_retry_delay(delay: Duration) -> Duration {
+    delay.saturating_mul(2).min(RETRY_MAX_DELAY)
+}
+
+/// Elapsed time to a tenth of a second, e.g. `12.4s`.
+fn format_seconds(elapsed: Duration) -> String {
+    format!("{:.1}s", elapsed.as_secs_f64())
+}
+
+/// Run one upgrade, retrying failures with backoff as `options` allows.
+fn upgrade_with_retries(
+    context: &AppContext,
+    pkg: &OutdatedPackage,
+    options: &ExecuteOptions,
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<String> {
+    let mut attempt = 0;
+    let mut delay = options.retry_delay;
+    loop {
+        let extra_args = options
+            .package_args
+            .get(short_name(&pkg.name))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        match executor.upgrade_package(pkg, extra_args, options.stream_output) {
+            Err(e) if attempt < options.retries => {
+                attempt += 1;
+                out.info(&format!(
+                    "    ↻ Upgrade of {} failed, retrying ({}/{})...",
+                    pkg.name, attempt, options.retries
+                ));
+                context.log(
+                    LogLevel::Warn,
+                    &format!(
+                        "RETRY: {} attempt {}/{} - {}",
+                        pkg.name, attempt, options.retries, e
+                    ),
+                )?;
+                std::thread::sleep(delay);
+                delay = next_retry_delay(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Show which outdated dependencies brew would upgrade along with each selected
+/// formula, whether or not they're enabled in the settings.
+fn preview_dependency_upgrades(
+    packages: &[OutdatedPackage],
+    executor: &dyn BrewExecutor,
+    out: &dyn OutputSink,
+) -> Result<()> {
+    let formulae: Vec<&OutdatedPackage> = packages
+        .iter()
+        .filter(|pkg| pkg.package_type == PackageType::Formula)
+        .collect();
+    if formulae.is_empty() {
+        return Ok(());
+    }
+
+    let mut deps_map = HashMap::new();
+    for pkg in &formulae {
+        deps_map.insert(pkg.name.clone(), executor.get_deps(&pkg.name)?);
+    }
+    let mut all_deps: Vec<String> = deps_map.values().flatten().cloned().collect();
+    all_deps.sort();
+    all_deps.dedup();
+    let outdated_deps = executor.get_outdated_packages_for(&all_deps, &[])?;
+
+    out.info("\nDependencies brew would upgrade along the way:");
+    for pkg in &formulae {
+        let pulled: Vec<String> = outdated_deps
+            .iter()
+            .filter(|dep| deps_map[&pkg.name].contains(&dep.name))
+            .map(|dep| {
+                format!(
+                    "{} ({} → {})",
+                    dep.name, dep.current_version, dep.available_version
+                )
+            })
+            .collect();
+        if pulled.is_empty() {
+            out.info(&format!("  {}: no outdated dependencies", pkg.name));
+        } else {
+            out.info(&format!("  {}: {}", pkg.name, pulled.join(", ")));
+        }
+    }
+
+    Ok(())
+}
+
+fn execute_upgrades(
+    context: &AppContext,
+    packages: &[OutdatedPackage],
+    options: &ExecuteOptions,
+    executor: &(dyn BrewExecutor + Sync),
+    out: &dyn OutputSink,
+) -> Result<UpgradeSummary> {
+    let started = Instant::now();
+    let dry_run = options.dry_run;
+
+    // Major bumps are the likeliest to break something, so single them out
+    let is_major = |pkg: &OutdatedPackage| {
+        version_jump_kind(&pkg.current_version, &pkg.available_version) == VersionJump::Major
+    };
+    let major_count = packages.iter().filter(|pkg| is_major(pkg)).count();
+    for pkg in packages.iter().filter(|pkg| is_major(pkg)) {
+        out.warn(&format!(
+            "⚠ major upgrade: {} {} → {}",
+            pkg.name, pkg.current_version, pkg.available_version
+        ));
+    }
+    let mut results = Vec::new();
+    let without_major: Vec<OutdatedPackage>;
+    let packages = if major_count > 0
+        && !dry_run
+        && options.confirm_major
+        && !confirm(&format!(
+            "Go ahead with {} major upgrade(s)? Answering n upgrades the rest only (y/n): ",
+            major_count
+        ))? {
+        for pkg in packages.iter().filter(|pkg| is_major(pkg)) {
+            out.info(&format!("Skipping {}: major upgrade declined", pkg.name));
+            results.push(PackageResult::skipped(pkg, "major upgrade declined"));
+        }
+        without_major = packages
+            .iter()
+            .filter(|pkg| !is_major(pkg))
+            .cloned()
+            .collect();
+        &without_major[..]
+    } else {
+        packages
+    };
+
+    let bar = if options.progress && !dry_run {
+        let bar = ProgressBar::new(packages.len() as u64);
+        bar.set_style(
+            ProgressStyle::with_template("{bar:30} {pos}/{len} {msg}")
+                .expect("progress template is valid"),
+        );
+        bar
+    } else {
+        ProgressBar::hidden()
+    };
+    let progress_sink = ProgressSink::new(&bar, out);
+    let out: &dyn OutputSink = &progress_sink;
+
+    out.info(&format!(
+        "\n{} upgrade for {} packages:",
+        if dry_run {
+            "Would execute"
+        } else {
+            "Executing"
+        },
+        packages.len()
+    ));
+
+    if dry_run && options.show_deps {
+        preview_dependency_upgrades(packages, executor, out)?;
+    }
+
+    if !dry_run {
+        context.log(
+            LogLevel::Info,
+            &format!("Starting upgrade of {} packages", packages.len()),
+        )?;
+        if options.parallel_fetch {
+            prefetch_packages(packages, executor, out);
+        }
+    }
+
+    let mut successful_upgrades = 0;
+    let mut failed_upgrades = 0;
+    let mut processed = 0;
+    let mut manual_steps: Vec<(String, String)> = Vec::new();
+    let mut slowest: Option<(String, Duration)> = None;
+
+    let batch_size = options
+        .batch_size
+        .filter(|&size| size > 0)
+        .unwrap_or(packages.len())
+        .max(1);
+    let batch_count = packages.len().div_ceil(batch_size);
+    let batching = batch_count > 1;
+
+    for (batch_index, batch) in packages.chunks(batch_size).enumerate() {
+        if batching {
+            if batch_index > 0
+                && options.confirm_batches
+                && !bar.suspend(|| {
+                    confirm(&format!(
+                        "Continue with batch {}/{}? (y/n): ",
+                        batch_index + 1,
+                        batch_count
+                    ))
+                })?
+            {
+                out.info(&format!(
+                    "Stopping; {} package(s) left un-upgraded.",
+                    packages.len() - processed
+                ));
+                break;
+            }
+            out.info(&format!("\nBatch {}/{}:", batch_index + 1, batch_count));
+        }
+
+        for pkg in batch {
+            out.info(&format!(
+                "  {} {} {} → {}{}",
+                if dry_run {
+                    "Would upgrade"
+                } else {
+                    "Upgrading"
+                },
+                pkg.name,
+                pkg.current_version,
+                pkg.available_version,
+                options
+                    .package_args
+                    .get(short_name(&pkg.name))
+                    .map(|args| format!(" (with {})", args.join(" ")))
+                    .unwrap_or_default()
+            ));
+
+            if !dry_run {
+                bar.set_message(pkg.name.clone());
+                let upgrade_started = Instant::now();
+                let upgrade = upgrade_with_retries(context, pkg, options, executor, out);
+                let elapsed = upgrade_started.elapsed();
+                if slowest
+                    .as_ref()
+                    .is_none_or(|(_, longest)| elapsed > *longest)
+                {
+                    slowest = Some((pkg.name.clone(), elapsed));
+                }
+                bar.inc(1);
+                match upgrade {
+                    Ok(output) => {
+                        out.info(&format!("    ✅ Successfully upgraded {}", pkg.name));
+                        manual_steps.extend(
+                            extract_manual_steps(&output)
+                                .into_iter()
+                                .map(|step| (pkg.name.clone(), step)),
+                        );
+                        context.log(
+                            LogLevel::Info,
+                            &format!(
+                                "SUCCESS: {} {} → {} ({})",
+                                pkg.name,
+                                pkg.current_version,
+                                pkg.available_version,
+                                format_seconds(elapsed)
+                            ),
+                        )?;
+                        successful_upgrades += 1;
+                        results.push(PackageResult::new(pkg, UpgradeStatus::Upgraded));
+                    }
+                    Err(e) => {
+                        out.error(&format!("    ❌ Failed to upgrade {}: {}", pkg.name, e));
+                        context.log(
+                            LogLevel::Error,
+                            &format!(
+                                "FAILED: {} {} → {} ({}) - {}",
+                                pkg.name,
+                                pkg.current_version,
+                                pkg.available_version,
+                                format_seconds(elapsed),
+                                e
+                            ),
+                        )?;
+                        failed_upgrades += 1;
+                        results.push(PackageResult::new(pkg, UpgradeStatus::Failed));
+                    }
+                }
+            } else {
+                results.push(PackageResult::new(pkg, UpgradeStatus::DryRun));
+            }
+            processed += 1;
+        }
+
+        if batching {
+            out.info(&format!(
+                "  Batch {}/{} done: {}/{} processed, {} successful, {} failed so far",
+                batch_index + 1,
+                batch_count,
+                processed,
+                packages.len(),
+                successful_upgrades,
+                failed_upgrades
+            ));
+        }
+    }
+
+    bar.finish_and_clear();
+
+    if !manual_steps.is_empty() {
+        out.info("\nManual steps required:");
+        for (name, step) in &manual_steps {
+            out.info(&format!("  {}: {}", name, step));
+        }
+    }
+
+    let report = |message: &str| {
+        if options.json_results {
+            out.info(message);
+        } else {
+            out.result(message);
+        }
+    };
+    if dry_run {
+        report("\nDry run completed. Use without --dry-run to execute upgrades.");
+    } else {
+        report(&format!(
+            "\nUpgrade completed! {} successful, {} failed",
+            successful_upgrades, failed_upgrades
+        ));
+        if let Some((name, elapsed)) = &slowest {
+            report(&format!("Slowest: {} ({})", name, format_seconds(*elapsed)));
+        }
+        context.log(
+            LogLevel::Info,
+            &format!(
+                "Upgrade session completed: {} successful, {} failed",
+                successful_upgrades, failed_upgrades
+            ),
+        )?;
+    }
+
+    Ok(UpgradeSummary {
+        selected: packages.len(),
+        successful: successful_upgrades,
+        failed: failed_upgrades,
+        dry_run,
+        upgrade_duration_ms: started.elapsed().as_millis(),
+        results,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brew::MockBrewExecutor;
+    use tempfile::TempDir;
+
+    /// Keeps the upgrade log (and anything else the tool writes) inside `dir`.
+    fn test_context(dir: &Path) -> AppContext {
+        AppContext {
+            config_dir: Some(dir.to_path_buf()),
+            ..Default::default()
+        }
+    }
+
+    fn test_cli(command: Commands, config_path: &Path) -> Cli {
+        Cli {
+            config: Some(config_path.to_string_lossy().to_string()),
+            ..Cli::with_command(command)
+        }
+    }
+
+    #[test]
+    fn test_most_upgraded() {
+        let session = |upgraded: &[&str]| Session {
+            started: String::new(),
+            planned: upgraded.len(),
+            upgraded: upgraded.iter().map(|name| name.to_string()).collect(),
+            failed: 0,
+            completed: true,
+        };
+        let sessions = vec![
+            session(&["git", "node"]),
+            session(&["node", "docker"]),
+            session(&["node", "git"]),
+        ];
+
+        assert_eq!(
+            most_upgraded(&sessions),
+            vec![
+                ("node".to_string(), 3),
+                ("git".to_string(), 2),
+                ("docker".to_string(), 1),
+            ]
+        );
+        assert!(most_upgraded(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_editor_command() {
+        assert_eq!(editor_command(None), ("vi".to_string(), vec![]));
+        assert_eq!(editor_command(Some("  ")), ("vi".to_string(), vec![]));
+        assert_eq!(
+            editor_command(Some("code --wait")),
+            ("code".to_string(), vec!["--wait".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_dump_command_with_mock() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+
+        let executor = MockBrewExecutor::new();
+        let cli = test_cli(Commands::Dump(DumpArgs::default()), &config_path);
+
+        dump_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &DumpArgs::default(),
+            &executor,
+            &CapturingSink::new(),
+        )?;
+
+        assert!(config_path.exists());
+        let content = std::fs::read_to_string(&config_path)?;
+        assert!(content.contains("# Brew Auto-Update Settings"));
+        assert!(content.contains("git"));
+        assert!(content.contains("docker"));
+        assert!(content.contains("- [x] git  <!-- outdated: 2.40.0 → 2.41.0 -->"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_command_backup() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let context = test_context(temp_dir.path());
+        let config_path = temp_dir.path().join("settings.md");
+        let curated = "# Brew Auto-Update Settings\n\n## Formulae\n\n- [ ] git\n  > hold for now\n";
+        std::fs::write(&config_path, curated)?;
+
+        let executor = MockBrewExecutor::new();
+        let args = DumpArgs {
+            backup: true,
+            ..Default::default()
+        };
+        let mut cli = Cli {
+            dry_run: true,
+            ..test_cli(Commands::Dump(DumpArgs::default()), &config_path)
+        };
+        let backup_path = temp_dir.path().join("settings.md.bak");
+
+        // A dry run leaves everything alone
+        dump_command(&context, &cli, &args, &executor, &CapturingSink::new())?;
+        assert!(!backup_path.exists());
+
+        cli.dry_run = false;
+        dump_command(&context, &cli, &args, &executor, &CapturingSink::new())?;
+        assert_eq!(std::fs::read_to_string(&backup_path)?, curated);
+        assert_ne!(std::fs::read_to_string(&config_path)?, curated);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_command_includes_stats() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+
+        let executor = MockBrewExecutor::new();
+        let cli = test_cli(Commands::Dump(DumpArgs::default()), &config_path);
+
+        dump_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &DumpArgs::default(),
+            &executor,
+            &CapturingSink::new(),
+        )?;
+
+        assert!(config_path.exists());
+        let content = std::fs::read_to_string(&config_path)?;
+
+        // Check that stats section is included
+        assert!(content.contains("## Statistics"));
+        assert!(content.contains("**Total Packages**"));
+        assert!(content.contains("**Homebrew Version**"));
+        assert!(content.contains("**System**"));
+        assert!(content.contains("**Outdated Packages**"));
+
+        // Check that package sections are still present
+        assert!(content.contains("## Formulae"));
+        assert!(content.contains("## Casks"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_command_also_json() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        let json_path = temp_dir.path().join("settings.json");
+
+        let executor = MockBrewExecutor::new();
+        let args = DumpArgs {
+            also_json: Some(json_path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let cli = test_cli(Commands::Dump(DumpArgs::default()), &config_path);
+
+        std::fs::write(&config_path, "## Formulae\n\n- [ ] node\n")?;
+        dump_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &args,
+            &executor,
+            &CapturingSink::new(),
+        )?;
+
+        assert!(config_path.exists());
+        assert!(json_path.exists());
+
+        let markdown_settings = read_existing_settings(&config_path)?;
+        let document: crate::config::SettingsDocument =
+            serde_json::from_str(&std::fs::read_to_string(&json_path)?)?;
+
+        let mut json_settings = document.formulae;
+        json_settings.extend(document.casks);
+        assert_eq!(json_settings.len(), markdown_settings.len());
+        for (package, enabled) in &json_settings {
+            assert_eq!(markdown_settings.get(package), Some(enabled));
+        }
+        assert_eq!(json_settings.get("node"), Some(&false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_candidate_filter_explains_up_to_date_package() -> Result<()> {
+        let executor = MockBrewExecutor::new();
+        let outdated = executor.get_outdated_packages()?;
+
+        let mut settings = HashMap::new();
+        settings.insert("git".to_string(), true);
+        settings.insert("node".to_string(), true);
+        settings.insert("docker".to_string(), false);
+        let enabled = vec!["git".to_string(), "node".to_string()];
+
+        let mut explanations = Vec::new();
+        let upgradeable =
+            CandidateFilter::new(&settings, &enabled).apply(&outdated, &mut explanations);
+
+        assert_eq!(upgradeable.len(), 1);
+        assert_eq!(upgradeable[0].name, "git");
+        assert!(explanations
+            .contains(&"node: enabled ✓, outdated ✗ → skipped (up to date)".to_string()));
+        assert!(explanations.contains(&"git: enabled ✓, outdated ✓ → will upgrade".to_string()));
+        assert!(explanations
+            .contains(&"docker: enabled ✗ → skipped (disabled in settings)".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compute_dump_with_mock() -> Result<()> {
+        let result = compute_dump(&MockBrewExecutor::new())?;
+        assert_eq!(result.formulae, vec!["git", "node", "python"]);
+        assert_eq!(
+            result.casks,
+            vec!["visual-studio-code", "docker", "firefox"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compute_upgradeable_with_mock() -> Result<()> {
+        let executor = MockBrewExecutor::new();
+        let settings = HashMap::from([
+            ("git".to_string(), true),
+            ("node".to_string(), true),
+            ("docker".to_string(), false),
+        ]);
+
+        // docker is outdated too, but disabled
+        let names: Vec<String> = compute_upgradeable(&executor, &settings)?
+            .into_iter()
+            .map(|pkg| pkg.name)
+            .collect();
+        assert_eq!(names, vec!["git"]);
+
+        let executor = executor.with_pinned_formulae(vec!["git".to_string()]);
+        assert!(compute_upgradeable(&executor, &settings)?.is_empty());
+        assert!(compute_upgradeable(&executor, &HashMap::new())?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_command_refuses_while_brew_is_busy() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let context = test_context(temp_dir.path());
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(&config_path, "## Formulae\n\n- [x] git\n")?;
+        let executor = MockBrewExecutor::new().with_busy_brew();
+        let mut cli = test_cli(Commands::Upgrade(UpgradeArgs::default()), &config_path);
+        let args = UpgradeArgs {
+            yes: true,
+            ..Default::default()
+        };
+
+        let error =
+            upgrade_command(&context, &cli, &args, &executor, &CapturingSink::new()).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Another Homebrew process is running"));
+
+        // Listing the outdated packages only reads, so it can go ahead
+        let list_args = UpgradeArgs {
+            list_outdated: true,
+            ..Default::default()
+        };
+        upgrade_command(&context, &cli, &list_args, &executor, &CapturingSink::new())?;
+
+        // A dry run doesn't touch anything, so it can go ahead
+        cli.dry_run = true;
+        upgrade_command(&context, &cli, &args, &executor, &CapturingSink::new())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_warn_if_settings_old() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(
+            &config_path,
+            "Generated on: 2024-08-01 10:30:00 UTC\n\n## Formulae\n\n- [x] git\n",
+        )?;
+        let now = DateTime::parse_from_rfc3339("2024-09-15T10:30:00Z")?.with_timezone(&Utc);
+
+        let sink = CapturingSink::new();
+        let warnings = Warnings::new(&sink, &[]);
+        warn_if_settings_old(&config_path, 30, now, &warnings)?;
+        assert!(sink.contains(OutputLevel::Warn, "generated 45 days ago"));
+
+        let sink = CapturingSink::new();
+        let warnings = Warnings::new(&sink, &[]);
+        warn_if_settings_old(&config_path, 60, now, &warnings)?;
+        warn_if_settings_old(&config_path, 0, now, &warnings)?;
+        assert!(sink.messages().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_seconds() {
+        assert_eq!(format_seconds(Duration::from_millis(12_449)), "12.4s");
+        assert_eq!(format_seconds(Duration::ZERO), "0.0s");
+    }
+
+    #[test]
+    fn test_candidate_filter_matches_tap_qualified_names() {
+        let outdated = vec![OutdatedPackage {
+            name: "homebrew/core/git".to_string(),
+            current_version: "2.40.0".to_string(),
+            available_version: "2.41.0".to_string(),
+            package_type: PackageType::Formula,
+        }];
+        let settings = HashMap::from([("git".to_string(), true)]);
+        let enabled = vec!["git".to_string()];
+
+        let mut explanations = Vec::new();
+        let filter = CandidateFilter::new(&settings, &enabled);
+        let kept = filter.apply(&outdated, &mut explanations);
+        // The full name is kept, so brew upgrades the right tap's formula
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].name, "homebrew/core/git");
+        assert_eq!(
+            explanations,
+            vec!["git: enabled ✓, outdated ✓ → will upgrade"]
+        );
+
+        let pinned = vec!["git".to_string()];
+        let filter = CandidateFilter {
+            pinned_formulae: &pinned,
+            ..CandidateFilter::new(&settings, &enabled)
+        };
+        assert!(filter.apply(&outdated, &mut Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_candidate_filter_min_bump() {
+        let package = |name: &str, current: &str, available: &str| OutdatedPackage {
+            name: name.to_string(),
+            current_version: current.to_string(),
+            available_version: available.to_string(),
+            package_type: PackageType::Formula,
+        };
+        let outdated = vec![
+            package("node", "18.16.0", "20.5.0"),
+            package("git", "2.40.0", "2.41.0"),
+            package("wget", "1.21.3", "1.21.4"),
+        ];
+        let settings: HashMap<String, bool> = outdated
+            .iter()
+            .map(|pkg| (pkg.name.clone(), true))
+            .collect();
+        let enabled: Vec<String> = settings.keys().cloned().collect();
+
+        let kept = |min_bump: MinBump| -> Vec<String> {
+            let filter = CandidateFilter {
+                min_bump: Some(min_bump),
+                ..CandidateFilter::new(&settings, &enabled)
+            };
+            let mut names: Vec<String> = filter
+                .apply(&outdated, &mut Vec::new())
+                .iter()
+                .map(|pkg| pkg.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(kept(MinBump::Major), vec!["node"]);
+        assert_eq!(kept(MinBump::Minor), vec!["git", "node"]);
+        assert_eq!(kept(MinBump::Patch), vec!["git", "node", "wget"]);
+
+        let mut explanations = Vec::new();
+        let filter = CandidateFilter {
+            min_bump: Some(MinBump::Major),
+            ..CandidateFilter::new(&settings, &enabled)
+        };
+        filter.apply(&outdated, &mut explanations);
+        assert!(explanations
+            .iter()
+            .any(|line| line.contains("below --min-bump")));
+    }
+
+    #[test]
+    fn test_candidate_filter_skips_running_services() -> Result<()> {
+        let executor = MockBrewExecutor::new().with_outdated_packages(vec![
+            OutdatedPackage {
+                name: "postgresql@14".to_string(),
+                current_version: "14.9".to_string(),
+                available_version: "14.10".to_string(),
+                package_type: PackageType::Formula,
+            },
+            OutdatedPackage {
+                name: "git".to_string(),
+                current_version: "2.40.0".to_string(),
+                available_version: "2.41.0".to_string(),
+                package_type: PackageType::Formula,
+            },
+        ]);
+        let outdated = executor.get_outdated_packages()?;
+        let running = executor.get_running_services()?;
+
+        let mut settings = HashMap::new();
+        settings.insert("git".to_string(), true);
+        settings.insert("postgresql@14".to_string(), true);
+        let enabled = vec!["git".to_string(), "postgresql@14".to_string()];
+
+        let filter = CandidateFilter {
+            running_services: &running,
+            ..CandidateFilter::new(&settings, &enabled)
+        };
+        let mut explanations = Vec::new();
+        let upgradeable = filter.apply(&outdated, &mut explanations);
+
+        assert_eq!(upgradeable.len(), 1);
+        assert_eq!(upgradeable[0].name, "git");
+        assert!(explanations.iter().any(
+            |e| e.starts_with("postgresql@14:") && e.ends_with("skipped (service is running)")
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_homepages_for_selected_packages() -> Result<()> {
+        let executor = MockBrewExecutor::new().with_package_info(
+            "node",
+            crate::brew::PackageInfo {
+                homepage: Some("https://nodejs.org/".to_string()),
+                description: None,
+            },
+        );
+        let mut selected = executor.get_outdated_packages()?;
+        selected.push(OutdatedPackage {
+            name: "node".to_string(),
+            current_version: "20.0.0".to_string(),
+            available_version: "20.1.0".to_string(),
+            package_type: PackageType::Formula,
+        });
+        selected.push(OutdatedPackage {
+            name: "no-homepage".to_string(),
+            current_version: "1.0".to_string(),
+            available_version: "1.1".to_string(),
+            package_type: PackageType::Formula,
+        });
+
+        let homepages = collect_homepages(&selected, &executor);
+
+        assert_eq!(
+            homepages,
+            vec![
+                ("git".to_string(), "https://git-scm.com".to_string()),
+                (
+                    "docker".to_string(),
+                    "https://www.docker.com/products/docker-desktop".to_string()
+                ),
+                ("node".to_string(), "https://nodejs.org/".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_candidate_filter_allow_downgrade() {
+        let outdated = vec![OutdatedPackage {
+            name: "some-app".to_string(),
+            current_version: "2.0.0".to_string(),
+            available_version: "1.9.0".to_string(),
+            package_type: PackageType::Cask,
+        }];
+        let mut settings = HashMap::new();
+        settings.insert("some-app".to_string(), true);
+        let enabled = vec!["some-app".to_string()];
+
+        let mut explanations = Vec::new();
+        let upgradeable =
+            CandidateFilter::new(&settings, &enabled).apply(&outdated, &mut explanations);
+        assert!(upgradeable.is_empty());
+        assert!(explanations[0].ends_with("skipped (available version isn't newer)"));
+
+        let filter = CandidateFilter {
+            allow_downgrade: true,
+            ..CandidateFilter::new(&settings, &enabled)
+        };
+        let upgradeable = filter.apply(&outdated, &mut Vec::new());
+        assert_eq!(upgradeable.len(), 1);
+        assert_eq!(upgradeable[0].name, "some-app");
+    }
+
+    #[test]
+    fn test_candidate_filter_skips_auto_updating_casks() {
+        let executor = MockBrewExecutor::new();
+        let outdated = executor.get_outdated_packages().unwrap();
+        let settings = HashMap::from([("git".to_string(), true), ("docker".to_string(), true)]);
+        let enabled = vec!["git".to_string(), "docker".to_string()];
+        let auto_updating = vec!["docker".to_string()];
+        let filter = CandidateFilter {
+            auto_updating_casks: &auto_updating,
+            ..CandidateFilter::new(&settings, &enabled)
+        };
+
+        let mut explanations = Vec::new();
+        let upgradeable = filter.apply(&outdated, &mut explanations);
+
+        let names: Vec<&str> = upgradeable.iter().map(|pkg| pkg.name.as_str()).collect();
+        assert_eq!(names, vec!["git"]);
+        assert!(explanations.contains(
+            &"docker: enabled ✓, outdated ✓, not self-updating ✗ → skipped (cask updates itself)"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn test_candidate_filter_skips_pinned_formulae() {
+        let executor = MockBrewExecutor::new();
+        let outdated = executor.get_outdated_packages().unwrap();
+        let mut settings = HashMap::new();
+        settings.insert("git".to_string(), true);
+        settings.insert("docker".to_string(), true);
+        let enabled = vec!["git".to_string(), "docker".to_string()];
+        let pinned = vec!["git".to_string(), "docker".to_string()];
+
+        let filter = CandidateFilter {
+            pinned_formulae: &pinned,
+            ..CandidateFilter::new(&settings, &enabled)
+        };
+        let mut explanations = Vec::new();
+        let upgradeable = filter.apply(&outdated, &mut explanations);
+
+        // Only formulae can be pinned, so the docker cask is still offered
+        assert_eq!(upgradeable.len(), 1);
+        assert_eq!(upgradeable[0].name, "docker");
+        assert!(explanations.contains(
+            &"git: enabled ✓, outdated ✓, unpinned ✗ → skipped (pinned with brew pin)".to_string()
+        ));
+    }
+
+    #[test]
+    fn test_candidate_filter_only_type() {
+        let executor = MockBrewExecutor::new();
+        let outdated = executor.get_outdated_packages().unwrap();
+        let mut settings = HashMap::new();
+        settings.insert("git".to_string(), true);
+        settings.insert("docker".to_string(), true);
+        let enabled = vec!["git".to_string(), "docker".to_string()];
+
+        let filter = CandidateFilter {
+            only_type: Some(PackageType::Formula),
+            ..CandidateFilter::new(&settings, &enabled)
+        };
+        let mut explanations = Vec::new();
+        let upgradeable = filter.apply(&outdated, &mut explanations);
+        assert_eq!(upgradeable.len(), 1);
+        assert_eq!(upgradeable[0].name, "git");
+        assert!(explanations.contains(
+            &"docker: enabled ✓, outdated ✓, formula ✗ → skipped (--formula-only)".to_string()
+        ));
+
+        let filter = CandidateFilter {
+            only_type: Some(PackageType::Cask),
+            ..CandidateFilter::new(&settings, &enabled)
+        };
+        let upgradeable = filter.apply(&outdated, &mut Vec::new());
+        assert_eq!(upgradeable.len(), 1);
+        assert_eq!(upgradeable[0].name, "docker");
+    }
+
+    #[test]
+    fn test_retain_installed_drops_uninstalled_packages() -> Result<()> {
+        // wget is enabled in settings but no longer installed; git is only
+        // installed as a dependency now, which still counts
+        let executor = MockBrewExecutor::new()
+            .with_formulae(vec!["node".to_string()])
+            .with_casks(vec!["docker".to_string()]);
+
+        let mut enabled = vec![
+            "git".to_string(),
+            "wget".to_string(),
+            "node".to_string(),
+            "docker".to_string(),
+        ];
+        retain_installed(&mut enabled, &executor)?;
+
+        assert_eq!(
+            enabled,
+            vec!["git".to_string(), "node".to_string(), "docker".to_string()]
+        );
+
+        Ok(())
+    }
 
-        assert!(config_path.exists());
+    #[test]
+    fn test_split_by_section_keeps_only_enabled_names() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(
+            &config_path,
+            "## Formulae\n\n- [x] git\n- [ ] node\n\n## Casks\n\n- [x] docker\n- [ ] firefox\n",
+        )?;
+
+        let enabled = vec!["git".to_string(), "docker".to_string()];
+        let (formulae, casks) = split_by_section(&config_path, &enabled)?;
+
+        assert_eq!(formulae, vec!["git".to_string()]);
+        assert_eq!(casks, vec!["docker".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_command_reports_changes_since_settings() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(&config_path, "## Formulae\n\n- [x] git\n- [ ] node\n")?;
+
+        let executor = MockBrewExecutor::new();
+        let cli = test_cli(Commands::Stats(StatsArgs::default()), &config_path);
+        let sink = CapturingSink::new();
+
+        stats_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &StatsArgs::default(),
+            &executor,
+            &sink,
+        )?;
+
+        // python was added since the settings were written
+        assert!(sink.contains(OutputLevel::Result, "**Total Packages**: 6"));
+        assert!(sink.contains(OutputLevel::Result, "+1 formulae"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_command_json_prints_report_without_writing() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(&config_path, "## Formulae\n\n- [ ] node\n")?;
+
+        let executor = MockBrewExecutor::new();
+        let cli = Cli {
+            dry_run: true,
+            json: true,
+            ..test_cli(Commands::Dump(DumpArgs::default()), &config_path)
+        };
+        let sink = CapturingSink::new();
+
+        dump_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &DumpArgs::default(),
+            &executor,
+            &sink,
+        )?;
+
+        let results = sink.messages_at(OutputLevel::Result);
+        assert_eq!(results.len(), 1);
+        let report: serde_json::Value = serde_json::from_str(&results[0])?;
+        assert_eq!(
+            report["formulae"],
+            serde_json::json!(["git", "node", "python"])
+        );
+        assert_eq!(report["casks"][0], "docker");
+        assert_eq!(report["enabled"]["node"], false);
+        assert_eq!(report["enabled"]["git"], true);
+
+        // The settings file is left as it was
+        assert_eq!(
+            std::fs::read_to_string(&config_path)?,
+            "## Formulae\n\n- [ ] node\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_command_dry_run_reports_through_sink() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+
+        let executor = MockBrewExecutor::new();
+        let cli = Cli {
+            dry_run: true,
+            ..test_cli(Commands::Dump(DumpArgs::default()), &config_path)
+        };
+        let sink = CapturingSink::new();
+
+        dump_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &DumpArgs::default(),
+            &executor,
+            &sink,
+        )?;
+
+        assert!(!config_path.exists());
+        assert!(sink.contains(OutputLevel::Info, "Found 3 manually installed formulae"));
+        assert!(sink.contains(OutputLevel::Result, "# Brew Auto-Update Settings"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_command_up_to_date_reports_result() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(&config_path, "## Formulae\n\n- [x] node\n")?;
+
+        let executor = MockBrewExecutor::new();
+        let cli = Cli {
+            dry_run: true,
+            ..test_cli(Commands::Upgrade(UpgradeArgs::default()), &config_path)
+        };
+        let sink = CapturingSink::new();
+
+        upgrade_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &UpgradeArgs::default(),
+            &executor,
+            &sink,
+        )?;
+
+        assert!(sink.contains(OutputLevel::Info, "Checking for outdated packages"));
+        assert!(sink.contains(OutputLevel::Result, "All enabled packages are up to date!"));
+        assert!(!sink.contains(OutputLevel::Result, "disabled"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_command_survives_failed_cask_info() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(
+            &config_path,
+            "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker\n",
+        )?;
+
+        let executor = MockBrewExecutor::new().with_failing_cask_info();
+        let args = UpgradeArgs {
+            yes: true,
+            skip_auto_updates: true,
+            ..Default::default()
+        };
+        let cli = Cli {
+            dry_run: true,
+            ..test_cli(Commands::Upgrade(UpgradeArgs::default()), &config_path)
+        };
+        let sink = CapturingSink::new();
+
+        upgrade_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &args,
+            &executor,
+            &sink,
+        )?;
+
+        assert!(sink.contains(
+            OutputLevel::Warn,
+            "Couldn't check which casks update themselves"
+        ));
+        // Nothing is known to update itself, so docker stays a candidate
+        assert!(sink.contains(OutputLevel::Info, "Would upgrade docker"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_command_up_to_date_notes_disabled_outdated() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        // git is outdated in the mock but unchecked; node is current
+        std::fs::write(
+            &config_path,
+            "## Formulae\n\n- [ ] git\n- [x] node\n\n## Casks\n\n- [x] firefox\n",
+        )?;
+
+        let executor = MockBrewExecutor::new();
+        let cli = Cli {
+            dry_run: true,
+            ..test_cli(Commands::Upgrade(UpgradeArgs::default()), &config_path)
+        };
+        let sink = CapturingSink::new();
+
+        upgrade_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &UpgradeArgs::default(),
+            &executor,
+            &sink,
+        )?;
+
+        assert!(sink.contains(
+            OutputLevel::Result,
+            "All enabled packages are up to date! (1 disabled package is outdated — run 'list --disabled-only' to review)"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_command_only_ignores_missing_settings() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+
+        let executor = MockBrewExecutor::new();
+        let args = UpgradeArgs {
+            only: vec!["git".to_string(), "wget".to_string()],
+            yes: true,
+            ..Default::default()
+        };
+        let cli = Cli {
+            dry_run: true,
+            ..test_cli(Commands::Upgrade(UpgradeArgs::default()), &config_path)
+        };
+        let sink = CapturingSink::new();
+
+        upgrade_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &args,
+            &executor,
+            &sink,
+        )?;
+
+        assert!(sink.contains(OutputLevel::Info, "Would upgrade git"));
+        assert!(!sink.contains(OutputLevel::Info, "Would upgrade docker"));
+        assert!(sink.contains(
+            OutputLevel::Warn,
+            "Warning [not-outdated]: wget is not outdated"
+        ));
+        assert!(!config_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_command_exclude_drops_package() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(
+            &config_path,
+            "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker\n",
+        )?;
+
+        let executor = MockBrewExecutor::new();
+        let args = UpgradeArgs {
+            exclude: vec!["git".to_string()],
+            explain: true,
+            yes: true,
+            ..Default::default()
+        };
+        let cli = Cli {
+            dry_run: true,
+            ..test_cli(Commands::Upgrade(UpgradeArgs::default()), &config_path)
+        };
+        let sink = CapturingSink::new();
+
+        upgrade_command(
+            &test_context(temp_dir.path()),
+            &cli,
+            &args,
+            &executor,
+            &sink,
+        )?;
+
+        assert!(sink.contains(OutputLevel::Info, "Excluding git (--exclude)"));
+        assert!(sink.contains(
+            OutputLevel::Info,
+            "git: enabled ✓, outdated ✓, not excluded ✗ → skipped (--exclude)"
+        ));
+        assert!(!sink.contains(OutputLevel::Info, "Would upgrade git"));
+        assert!(sink.contains(OutputLevel::Info, "Would upgrade docker"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_dir_writable() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let nested = temp_dir.path().join("config").join("brew-update-helper");
+
+        check_dir_writable(&nested)?;
+        assert!(nested.is_dir());
+        assert_eq!(std::fs::read_dir(&nested)?.count(), 0);
+
+        // A file where the directory should be can't hold anything
+        let blocked = temp_dir.path().join("blocked");
+        std::fs::write(&blocked, "")?;
+        assert!(check_dir_writable(&blocked).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_settings_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+
+        let error = check_settings_file(&config_path).unwrap_err().to_string();
+        assert!(error.contains("run 'dump'"));
+
+        std::fs::write(&config_path, "## Formulae\n\n- [x] git\n- [ ] node\n")?;
+        assert!(check_settings_file(&config_path)?.starts_with("2 packages"));
+
+        std::fs::write(&config_path, "## Formulae\n\n- [?] git\n")?;
+        assert!(check_settings_file(&config_path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_log_writable() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let log_path = temp_dir.path().join("logs").join("upgrade.log");
+
+        check_log_writable(&log_path)?;
+        assert!(log_path.exists());
+
+        // A directory in the log's place can't be appended to
+        assert!(check_log_writable(temp_dir.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_brew_installed() {
+        assert_eq!(
+            check_brew_installed(&MockBrewExecutor::new()).unwrap(),
+            "Homebrew 4.1.5"
+        );
+        assert!(check_brew_installed(&MockBrewExecutor::new().with_failed_verification()).is_err());
+    }
+
+    #[test]
+    fn test_execute_upgrades_dry_run_shows_outdated_deps() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package = |name: &str, current: &str, available: &str| OutdatedPackage {
+            name: name.to_string(),
+            current_version: current.to_string(),
+            available_version: available.to_string(),
+            package_type: PackageType::Formula,
+        };
+        let git = package("git", "2.40.0", "2.41.0");
+        let node = package("node", "20.5.0", "20.6.0");
+        let executor = MockBrewExecutor::new()
+            .with_deps("node", vec!["icu4c".to_string()])
+            .with_outdated_packages(vec![
+                git.clone(),
+                node.clone(),
+                package("pcre2", "10.42", "10.43"),
+            ]);
+        let sink = CapturingSink::new();
+        let options = ExecuteOptions {
+            dry_run: true,
+            show_deps: true,
+            ..Default::default()
+        };
+
+        execute_upgrades(
+            &test_context(temp_dir.path()),
+            &[git, node],
+            &options,
+            &executor,
+            &sink,
+        )?;
+
+        assert!(sink.contains(OutputLevel::Info, "git: pcre2 (10.42 → 10.43)"));
+        assert!(sink.contains(OutputLevel::Info, "node: no outdated dependencies"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_upgrades_dry_run_reports_through_sink() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let executor = MockBrewExecutor::new();
+        let packages = executor.get_outdated_packages()?;
+        let sink = CapturingSink::new();
+
+        let options = ExecuteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        execute_upgrades(
+            &test_context(temp_dir.path()),
+            &packages,
+            &options,
+            &executor,
+            &sink,
+        )?;
+
+        assert!(sink.contains(OutputLevel::Info, "Would upgrade git 2.40.0 → 2.41.0"));
+        assert!(sink.contains(OutputLevel::Result, "Dry run completed"));
+        assert!(sink.messages_at(OutputLevel::Error).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_command_keeps_installed_dependencies() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        std::fs::write(
+            &config_path,
+            "## Formulae\n\n- [x] git\n- [x] node\n- [x] vim\n",
+        )?;
+
+        // node is still installed, but no longer on request
+        let executor = MockBrewExecutor::new().with_formulae(vec!["git".to_string()]);
+        let cli = test_cli(Commands::Clean, &config_path);
+        let sink = CapturingSink::new();
+
+        clean_command(&test_context(temp_dir.path()), &cli, &executor, &sink)?;
+
+        assert!(sink.contains(OutputLevel::Result, "Removed vim"));
+        assert!(!sink.contains(OutputLevel::Result, "Removed node"));
         let content = std::fs::read_to_string(&config_path)?;
+        assert!(content.contains("- [x] node"));
+        assert!(!content.contains("vim"));
+        Ok(())
+    }
 
-        // Check that stats section is included
-        assert!(content.contains("## Statistics"));
-        assert!(content.contains("**Total Packages**"));
-        assert!(content.contains("**Homebrew Version**"));
-        assert!(content.contains("**System**"));
-        assert!(content.contains("**Outdated Packages**"));
+    #[test]
+    fn test_package_result_reason_only_when_skipped() -> Result<()> {
+        let pkg = OutdatedPackage {
+            name: "git".to_string(),
+            current_version: "1.0".to_string(),
+            available_version: "2.0".to_string(),
+            package_type: PackageType::Formula,
+        };
 
-        // Check that package sections are still present
-        assert!(content.contains("## Formulae"));
-        assert!(content.contains("## Casks"));
+        let skipped = serde_json::to_value(PackageResult::skipped(&pkg, "major upgrade declined"))?;
+        assert_eq!(skipped["status"], "skipped");
+        assert_eq!(skipped["reason"], "major upgrade declined");
+
+        let upgraded = serde_json::to_value(PackageResult::new(&pkg, UpgradeStatus::Upgraded))?;
+        assert!(upgraded.get("reason").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_upgrades_flags_major_upgrades() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let context = test_context(temp_dir.path());
+        let package = |name: &str, current: &str, available: &str| OutdatedPackage {
+            name: name.to_string(),
+            current_version: current.to_string(),
+            available_version: available.to_string(),
+            package_type: PackageType::Formula,
+        };
+        let packages = vec![
+            package("buh-test-minor", "1.2.0", "1.3.0"),
+            package("buh-test-major", "1.2.0", "2.0.0"),
+        ];
+        let executor = MockBrewExecutor::new();
+
+        let sink = CapturingSink::new();
+        let options = ExecuteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        execute_upgrades(&context, &packages, &options, &executor, &sink)?;
+        assert_eq!(
+            sink.messages_at(OutputLevel::Warn),
+            vec!["⚠ major upgrade: buh-test-major 1.2.0 → 2.0.0"]
+        );
+        assert!(sink.contains(OutputLevel::Info, "Would upgrade buh-test-major"));
+
+        // Without the extra confirmation (--yes, --allow-major) both go ahead
+        let sink = CapturingSink::new();
+        let summary = execute_upgrades(
+            &context,
+            &packages,
+            &ExecuteOptions::default(),
+            &executor,
+            &sink,
+        )?;
+        assert_eq!(summary.successful, 2);
+        assert!(sink.contains(OutputLevel::Warn, "major upgrade: buh-test-major"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_upgrades_counts_failures() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let context = test_context(temp_dir.path());
+        let packages: Vec<OutdatedPackage> = ["buh-test-pass", "buh-test-fail"]
+            .iter()
+            .map(|name| OutdatedPackage {
+                name: name.to_string(),
+                current_version: "1.0".to_string(),
+                available_version: "1.1".to_string(),
+                package_type: PackageType::Formula,
+            })
+            .collect();
+        let executor =
+            MockBrewExecutor::new().with_failing_packages(vec!["buh-test-fail".to_string()]);
+        let sink = CapturingSink::new();
+
+        let summary = execute_upgrades(
+            &context,
+            &packages,
+            &ExecuteOptions::default(),
+            &executor,
+            &sink,
+        )?;
+
+        assert_eq!(summary.successful, 1);
+        assert_eq!(summary.failed, 1);
+        assert!(sink.contains(OutputLevel::Error, "Failed to upgrade buh-test-fail"));
+        assert!(sink.contains(OutputLevel::Result, "1 successful, 1 failed"));
+
+        assert!(sink.contains(OutputLevel::Result, "Slowest: buh-test-"));
+        let statuses: Vec<(&str, UpgradeStatus)> = summary
+            .results
+            .iter()
+            .map(|result| (result.name.as_str(), result.status))
+            .collect();
+        assert!(statuses.contains(&("buh-test-pass", UpgradeStatus::Upgraded)));
+        assert!(statuses.contains(&("buh-test-fail", UpgradeStatus::Failed)));
+
+        // Each outcome carries how long the upgrade took (about nothing for the mock),
+        // read back from this test's own log rather than the shared one
+        let log_path = context.log_path()?;
+        assert!(log_path.starts_with(temp_dir.path()));
+        let log = std::fs::read_to_string(log_path)?;
+        let success = log
+            .lines()
+            .find(|line| line.contains("SUCCESS: buh-test-pass 1.0 → 1.1 ("))
+            .expect("success is logged");
+        assert!(success.ends_with("s)"));
+        let failure = log
+            .lines()
+            .find(|line| line.contains("FAILED: buh-test-fail 1.0 → 1.1 ("))
+            .expect("failure is logged");
+        assert!(failure.ends_with("s) - Error: mock upgrade of buh-test-fail failed"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_upgrades_retries_transient_failures() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let context = test_context(temp_dir.path());
+        let packages: Vec<OutdatedPackage> = ["buh-test-flaky", "buh-test-broken"]
+            .iter()
+            .map(|name| OutdatedPackage {
+                name: name.to_string(),
+                current_version: "1.0".to_string(),
+                available_version: "1.1".to_string(),
+                package_type: PackageType::Cask,
+            })
+            .collect();
+        let executor = MockBrewExecutor::new()
+            .with_flaky_package("buh-test-flaky", 2)
+            .with_flaky_package("buh-test-broken", 5);
+        let sink = CapturingSink::new();
+        let options = ExecuteOptions {
+            retries: 2,
+            ..Default::default()
+        };
+
+        let summary = execute_upgrades(&context, &packages, &options, &executor, &sink)?;
+
+        assert_eq!(summary.successful, 1);
+        assert_eq!(summary.failed, 1);
+        assert!(sink.contains(
+            OutputLevel::Info,
+            "Upgrade of buh-test-flaky failed, retrying (2/2)"
+        ));
+        assert!(sink.contains(OutputLevel::Error, "Failed to upgrade buh-test-broken"));
+
+        let log = std::fs::read_to_string(context.log_path()?)?;
+        assert!(log.contains("RETRY: buh-test-flaky attempt 1/2"));
+        assert!(log.contains("SUCCESS: buh-test-flaky 1.0 → 1.1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_retry_delay_is_capped() {
+        assert_eq!(next_retry_delay(RETRY_BASE_DELAY), Duration::from_secs(10));
+        assert_eq!(next_retry_delay(Duration::from_secs(20)), RETRY_MAX_DELAY);
+        assert_eq!(next_retry_delay(RETRY_MAX_DELAY), RETRY_MAX_DELAY);
+        assert_eq!(next_retry_delay(Duration::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn test_execute_upgrades_parallel_fetch() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let context = test_context(temp_dir.path());
+        let packages: Vec<OutdatedPackage> =
+            ["buh-test-fetch-a", "buh-test-fetch-b", "buh-test-fetch-c"]
+                .iter()
+                .map(|name| OutdatedPackage {
+                    name: name.to_string(),
+                    current_version: "1.0".to_string(),
+                    available_version: "1.1".to_string(),
+                    package_type: PackageType::Formula,
+                })
+                .collect();
+        let executor = MockBrewExecutor::new();
+        let sink = CapturingSink::new();
+        let options = ExecuteOptions {
+            parallel_fetch: true,
+            ..Default::default()
+        };
+
+        let summary = execute_upgrades(&context, &packages, &options, &executor, &sink)?;
+
+        let mut fetched = executor.fetched_packages();
+        fetched.sort();
+        assert_eq!(
+            fetched,
+            vec!["buh-test-fetch-a", "buh-test-fetch-b", "buh-test-fetch-c"]
+        );
+        assert_eq!(summary.successful, 3);
+
+        // Without the flag, and in dry runs, nothing is fetched
+        let executor = MockBrewExecutor::new();
+        execute_upgrades(
+            &context,
+            &packages,
+            &ExecuteOptions::default(),
+            &executor,
+            &sink,
+        )?;
+        let dry_run = ExecuteOptions {
+            dry_run: true,
+            parallel_fetch: true,
+            ..Default::default()
+        };
+        execute_upgrades(&context, &packages, &dry_run, &executor, &sink)?;
+        assert!(executor.fetched_packages().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_upgrades_json_results() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let executor = MockBrewExecutor::new();
+        let packages = executor.get_outdated_packages()?;
+        let sink = CapturingSink::new();
+        let options = ExecuteOptions {
+            dry_run: true,
+            json_results: true,
+            ..Default::default()
+        };
+
+        let summary = execute_upgrades(
+            &test_context(temp_dir.path()),
+            &packages,
+            &options,
+            &executor,
+            &sink,
+        )?;
+
+        // The human summary moves off the result channel
+        assert!(sink.messages_at(OutputLevel::Result).is_empty());
+        let json: serde_json::Value = serde_json::to_value(&summary.results)?;
+        assert_eq!(
+            json[0],
+            serde_json::json!({"name": "git", "from": "2.40.0", "to": "2.41.0", "status": "dry_run"})
+        );
+        assert_eq!(json.as_array().map(Vec::len), Some(packages.len()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_upgrades_in_batches() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let packages: Vec<OutdatedPackage> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|name| OutdatedPackage {
+                name: name.to_string(),
+                current_version: "1.0".to_string(),
+                available_version: "1.1".to_string(),
+                package_type: PackageType::Formula,
+            })
+            .collect();
+        let executor = MockBrewExecutor::new();
+        let sink = CapturingSink::new();
+        let options = ExecuteOptions {
+            dry_run: true,
+            batch_size: Some(2),
+            ..Default::default()
+        };
+
+        let summary = execute_upgrades(
+            &test_context(temp_dir.path()),
+            &packages,
+            &options,
+            &executor,
+            &sink,
+        )?;
+
+        let batch_summaries: Vec<String> = sink
+            .messages_at(OutputLevel::Info)
+            .into_iter()
+            .filter(|message| message.contains("done:"))
+            .collect();
+        assert_eq!(batch_summaries.len(), 3);
+        assert!(batch_summaries[0].contains("Batch 1/3 done: 2/5 processed"));
+        assert!(batch_summaries[2].contains("Batch 3/3 done: 5/5 processed"));
+        assert_eq!(summary.selected, 5);
 
         Ok(())
     }
@@ -1,25 +1,104 @@
 use anyhow::Result;
-use chrono::Utc;
-use std::collections::HashMap;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-pub fn get_config_path(custom_path: &Option<String>) -> Result<PathBuf> {
-    if let Some(path) = custom_path {
-        return Ok(PathBuf::from(path));
+use crate::brew::{same_package, OutdatedPackage, PackageType};
+use crate::utils::{AppContext, AppFile};
+
+/// Formats the `export` subcommand can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A Brewfile for `brew bundle`
+    Brewfile,
+}
+
+/// On-disk formats for the settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    /// Markdown checkboxes (the default)
+    Markdown,
+    /// `[formulae]` and `[casks]` tables of name = true/false
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Pick the backend from the file extension; anything but `.toml` is markdown.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Markdown,
+        }
     }
 
-    // For testing, use current directory
-    if std::env::var("CARGO_MANIFEST_DIR").is_ok() {
-        return Ok(PathBuf::from("./brew-settings.md"));
+    fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Markdown => "md",
+            ConfigFormat::Toml => "toml",
+        }
     }
+}
+
+/// TOML representation of the settings file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TomlSettings {
+    #[serde(default)]
+    formulae: BTreeMap<String, bool>,
+    #[serde(default)]
+    casks: BTreeMap<String, bool>,
+}
+
+/// JSON representation of the settings file, for tooling.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SettingsDocument {
+    pub generated_on: String,
+    pub formulae: BTreeMap<String, bool>,
+    pub casks: BTreeMap<String, bool>,
+}
+
+/// Settings file path used when `--config` isn't given.
+pub const CONFIG_ENV_VAR: &str = "BREW_UPDATE_HELPER_CONFIG";
 
-    // Production: use ~/.config/brew-update-helper/settings.md
-    let config_dir = dirs::config_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
-        .join("brew-update-helper");
+/// Where the settings file lives: `--config`, then `BREW_UPDATE_HELPER_CONFIG`,
+/// then the default location (see `AppContext::file_path`).
+pub fn get_config_path(
+    context: &AppContext,
+    custom_path: &Option<String>,
+    format: Option<ConfigFormat>,
+) -> Result<PathBuf> {
+    let env_path = std::env::var(CONFIG_ENV_VAR).ok();
+    resolve_config_path(context, custom_path.as_deref(), env_path.as_deref(), format)
+}
 
-    Ok(config_dir.join("settings.md"))
+fn resolve_config_path(
+    context: &AppContext,
+    custom_path: Option<&str>,
+    env_path: Option<&str>,
+    format: Option<ConfigFormat>,
+) -> Result<PathBuf> {
+    let explicit = custom_path.or(env_path.filter(|path| !path.is_empty()));
+    if let Some(path) = explicit {
+        let path = PathBuf::from(path);
+        if let Some(format) = format {
+            if ConfigFormat::from_path(&path) != format {
+                anyhow::bail!(
+                    "--config-format {} doesn't match the extension of {}",
+                    format.extension(),
+                    path.display()
+                );
+            }
+        }
+        return Ok(path);
+    }
+
+    let default = context.file_path(AppFile::Settings)?;
+    Ok(match format {
+        Some(format) => default.with_extension(format.extension()),
+        None => default,
+    })
 }
 
 pub fn read_existing_settings(config_path: &PathBuf) -> Result<HashMap<String, bool>> {
@@ -29,17 +108,21 @@ pub fn read_existing_settings(config_path: &PathBuf) -> Result<HashMap<String, b
         return Ok(settings);
     }
 
+    if ConfigFormat::from_path(config_path) == ConfigFormat::Toml {
+        return read_existing_settings_toml(config_path);
+    }
+
     let content = fs::read_to_string(config_path)?;
 
     for line in content.lines() {
         let line = line.trim();
         if line.starts_with("- [x]") {
             if let Some(package) = line.strip_prefix("- [x] ") {
-                settings.insert(package.trim().to_string(), true);
+                settings.insert(strip_annotation(package).to_string(), true);
             }
         } else if line.starts_with("- [ ]") {
             if let Some(package) = line.strip_prefix("- [ ] ") {
-                settings.insert(package.trim().to_string(), false);
+                settings.insert(strip_annotation(package).to_string(), false);
             }
         }
     }
@@ -47,6 +130,99 @@ pub fn read_existing_settings(config_path: &PathBuf) -> Result<HashMap<String, b
     Ok(settings)
 }
 
+/// A package's entry in the settings file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageSettings {
+    pub enabled: bool,
+    /// Extra `brew upgrade` arguments from a `{args: ...}` suffix
+    pub extra_args: Vec<String>,
+}
+
+/// Like `read_existing_settings`, but keeping each entry's `{args: ...}`.
+/// Arguments are split on whitespace; quoting isn't supported. TOML settings
+/// have no args syntax, so every TOML entry gets none.
+pub fn read_package_settings(config_path: &PathBuf) -> Result<HashMap<String, PackageSettings>> {
+    if !config_path.exists() || ConfigFormat::from_path(config_path) == ConfigFormat::Toml {
+        return Ok(read_existing_settings(config_path)?
+            .into_iter()
+            .map(|(name, enabled)| {
+                (
+                    name,
+                    PackageSettings {
+                        enabled,
+                        extra_args: Vec::new(),
+                    },
+                )
+            })
+            .collect());
+    }
+
+    let content = fs::read_to_string(config_path)?;
+    Ok(parse_package_settings(&content))
+}
+
+fn parse_package_settings(content: &str) -> HashMap<String, PackageSettings> {
+    let mut settings = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        let (enabled, entry) = if let Some(entry) = line.strip_prefix("- [x] ") {
+            (true, entry)
+        } else if let Some(entry) = line.strip_prefix("- [ ] ") {
+            (false, entry)
+        } else {
+            continue;
+        };
+        let parts = split_entry(entry);
+        settings.insert(
+            parts.name.to_string(),
+            PackageSettings {
+                enabled,
+                extra_args: parts
+                    .args
+                    .map(|args| args.split_whitespace().map(str::to_string).collect())
+                    .unwrap_or_default(),
+            },
+        );
+    }
+    settings
+}
+
+/// Flip a package's checkbox in the settings file, leaving everything else as is.
+pub fn set_package_enabled(config_path: &PathBuf, package: &str, enabled: bool) -> Result<bool> {
+    if ConfigFormat::from_path(config_path) == ConfigFormat::Toml {
+        return set_package_enabled_toml(config_path, package, enabled);
+    }
+
+    let content = fs::read_to_string(config_path)?;
+    let checkbox = if enabled { "[x]" } else { "[ ]" };
+    let mut found = false;
+
+    let mut updated: Vec<String> = content
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            if trimmed.starts_with("- [")
+                && extract_package_name(trimmed).as_deref() == Some(package)
+            {
+                found = true;
+                let rest = &trimmed["- [x]".len()..];
+                format!("- {}{}", checkbox, rest)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+
+    if found {
+        if content.ends_with('\n') {
+            updated.push(String::new());
+        }
+        fs::write(config_path, updated.join("\n"))?;
+    }
+
+    Ok(found)
+}
+
 pub fn read_previous_packages(config_path: &PathBuf) -> Result<(Vec<String>, Vec<String>)> {
     let mut formulae = Vec::new();
     let mut casks = Vec::new();
@@ -56,6 +232,14 @@ pub fn read_previous_packages(config_path: &PathBuf) -> Result<(Vec<String>, Vec
         return Ok((formulae, casks));
     }
 
+    if ConfigFormat::from_path(config_path) == ConfigFormat::Toml {
+        let document = read_toml_settings(config_path)?;
+        return Ok((
+            document.formulae.into_keys().collect(),
+            document.casks.into_keys().collect(),
+        ));
+    }
+
     let content = fs::read_to_string(config_path)?;
 
     for line in content.lines() {
@@ -79,21 +263,581 @@ pub fn read_previous_packages(config_path: &PathBuf) -> Result<(Vec<String>, Vec
     Ok((formulae, casks))
 }
 
+/// Every package entry in a settings file with the section it's listed under,
+/// duplicates included, in file order.
+pub fn read_settings_entries(config_path: &PathBuf) -> Result<Vec<(PackageType, String)>> {
+    if ConfigFormat::from_path(config_path) == ConfigFormat::Toml {
+        // TOML rejects duplicate keys while parsing, so only sections can be wrong
+        let document = read_toml_settings(config_path)?;
+        return Ok(document
+            .formulae
+            .into_keys()
+            .map(|name| (PackageType::Formula, name))
+            .chain(
+                document
+                    .casks
+                    .into_keys()
+                    .map(|name| (PackageType::Cask, name)),
+            )
+            .collect());
+    }
+
+    let content = fs::read_to_string(config_path)?;
+    let mut entries = Vec::new();
+    let mut section = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if line == "## Formulae" {
+            section = Some(PackageType::Formula);
+        } else if line == "## Casks" {
+            section = Some(PackageType::Cask);
+        } else if line.starts_with("## ") {
+            section = None;
+        } else if let (Some(section), Some(name)) = (&section, extract_package_name(line)) {
+            entries.push((section.clone(), name));
+        }
+    }
+    Ok(entries)
+}
+
+/// Something wrong with a hand-edited settings file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingsProblem {
+    Duplicate(String),
+    WrongSection { name: String, listed: PackageType },
+    NotInstalled(String),
+}
+
+impl std::fmt::Display for SettingsProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let section = |package_type: &PackageType| match package_type {
+            PackageType::Formula => "Formulae",
+            PackageType::Cask => "Casks",
+        };
+        match self {
+            SettingsProblem::Duplicate(name) => write!(f, "{} is listed more than once", name),
+            SettingsProblem::WrongSection { name, listed } => write!(
+                f,
+                "{} is listed under {} but is installed as a {}",
+                name,
+                section(listed),
+                match listed {
+                    PackageType::Formula => "cask",
+                    PackageType::Cask => "formula",
+                }
+            ),
+            SettingsProblem::NotInstalled(name) => write!(f, "{} is no longer installed", name),
+        }
+    }
+}
+
+/// Cross-check settings entries against what's installed.
+pub fn find_settings_problems(
+    entries: &[(PackageType, String)],
+    formulae: &[String],
+    casks: &[String],
+) -> Vec<SettingsProblem> {
+    let mut problems = Vec::new();
+    let mut seen: Vec<&String> = Vec::new();
+
+    for (listed, name) in entries {
+        if seen.contains(&name) {
+            if !problems.contains(&SettingsProblem::Duplicate(name.clone())) {
+                problems.push(SettingsProblem::Duplicate(name.clone()));
+            }
+            continue;
+        }
+        seen.push(name);
+
+        let (same, other) = match listed {
+            PackageType::Formula => (formulae, casks),
+            PackageType::Cask => (casks, formulae),
+        };
+        if same.contains(name) {
+            continue;
+        }
+        if other.contains(name) {
+            problems.push(SettingsProblem::WrongSection {
+                name: name.clone(),
+                listed: listed.clone(),
+            });
+        } else {
+            problems.push(SettingsProblem::NotInstalled(name.clone()));
+        }
+    }
+
+    problems
+}
+
+/// A settings file with the entries for uninstalled packages taken out.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PrunedSettings {
+    pub content: String,
+    /// Names of the removed entries, in file order
+    pub removed: Vec<String>,
+}
+
+/// Drop the entries (and, in markdown, the `>` notes under them) for packages
+/// that are neither a current formula nor a current cask. Everything else is
+/// left exactly as written.
+pub fn prune_settings(
+    content: &str,
+    format: ConfigFormat,
+    current_formulae: &[String],
+    current_casks: &[String],
+) -> PrunedSettings {
+    // brew lists tapped formulae by their short name
+    let installed = |name: &str| {
+        current_formulae.iter().any(|pkg| same_package(pkg, name))
+            || current_casks.iter().any(|pkg| same_package(pkg, name))
+    };
+
+    let mut kept = Vec::new();
+    let mut removed = Vec::new();
+    let mut in_packages = false;
+    let mut dropping_notes = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        let entry = match format {
+            ConfigFormat::Markdown => {
+                if trimmed.starts_with("## ") {
+                    in_packages = trimmed == "## Formulae" || trimmed == "## Casks";
+                }
+                extract_package_name(trimmed)
+            }
+            ConfigFormat::Toml => {
+                if trimmed.starts_with('[') {
+                    in_packages = trimmed == "[formulae]" || trimmed == "[casks]";
+                }
+                trimmed
+                    .split_once('=')
+                    .map(|(key, _)| key.trim().trim_matches('"').to_string())
+            }
+        };
+
+        match entry {
+            Some(name) if in_packages => {
+                dropping_notes = !installed(&name);
+                if dropping_notes {
+                    removed.push(name);
+                    continue;
+                }
+            }
+            _ if dropping_notes && trimmed.starts_with('>') => continue,
+            _ => dropping_notes = false,
+        }
+        kept.push(line);
+    }
+
+    let mut content_out = kept.join("\n");
+    if content.ends_with('\n') {
+        content_out.push('\n');
+    }
+    PrunedSettings {
+        content: content_out,
+        removed,
+    }
+}
+
+/// Hand-written content in a settings file that `dump` carries over.
+#[derive(Debug, Default, PartialEq)]
+pub struct SettingsExtras {
+    /// `>` note lines, keyed by the package entry they sit under
+    pub notes: HashMap<String, Vec<String>>,
+    /// Sections with headings the tool doesn't generate, verbatim
+    pub custom_sections: Vec<String>,
+    /// `{args: ...}` contents, keyed by package
+    pub args: HashMap<String, String>,
+    /// The header's `Greedy casks:` choice, if set
+    pub greedy: Option<bool>,
+}
+
+/// Headings `generate_settings_content` writes itself.
+const GENERATED_SECTIONS: [&str; 3] = ["## Statistics", "## Formulae", "## Casks"];
+
+pub fn read_settings_extras(config_path: &PathBuf) -> Result<SettingsExtras> {
+    let mut extras = SettingsExtras::default();
+    if !config_path.exists() {
+        return Ok(extras);
+    }
+
+    let content = fs::read_to_string(config_path)?;
+    extras.greedy = parse_greedy_setting(&content);
+    // Other TOML comments aren't carried over; only the markdown format has free-form content
+    if ConfigFormat::from_path(config_path) == ConfigFormat::Toml {
+        return Ok(extras);
+    }
+    let mut last_package: Option<String> = None;
+    let mut in_custom_section = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("## ") {
+            in_custom_section = !GENERATED_SECTIONS.contains(&trimmed);
+            last_package = None;
+        }
+
+        if in_custom_section {
+            extras.custom_sections.push(line.to_string());
+        } else if let Some(package) = extract_package_name(trimmed) {
+            let entry = &trimmed["- [x] ".len()..];
+            if let Some(args) = split_entry(entry).args {
+                extras.args.insert(package.clone(), args.to_string());
+            }
+            last_package = Some(package);
+        } else if trimmed.starts_with('>') {
+            if let Some(package) = &last_package {
+                extras
+                    .notes
+                    .entry(package.clone())
+                    .or_default()
+                    .push(trimmed.to_string());
+            }
+        }
+    }
+
+    // Drop trailing blank lines so re-emitting doesn't grow the file each dump
+    while extras
+        .custom_sections
+        .last()
+        .is_some_and(|line| line.trim().is_empty())
+    {
+        extras.custom_sections.pop();
+    }
+
+    Ok(extras)
+}
+
+/// Put preserved notes back under their packages and append custom sections.
+pub fn merge_settings_extras(content: &str, extras: &SettingsExtras) -> String {
+    let mut merged = String::new();
+    for line in content.lines() {
+        let name = extract_package_name(line.trim());
+        match name.as_ref().and_then(|name| extras.args.get(name)) {
+            Some(args) => {
+                // Keep the args ahead of any `<!-- outdated -->` annotation
+                let (entry, annotation) = match line.find("  <!--") {
+                    Some(pos) => line.split_at(pos),
+                    None => (line, ""),
+                };
+                merged.push_str(&format!("{} {{args: {}}}{}", entry, args, annotation));
+            }
+            None => merged.push_str(line),
+        }
+        merged.push('\n');
+        if let (Some(greedy), true) = (extras.greedy, line.starts_with("Generated on:")) {
+            merged.push_str(&format!(
+                "\nGreedy casks: {}\n",
+                if greedy { "yes" } else { "no" }
+            ));
+        }
+        let notes = name.and_then(|name| extras.notes.get(&name));
+        for note in notes.into_iter().flatten() {
+            merged.push_str(&format!("  {}\n", note));
+        }
+    }
+
+    if !extras.custom_sections.is_empty() {
+        merged.push('\n');
+        for line in &extras.custom_sections {
+            merged.push_str(line);
+            merged.push('\n');
+        }
+    }
+    merged
+}
+
+/// Check that every checkbox line in a settings file parses, returning the
+/// number of package entries.
+pub fn check_settings_content(content: &str) -> Result<usize> {
+    let mut entries = 0;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if !line.starts_with("- [") {
+            continue;
+        }
+        match extract_package_name(line) {
+            Some(name) if !name.is_empty() => entries += 1,
+            _ => anyhow::bail!(
+                "line {} isn't a valid \"- [x] name\" entry: {}",
+                number + 1,
+                line
+            ),
+        }
+    }
+
+    if entries == 0 {
+        anyhow::bail!("no packages listed");
+    }
+    Ok(entries)
+}
+
+fn read_toml_settings(config_path: &Path) -> Result<TomlSettings> {
+    let content = fs::read_to_string(config_path)?;
+    toml::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("invalid TOML in {}: {}", config_path.display(), e))
+}
+
+pub fn read_existing_settings_toml(config_path: &Path) -> Result<HashMap<String, bool>> {
+    if !config_path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let document = read_toml_settings(config_path)?;
+    Ok(document
+        .formulae
+        .into_iter()
+        .chain(document.casks)
+        .collect())
+}
+
+/// Check that a TOML settings file parses, returning the number of package entries.
+pub fn check_settings_toml(content: &str) -> Result<usize> {
+    let document: TomlSettings = toml::from_str(content)?;
+    let entries = document.formulae.len() + document.casks.len();
+    if entries == 0 {
+        anyhow::bail!("no packages listed");
+    }
+    Ok(entries)
+}
+
+/// Rewrite a `name = true/false` line in place so comments and layout survive.
+fn set_package_enabled_toml(config_path: &Path, package: &str, enabled: bool) -> Result<bool> {
+    let content = fs::read_to_string(config_path)?;
+    let mut found = false;
+
+    let mut updated: Vec<String> = content
+        .lines()
+        .map(|line| match line.split_once('=') {
+            Some((key, _)) if key.trim().trim_matches('"') == package => {
+                found = true;
+                format!("{}= {}", key, enabled)
+            }
+            _ => line.to_string(),
+        })
+        .collect();
+
+    if found {
+        if content.ends_with('\n') {
+            updated.push(String::new());
+        }
+        fs::write(config_path, updated.join("\n"))?;
+    }
+
+    Ok(found)
+}
+
+/// TOML settings, with `greedy` kept as a `# Greedy casks:` header comment.
+pub fn generate_settings_toml(
+    formulae: &[String],
+    casks: &[String],
+    existing_settings: &HashMap<String, bool>,
+    greedy: Option<bool>,
+) -> Result<String> {
+    let enabled_map = |packages: &[String]| -> BTreeMap<String, bool> {
+        packages
+            .iter()
+            .map(|pkg| {
+                (
+                    pkg.clone(),
+                    existing_settings.get(pkg).copied().unwrap_or(true),
+                )
+            })
+            .collect()
+    };
+
+    let document = TomlSettings {
+        formulae: enabled_map(formulae),
+        casks: enabled_map(casks),
+    };
+
+    let greedy = match greedy {
+        Some(true) => "# Greedy casks: yes\n",
+        Some(false) => "# Greedy casks: no\n",
+        None => "",
+    };
+    Ok(format!(
+        "# Brew Auto-Update Settings\n# Generated on: {}\n{}\n{}",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+        greedy,
+        toml::to_string(&document)?
+    ))
+}
+
 fn extract_package_name(line: &str) -> Option<String> {
     if line.starts_with("- [x] ") {
-        line.strip_prefix("- [x] ").map(|s| s.trim().to_string())
+        line.strip_prefix("- [x] ")
+            .map(|s| strip_annotation(s).to_string())
     } else if line.starts_with("- [ ] ") {
-        line.strip_prefix("- [ ] ").map(|s| s.trim().to_string())
+        line.strip_prefix("- [ ] ")
+            .map(|s| strip_annotation(s).to_string())
     } else {
         None
     }
 }
 
+/// Drop a trailing `<!-- ... -->` annotation, `{args: ...}` block and `@version`
+/// suffix from a package entry.
+fn strip_annotation(entry: &str) -> &str {
+    split_entry(entry).name
+}
+
+/// The parts of a `name @version {args: ...}  <!-- ... -->` package entry.
+struct EntryParts<'a> {
+    name: &'a str,
+    version: Option<&'a str>,
+    args: Option<&'a str>,
+}
+
+fn split_entry(entry: &str) -> EntryParts<'_> {
+    let entry = match entry.find("<!--") {
+        Some(pos) => entry[..pos].trim(),
+        None => entry.trim(),
+    };
+    let (entry, args) = match entry
+        .strip_suffix('}')
+        .and_then(|rest| rest.rsplit_once("{args:"))
+    {
+        Some((rest, args)) => (rest.trim(), Some(args.trim())),
+        None => (entry, None),
+    };
+    // Names can contain '@' themselves (python@3.11), so the suffix needs the space
+    let (name, version) = match entry.rsplit_once(" @") {
+        Some((name, version)) => (name.trim(), Some(version.trim())),
+        None => (entry, None),
+    };
+    EntryParts {
+        name,
+        version,
+        args,
+    }
+}
+
+/// Versions recorded with `dump --record-versions`; entries without one are left out.
+pub fn read_recorded_versions(config_path: &PathBuf) -> Result<HashMap<String, String>> {
+    let mut versions = HashMap::new();
+    if !config_path.exists() || ConfigFormat::from_path(config_path) == ConfigFormat::Toml {
+        return Ok(versions);
+    }
+
+    let content = fs::read_to_string(config_path)?;
+    for line in content.lines() {
+        let entry = line
+            .trim()
+            .strip_prefix("- [x] ")
+            .or_else(|| line.trim().strip_prefix("- [ ] "));
+        if let Some(EntryParts {
+            name,
+            version: Some(version),
+            ..
+        }) = entry.map(split_entry)
+        {
+            versions.insert(name.to_string(), version.to_string());
+        }
+    }
+
+    Ok(versions)
+}
+
+/// Replace the `@version` recorded for each package in `versions` that has
+/// one, e.g. after upgrading it. Entries without a recorded version are left
+/// alone. Returns how many entries changed.
+pub fn update_recorded_versions(
+    config_path: &PathBuf,
+    versions: &HashMap<String, String>,
+) -> Result<usize> {
+    if !config_path.exists() || ConfigFormat::from_path(config_path) == ConfigFormat::Toml {
+        return Ok(0);
+    }
+
+    let content = fs::read_to_string(config_path)?;
+    let mut changed = 0;
+    let mut updated: Vec<String> = content
+        .lines()
+        .map(|line| {
+            let entry = line
+                .trim()
+                .strip_prefix("- [x] ")
+                .or_else(|| line.trim().strip_prefix("- [ ] "));
+            let Some(EntryParts {
+                name,
+                version: Some(recorded),
+                ..
+            }) = entry.map(split_entry)
+            else {
+                return line.to_string();
+            };
+            match versions.get(name) {
+                Some(version) if version != recorded => {
+                    changed += 1;
+                    line.replacen(
+                        &format!("{} @{}", name, recorded),
+                        &format!("{} @{}", name, version),
+                        1,
+                    )
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect();
+
+    if changed > 0 {
+        if content.ends_with('\n') {
+            updated.push(String::new());
+        }
+        fs::write(config_path, updated.join("\n"))?;
+    }
+
+    Ok(changed)
+}
+
+fn outdated_annotation(
+    name: &str,
+    package_type: PackageType,
+    outdated: &[OutdatedPackage],
+) -> String {
+    outdated
+        .iter()
+        .find(|pkg| pkg.package_type == package_type && same_package(&pkg.name, name))
+        .map(|pkg| {
+            format!(
+                "  <!-- outdated: {} → {} -->",
+                pkg.current_version, pkg.available_version
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Give packages missing from `existing_settings` an explicit state, so new
+/// ones follow the chosen default instead of always starting enabled.
+pub fn settings_with_default(
+    formulae: &[String],
+    casks: &[String],
+    existing_settings: &HashMap<String, bool>,
+    default_enabled: bool,
+) -> HashMap<String, bool> {
+    let mut settings = existing_settings.clone();
+    for package in formulae.iter().chain(casks) {
+        settings.entry(package.clone()).or_insert(default_enabled);
+    }
+    settings
+}
+
+fn recorded_version(name: &str, versions: Option<&HashMap<String, String>>) -> String {
+    versions
+        .and_then(|versions| versions.get(name))
+        .map(|version| format!(" @{}", version))
+        .unwrap_or_default()
+}
+
 pub fn generate_settings_content(
     formulae: &[String],
     casks: &[String],
     existing_settings: &HashMap<String, bool>,
+    outdated: &[OutdatedPackage],
     stats: Option<&crate::stats::PackageStats>,
+    versions: Option<&HashMap<String, String>>,
 ) -> String {
     let mut content = String::new();
 
@@ -115,7 +859,12 @@ pub fn generate_settings_content(
     for formula in sorted_formulae {
         let enabled = existing_settings.get(&formula).copied().unwrap_or(true);
         let checkbox = if enabled { "[x]" } else { "[ ]" };
-        content.push_str(&format!("- {} {}\n", checkbox, formula));
+        let annotation = outdated_annotation(&formula, PackageType::Formula, outdated);
+        let version = recorded_version(&formula, versions);
+        content.push_str(&format!(
+            "- {} {}{}{}\n",
+            checkbox, formula, version, annotation
+        ));
     }
 
     // Casks section - sort alphabetically
@@ -125,9 +874,146 @@ pub fn generate_settings_content(
     for cask in sorted_casks {
         let enabled = existing_settings.get(&cask).copied().unwrap_or(true);
         let checkbox = if enabled { "[x]" } else { "[ ]" };
-        content.push_str(&format!("- {} {}\n", checkbox, cask));
+        let annotation = outdated_annotation(&cask, PackageType::Cask, outdated);
+        let version = recorded_version(&cask, versions);
+        content.push_str(&format!(
+            "- {} {}{}{}\n",
+            checkbox, cask, version, annotation
+        ));
+    }
+
+    content
+}
+
+pub fn generate_settings_json(
+    formulae: &[String],
+    casks: &[String],
+    existing_settings: &HashMap<String, bool>,
+) -> Result<String> {
+    let enabled_map = |packages: &[String]| -> BTreeMap<String, bool> {
+        packages
+            .iter()
+            .map(|pkg| {
+                let enabled = existing_settings.get(pkg).copied().unwrap_or(true);
+                (pkg.clone(), enabled)
+            })
+            .collect()
+    };
+
+    let document = SettingsDocument {
+        generated_on: Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        formulae: enabled_map(formulae),
+        casks: enabled_map(casks),
+    };
+
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// When a settings file was generated, from the `Generated on:` line that both
+/// the markdown and TOML formats write.
+pub fn parse_generated_timestamp(content: &str) -> Option<DateTime<Utc>> {
+    let timestamp = content.lines().find_map(|line| {
+        line.trim_start_matches('#')
+            .trim()
+            .strip_prefix("Generated on:")
+    })?;
+    let timestamp = timestamp.trim().trim_end_matches("UTC").trim();
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|naive| naive.and_utc())
+}
+
+/// Copy the settings file to `<name>.bak` next to it (replacing an older
+/// backup), returning the backup's path, or `None` if there's nothing to back up.
+pub fn backup_settings(config_path: &Path) -> Result<Option<PathBuf>> {
+    if !config_path.exists() {
+        return Ok(None);
+    }
+    let mut backup = config_path.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    fs::copy(config_path, &backup)?;
+    Ok(Some(backup))
+}
+
+/// The `Greedy casks: yes|no` line in a settings file's header, which decides
+/// whether casks that update themselves count as outdated. Only the header
+/// (before the first section) is searched.
+pub fn parse_greedy_setting(content: &str) -> Option<bool> {
+    let value = content
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with("## ") && !line.starts_with('['))
+        .find_map(|line| {
+            line.trim_start_matches('#')
+                .trim()
+                .strip_prefix("Greedy casks:")
+        })?;
+    match value.trim().to_ascii_lowercase().as_str() {
+        "yes" | "true" => Some(true),
+        "no" | "false" => Some(false),
+        _ => None,
     }
+}
+
+/// One installed package in a snapshot.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    pub name: String,
+    pub version: String,
+    #[serde(rename = "type")]
+    pub package_type: PackageType,
+}
+
+/// Every installed package with its exact version, for reproducing a setup later.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotDocument {
+    pub generated_on: String,
+    pub packages: Vec<SnapshotEntry>,
+}
 
+pub fn generate_snapshot_json(
+    versions: Vec<(String, String, PackageType)>,
+    generated_on: DateTime<Utc>,
+) -> Result<String> {
+    let document = SnapshotDocument {
+        generated_on: generated_on.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        packages: versions
+            .into_iter()
+            .map(|(name, version, package_type)| SnapshotEntry {
+                name,
+                version,
+                package_type,
+            })
+            .collect(),
+    };
+
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Render the enabled packages as a Brewfile; disabled ones are left out.
+pub fn generate_brewfile(
+    formulae: &[String],
+    casks: &[String],
+    existing_settings: &HashMap<String, bool>,
+) -> String {
+    let enabled = |packages: &[String]| -> Vec<String> {
+        let mut enabled: Vec<String> = packages
+            .iter()
+            .filter(|pkg| existing_settings.get(*pkg).copied().unwrap_or(false))
+            .cloned()
+            .collect();
+        enabled.sort();
+        enabled
+    };
+
+    let mut content = String::new();
+    for formula in enabled(formulae) {
+        content.push_str(&format!("brew \"{}\"\n", formula));
+    }
+    for cask in enabled(casks) {
+        content.push_str(&format!("cask \"{}\"\n", cask));
+    }
     content
 }
 
@@ -136,6 +1022,384 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_settings_extras_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        fs::write(
+            &config_path,
+            "# Brew Auto-Update Settings\n\n## Formulae\n\n- [x] git\n  > pinned to 2.x for work\n- [ ] node\n\n## Casks\n\n- [x] docker\n\n## My Notes\n\nRemember to restart Docker.\n\n",
+        )?;
+
+        let extras = read_settings_extras(&config_path)?;
+        assert_eq!(
+            extras.notes.get("git"),
+            Some(&vec!["> pinned to 2.x for work".to_string()])
+        );
+        assert_eq!(
+            extras.custom_sections,
+            vec!["## My Notes", "", "Remember to restart Docker."]
+        );
+
+        let formulae = vec!["git".to_string(), "node".to_string()];
+        let casks = vec!["docker".to_string()];
+        let existing = read_existing_settings(&config_path)?;
+        let generated = generate_settings_content(&formulae, &casks, &existing, &[], None, None);
+        let merged = merge_settings_extras(&generated, &extras);
+
+        assert!(merged.contains("- [x] git\n  > pinned to 2.x for work\n- [ ] node\n"));
+        assert!(merged.ends_with("\n## My Notes\n\nRemember to restart Docker.\n"));
+
+        // A second pass gives the same result
+        fs::write(&config_path, &merged)?;
+        let again = merge_settings_extras(&generated, &read_settings_extras(&config_path)?);
+        assert_eq!(again, merged);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_settings() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        assert_eq!(backup_settings(&config_path)?, None);
+
+        fs::write(&config_path, "## Formulae\n\n- [ ] git\n")?;
+        let backup = backup_settings(&config_path)?.unwrap();
+        assert_eq!(backup, temp_dir.path().join("settings.md.bak"));
+        assert_eq!(fs::read_to_string(&backup)?, "## Formulae\n\n- [ ] git\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_greedy_setting() {
+        assert_eq!(
+            parse_greedy_setting(
+                "# Brew Auto-Update Settings\n\nGreedy casks: no\n\n## Formulae\n"
+            ),
+            Some(false)
+        );
+        assert_eq!(
+            parse_greedy_setting(
+                "# Brew Auto-Update Settings\n# Greedy casks: yes\n\n[formulae]\n"
+            ),
+            Some(true)
+        );
+        assert_eq!(parse_greedy_setting("Greedy casks: maybe\n"), None);
+        // Custom sections aren't part of the header
+        assert_eq!(
+            parse_greedy_setting("## Formulae\n\n## My Notes\n\nGreedy casks: no\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_toml_settings_keep_greedy_setting() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.toml");
+        fs::write(
+            &config_path,
+            "# Brew Auto-Update Settings\n# Generated on: 2024-08-22 10:30:00 UTC\n# Greedy casks: no\n\n[formulae]\ngit = true\n",
+        )?;
+
+        let extras = read_settings_extras(&config_path)?;
+        assert_eq!(extras.greedy, Some(false));
+
+        let content =
+            generate_settings_toml(&["git".to_string()], &[], &HashMap::new(), extras.greedy)?;
+        assert!(content.contains(" UTC\n# Greedy casks: no\n\n[formulae]\n"));
+        assert_eq!(parse_greedy_setting(&content), Some(false));
+        assert_eq!(check_settings_toml(&content)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_extras_keep_greedy_setting() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        fs::write(
+            &config_path,
+            "# Brew Auto-Update Settings\n\nGenerated on: 2024-08-22 10:30:00 UTC\n\nGreedy casks: no\n\n## Formulae\n\n- [x] git\n",
+        )?;
+
+        let extras = read_settings_extras(&config_path)?;
+        assert_eq!(extras.greedy, Some(false));
+
+        let generated = generate_settings_content(
+            &["git".to_string()],
+            &[],
+            &read_existing_settings(&config_path)?,
+            &[],
+            None,
+            None,
+        );
+        let merged = merge_settings_extras(&generated, &extras);
+        assert!(merged.contains("UTC\n\nGreedy casks: no\n\n## Formulae\n"));
+        assert_eq!(parse_greedy_setting(&merged), Some(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_package_settings_with_args() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        fs::write(
+            &config_path,
+            "## Formulae\n\n- [x] git @2.40.0\n- [ ] python@3.11 {args: --force}\n\n## Casks\n\n- [x] docker {args: --no-quarantine --force}  <!-- outdated: 4.18.0 → 4.19.0 -->\n",
+        )?;
+
+        let settings = read_package_settings(&config_path)?;
+        assert_eq!(
+            settings.get("git"),
+            Some(&PackageSettings {
+                enabled: true,
+                extra_args: Vec::new(),
+            })
+        );
+        assert_eq!(
+            settings.get("python@3.11"),
+            Some(&PackageSettings {
+                enabled: false,
+                extra_args: vec!["--force".to_string()],
+            })
+        );
+        assert_eq!(
+            settings["docker"].extra_args,
+            vec!["--no-quarantine", "--force"]
+        );
+
+        // The plain reader sees the same names
+        let existing = read_existing_settings(&config_path)?;
+        assert_eq!(existing.get("docker"), Some(&true));
+        assert_eq!(existing.get("python@3.11"), Some(&false));
+        assert_eq!(read_recorded_versions(&config_path)?["git"], "2.40.0");
+
+        // Dump carries the args over, ahead of the outdated annotation
+        let extras = read_settings_extras(&config_path)?;
+        let merged = merge_settings_extras(
+            "- [x] docker  <!-- outdated: 4.18.0 → 4.19.0 -->\n- [x] git\n",
+            &extras,
+        );
+        assert_eq!(
+            merged,
+            "- [x] docker {args: --no-quarantine --force}  <!-- outdated: 4.18.0 → 4.19.0 -->\n- [x] git\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_settings_markdown() {
+        let content = "# Brew Auto-Update Settings\n\n## Formulae\n\n- [x] git\n  > keep on latest\n- [ ] vim\n  > pinned for plugins\n- [x] node\n\n## Casks\n\n- [x] docker\n- [ ] zoom\n\n## My Notes\n\n- [x] vim is great\n";
+        let formulae = vec!["git".to_string(), "node".to_string()];
+        let casks = vec!["docker".to_string()];
+
+        let pruned = prune_settings(content, ConfigFormat::Markdown, &formulae, &casks);
+        assert_eq!(pruned.removed, vec!["vim", "zoom"]);
+        assert_eq!(
+            pruned.content,
+            "# Brew Auto-Update Settings\n\n## Formulae\n\n- [x] git\n  > keep on latest\n- [x] node\n\n## Casks\n\n- [x] docker\n\n## My Notes\n\n- [x] vim is great\n"
+        );
+
+        let untouched = prune_settings(&pruned.content, ConfigFormat::Markdown, &formulae, &casks);
+        assert!(untouched.removed.is_empty());
+        assert_eq!(untouched.content, pruned.content);
+    }
+
+    #[test]
+    fn test_prune_settings_toml() {
+        let content = "# Brew Auto-Update Settings\n\n[formulae]\ngit = true\n\"python@3.11\" = false\n\n[casks]\ndocker = true\n";
+        let formulae = vec!["git".to_string()];
+        let casks = vec!["docker".to_string()];
+
+        let pruned = prune_settings(content, ConfigFormat::Toml, &formulae, &casks);
+        assert_eq!(pruned.removed, vec!["python@3.11"]);
+        assert_eq!(
+            pruned.content,
+            "# Brew Auto-Update Settings\n\n[formulae]\ngit = true\n\n[casks]\ndocker = true\n"
+        );
+    }
+
+    #[test]
+    fn test_find_settings_problems() {
+        let entries = vec![
+            (PackageType::Formula, "git".to_string()),
+            (PackageType::Formula, "docker".to_string()),
+            (PackageType::Formula, "vim".to_string()),
+            (PackageType::Cask, "firefox".to_string()),
+            (PackageType::Formula, "git".to_string()),
+            (PackageType::Cask, "git".to_string()),
+        ];
+        let formulae = vec!["git".to_string()];
+        let casks = vec!["docker".to_string(), "firefox".to_string()];
+
+        let problems = find_settings_problems(&entries, &formulae, &casks);
+        assert_eq!(
+            problems,
+            vec![
+                SettingsProblem::WrongSection {
+                    name: "docker".to_string(),
+                    listed: PackageType::Formula,
+                },
+                SettingsProblem::NotInstalled("vim".to_string()),
+                SettingsProblem::Duplicate("git".to_string()),
+            ]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            "docker is listed under Formulae but is installed as a cask"
+        );
+        assert!(find_settings_problems(&entries[..1], &formulae, &casks).is_empty());
+    }
+
+    #[test]
+    fn test_read_settings_entries_keeps_duplicates() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        fs::write(
+            &config_path,
+            "## Statistics\n\n- [x] not-a-package\n\n## Formulae\n\n- [x] git\n- [ ] git\n\n## Casks\n\n- [x] docker\n",
+        )?;
+
+        assert_eq!(
+            read_settings_entries(&config_path)?,
+            vec![
+                (PackageType::Formula, "git".to_string()),
+                (PackageType::Formula, "git".to_string()),
+                (PackageType::Cask, "docker".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_with_default_policies() {
+        let formulae = vec!["git".to_string(), "node".to_string()];
+        let casks = vec!["docker".to_string()];
+        let mut existing = HashMap::new();
+        existing.insert("git".to_string(), true);
+        existing.insert("docker".to_string(), false);
+
+        let disabled = settings_with_default(&formulae, &casks, &existing, false);
+        let content = generate_settings_content(&formulae, &casks, &disabled, &[], None, None);
+        assert!(content.contains("- [x] git\n"));
+        assert!(content.contains("- [ ] node\n"));
+        assert!(content.contains("- [ ] docker\n"));
+
+        let enabled = settings_with_default(&formulae, &casks, &existing, true);
+        let content = generate_settings_content(&formulae, &casks, &enabled, &[], None, None);
+        assert!(content.contains("- [x] git\n"));
+        assert!(content.contains("- [x] node\n"));
+        assert!(content.contains("- [ ] docker\n"));
+    }
+
+    #[test]
+    fn test_outdated_annotation_matches_tap_qualified_names() {
+        let outdated = vec![OutdatedPackage {
+            name: "homebrew/core/git".to_string(),
+            current_version: "2.40.0".to_string(),
+            available_version: "2.41.0".to_string(),
+            package_type: PackageType::Formula,
+        }];
+
+        assert_eq!(
+            outdated_annotation("git", PackageType::Formula, &outdated),
+            "  <!-- outdated: 2.40.0 → 2.41.0 -->"
+        );
+        assert_eq!(outdated_annotation("git", PackageType::Cask, &outdated), "");
+    }
+
+    #[test]
+    fn test_recorded_versions_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+
+        let formulae = vec!["git".to_string(), "python@3.11".to_string()];
+        let casks = vec!["docker".to_string()];
+        let mut existing = HashMap::new();
+        existing.insert("docker".to_string(), false);
+        let versions = HashMap::from([
+            ("git".to_string(), "2.40.0".to_string()),
+            ("python@3.11".to_string(), "3.11.5".to_string()),
+        ]);
+
+        let content =
+            generate_settings_content(&formulae, &casks, &existing, &[], None, Some(&versions));
+        assert!(content.contains("- [x] git @2.40.0\n"));
+        assert!(content.contains("- [x] python@3.11 @3.11.5\n"));
+        assert!(content.contains("- [ ] docker\n"));
+        fs::write(&config_path, &content)?;
+
+        assert_eq!(read_recorded_versions(&config_path)?, versions);
+        let settings = read_existing_settings(&config_path)?;
+        assert_eq!(settings.get("python@3.11"), Some(&true));
+        assert_eq!(settings.get("docker"), Some(&false));
+        assert_eq!(read_previous_packages(&config_path)?.0, formulae);
+
+        // Older files without versions still parse
+        fs::write(&config_path, "## Formulae\n\n- [x] git\n")?;
+        assert!(read_recorded_versions(&config_path)?.is_empty());
+        assert_eq!(
+            read_existing_settings(&config_path)?.get("git"),
+            Some(&true)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_recorded_versions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.md");
+        fs::write(
+            &config_path,
+            "## Formulae\n\n- [x] git @2.40.0 {args: --HEAD}\n- [x] python@3.11 @3.11.5\n- [x] node\n",
+        )?;
+
+        let upgraded = HashMap::from([
+            ("git".to_string(), "2.41.0".to_string()),
+            ("python@3.11".to_string(), "3.11.5".to_string()),
+            ("node".to_string(), "21.0.0".to_string()),
+        ]);
+        // python is already current and node has no recorded version
+        assert_eq!(update_recorded_versions(&config_path, &upgraded)?, 1);
+
+        let content = fs::read_to_string(&config_path)?;
+        assert!(content.contains("- [x] git @2.41.0 {args: --HEAD}\n"));
+        assert!(content.contains("- [x] python@3.11 @3.11.5\n"));
+        assert!(content.contains("- [x] node\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_settings_content() {
+        let content = "## Formulae\n\n- [x] git\n- [ ] node  <!-- outdated: 1 → 2 -->\n";
+        assert_eq!(check_settings_content(content).unwrap(), 2);
+
+        let error = check_settings_content("## Formulae\n\n- [x] git\n- [X] node\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("line 4"), "{}", error);
+
+        assert!(check_settings_content("- [x] \n").is_err());
+        assert!(check_settings_content("# Brew Auto-Update Settings\n").is_err());
+    }
+
+    #[test]
+    fn test_generate_brewfile() {
+        let formulae = vec!["node".to_string(), "git".to_string(), "wget".to_string()];
+        let casks = vec!["firefox".to_string(), "docker".to_string()];
+        let mut existing_settings = HashMap::new();
+        existing_settings.insert("git".to_string(), true);
+        existing_settings.insert("node".to_string(), true);
+        existing_settings.insert("wget".to_string(), false);
+        existing_settings.insert("docker".to_string(), true);
+        existing_settings.insert("firefox".to_string(), false);
+
+        let brewfile = generate_brewfile(&formulae, &casks, &existing_settings);
+
+        assert_eq!(brewfile, "brew \"git\"\nbrew \"node\"\ncask \"docker\"\n");
+    }
+
     #[test]
     fn test_generate_settings_content() {
         let formulae = vec!["git".to_string(), "node".to_string()];
@@ -145,7 +1409,8 @@ mod tests {
         existing_settings.insert("node".to_string(), false);
         existing_settings.insert("docker".to_string(), false);
 
-        let content = generate_settings_content(&formulae, &casks, &existing_settings, None);
+        let content =
+            generate_settings_content(&formulae, &casks, &existing_settings, &[], None, None);
 
         assert!(content.contains("# Brew Auto-Update Settings"));
         assert!(content.contains("## Formulae"));
@@ -156,6 +1421,124 @@ mod tests {
         assert!(content.contains("- [x] firefox")); // New package defaults to enabled
     }
 
+    #[test]
+    fn test_generate_settings_content_annotates_outdated() -> Result<()> {
+        let formulae = vec!["git".to_string(), "node".to_string()];
+        let casks = vec!["docker".to_string()];
+        let outdated = vec![
+            OutdatedPackage {
+                name: "git".to_string(),
+                current_version: "2.40.0".to_string(),
+                available_version: "2.41.0".to_string(),
+                package_type: PackageType::Formula,
+            },
+            OutdatedPackage {
+                name: "docker".to_string(),
+                current_version: "4.18.0".to_string(),
+                available_version: "4.19.0".to_string(),
+                package_type: PackageType::Cask,
+            },
+        ];
+
+        let content =
+            generate_settings_content(&formulae, &casks, &HashMap::new(), &outdated, None, None);
+
+        assert!(content.contains("- [x] git  <!-- outdated: 2.40.0 → 2.41.0 -->"));
+        assert!(content.contains("- [x] docker  <!-- outdated: 4.18.0 → 4.19.0 -->"));
+        assert!(content.contains("- [x] node\n"));
+
+        // Re-parsing must ignore the annotations
+        let temp_dir = TempDir::new()?;
+        let settings_path = temp_dir.path().join("settings.md");
+        std::fs::write(&settings_path, &content)?;
+
+        let settings = read_existing_settings(&settings_path)?;
+        assert_eq!(settings.get("git"), Some(&true));
+        assert_eq!(settings.get("docker"), Some(&true));
+        assert_eq!(settings.len(), 3);
+
+        let (previous_formulae, previous_casks) = read_previous_packages(&settings_path)?;
+        assert!(previous_formulae.contains(&"git".to_string()));
+        assert_eq!(previous_casks, vec!["docker".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_settings_json() -> Result<()> {
+        let formulae = vec!["node".to_string(), "git".to_string()];
+        let casks = vec!["docker".to_string()];
+        let mut existing_settings = HashMap::new();
+        existing_settings.insert("node".to_string(), false);
+
+        let json = generate_settings_json(&formulae, &casks, &existing_settings)?;
+        let document: SettingsDocument = serde_json::from_str(&json)?;
+
+        assert_eq!(document.formulae.get("git"), Some(&true));
+        assert_eq!(document.formulae.get("node"), Some(&false));
+        assert_eq!(document.casks.get("docker"), Some(&true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_generated_timestamp() {
+        let expected = DateTime::parse_from_rfc3339("2024-08-22T10:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_generated_timestamp(
+                "# Brew Auto-Update Settings\n\nGenerated on: 2024-08-22 10:30:00 UTC\n"
+            ),
+            Some(expected)
+        );
+        // The TOML format writes it as a comment
+        assert_eq!(
+            parse_generated_timestamp("# Generated on: 2024-08-22 10:30:00 UTC\n\n[formulae]\n"),
+            Some(expected)
+        );
+
+        assert_eq!(
+            parse_generated_timestamp("## Formulae\n\n- [x] git\n"),
+            None
+        );
+        assert_eq!(parse_generated_timestamp("Generated on: yesterday\n"), None);
+    }
+
+    #[test]
+    fn test_generate_snapshot_json() -> Result<()> {
+        let generated_on =
+            DateTime::parse_from_rfc3339("2024-08-22T10:30:00Z")?.with_timezone(&Utc);
+        let json = generate_snapshot_json(
+            vec![
+                (
+                    "git".to_string(),
+                    "2.41.0".to_string(),
+                    PackageType::Formula,
+                ),
+                (
+                    "docker".to_string(),
+                    "4.19.0".to_string(),
+                    PackageType::Cask,
+                ),
+            ],
+            generated_on,
+        )?;
+
+        let value: serde_json::Value = serde_json::from_str(&json)?;
+        assert_eq!(value["generated_on"], "2024-08-22 10:30:00 UTC");
+        assert_eq!(
+            value["packages"][0],
+            serde_json::json!({"name": "git", "version": "2.41.0", "type": "formula"})
+        );
+        assert_eq!(value["packages"][1]["type"], "cask");
+
+        let document: SnapshotDocument = serde_json::from_str(&json)?;
+        assert_eq!(document.packages.len(), 2);
+
+        Ok(())
+    }
+
     #[test]
     fn test_read_existing_settings() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -194,7 +1577,7 @@ Generated on: 2024-08-22 10:30:00 UTC
         // Simulate development environment
         std::env::set_var("CARGO_MANIFEST_DIR", "/some/path");
 
-        let path = get_config_path(&None)?;
+        let path = get_config_path(&AppContext::default(), &None, None)?;
         assert_eq!(path, PathBuf::from("./brew-settings.md"));
 
         std::env::remove_var("CARGO_MANIFEST_DIR");
@@ -237,18 +1620,123 @@ Generated on: 2024-08-22 10:30:00 UTC
         Ok(())
     }
 
+    #[test]
+    fn test_set_package_enabled() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let settings_path = temp_dir.path().join("settings.md");
+        std::fs::write(
+            &settings_path,
+            "## Formulae\n\n- [x] git  <!-- outdated: 2.40.0 → 2.41.0 -->\n- [x] node\n",
+        )?;
+
+        assert!(set_package_enabled(&settings_path, "git", false)?);
+        assert!(!set_package_enabled(&settings_path, "missing", false)?);
+
+        let content = std::fs::read_to_string(&settings_path)?;
+        assert!(content.contains("- [ ] git  <!-- outdated: 2.40.0 → 2.41.0 -->\n"));
+        assert!(content.contains("- [x] node\n"));
+
+        let settings = read_existing_settings(&settings_path)?;
+        assert_eq!(settings.get("git"), Some(&false));
+        assert_eq!(settings.get("node"), Some(&true));
+
+        Ok(())
+    }
+
     #[test]
     fn test_extract_package_name() {
         assert_eq!(extract_package_name("- [x] git"), Some("git".to_string()));
         assert_eq!(extract_package_name("- [ ] node"), Some("node".to_string()));
+        assert_eq!(
+            extract_package_name("- [x] git  <!-- outdated: 2.40.0 → 2.41.0 -->"),
+            Some("git".to_string())
+        );
         assert_eq!(extract_package_name("## Formulae"), None);
         assert_eq!(extract_package_name("random text"), None);
     }
 
+    #[test]
+    fn test_get_config_path_format_must_match_extension() {
+        let custom_path = Some("/custom/path/settings.md".to_string());
+        let error = get_config_path(
+            &AppContext::default(),
+            &custom_path,
+            Some(ConfigFormat::Toml),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("--config-format toml"));
+    }
+
+    #[test]
+    fn test_toml_settings_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("settings.toml");
+
+        let formulae = vec!["git".to_string(), "python@3.11".to_string()];
+        let casks = vec!["docker".to_string()];
+        let mut existing = HashMap::new();
+        existing.insert("python@3.11".to_string(), false);
+
+        let content = generate_settings_toml(&formulae, &casks, &existing, None)?;
+        assert!(content.contains("[formulae]\ngit = true\n\"python@3.11\" = false\n"));
+        fs::write(&config_path, &content)?;
+
+        let settings = read_existing_settings(&config_path)?;
+        assert_eq!(settings.get("git"), Some(&true));
+        assert_eq!(settings.get("python@3.11"), Some(&false));
+        assert_eq!(settings.get("docker"), Some(&true));
+        assert_eq!(check_settings_toml(&content)?, 3);
+
+        let (previous_formulae, previous_casks) = read_previous_packages(&config_path)?;
+        assert_eq!(previous_formulae, formulae);
+        assert_eq!(previous_casks, casks);
+
+        assert!(set_package_enabled(&config_path, "docker", false)?);
+        assert!(!set_package_enabled(&config_path, "missing", false)?);
+        let updated = fs::read_to_string(&config_path)?;
+        assert!(updated.starts_with("# Brew Auto-Update Settings\n"));
+        assert_eq!(
+            read_existing_settings(&config_path)?.get("docker"),
+            Some(&false)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_config_path_precedence() -> Result<()> {
+        let context = AppContext::default();
+        let from_env = resolve_config_path(&context, None, Some("/env/settings.md"), None)?;
+        assert_eq!(from_env, PathBuf::from("/env/settings.md"));
+
+        let from_flag = resolve_config_path(
+            &context,
+            Some("/flag/settings.md"),
+            Some("/env/settings.md"),
+            None,
+        )?;
+        assert_eq!(from_flag, PathBuf::from("/flag/settings.md"));
+
+        // An empty variable counts as unset
+        let fallback = resolve_config_path(&context, None, Some(""), None)?;
+        assert_eq!(fallback, resolve_config_path(&context, None, None, None)?);
+        assert_ne!(fallback, PathBuf::from(""));
+
+        assert!(resolve_config_path(
+            &context,
+            None,
+            Some("/env/settings.md"),
+            Some(ConfigFormat::Toml)
+        )
+        .is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_get_config_path_custom() -> Result<()> {
         let custom_path = Some("/custom/path/settings.md".to_string());
-        let path = get_config_path(&custom_path)?;
+        let path = get_config_path(&AppContext::default(), &custom_path, None)?;
         assert_eq!(path, PathBuf::from("/custom/path/settings.md"));
         Ok(())
     }
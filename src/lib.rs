@@ -2,55 +2,194 @@ pub mod brew;
 pub mod cli;
 pub mod commands;
 pub mod config;
+pub mod order;
+pub mod output;
 pub mod stats;
 pub mod ui;
 pub mod utils;
+pub mod validate;
+pub mod version;
+pub mod warnings;
 
 // Re-export main types for convenience
-pub use brew::{BrewExecutor, OutdatedPackage, PackageType};
-pub use cli::{Cli, Commands};
+pub use brew::{BrewExecutor, OutdatedPackage, PackageInfo, PackageType};
+pub use cli::{
+    Cli, Commands, DumpArgs, ExportArgs, HistoryArgs, ListArgs, SnapshotArgs, StatsArgs,
+    UpgradeArgs,
+};
+pub use commands::{compute_dump, compute_upgradeable, greedy_casks, DumpResult};
 pub use config::{
-    generate_settings_content, get_config_path, read_existing_settings, read_previous_packages,
+    backup_settings, check_settings_content, check_settings_toml, generate_brewfile,
+    generate_settings_content, generate_settings_json, generate_settings_toml,
+    generate_snapshot_json, get_config_path, parse_generated_timestamp, parse_greedy_setting,
+    prune_settings, read_existing_settings, read_existing_settings_toml, read_previous_packages,
+    read_recorded_versions, set_package_enabled, ConfigFormat, ExportFormat, PrunedSettings,
+    SnapshotDocument, SnapshotEntry, CONFIG_ENV_VAR,
+};
+pub use order::{order_by_dependencies, sort_for_selection, SelectionSort, UpgradeOrder};
+pub use output::{CapturingSink, OutputSink, QuietSink, ResultOnlySink, StdoutSink};
+pub use stats::{PackageStats, StatsFormat};
+pub use utils::{
+    append_log, get_last_session, parse_cleanup_freed, parse_log_sessions, read_log_with_rotations,
+    write_files_atomically, AppContext, AppFile, JsonLogEntry, LogFormat, LogLevel, Session,
+    DEFAULT_PROFILE,
 };
-pub use utils::{get_log_path, log_operation};
+pub use validate::{validate_config, ResolvedConfig};
+pub use version::{
+    compare_versions, is_downgrade, is_newer, version_jump_kind, MinBump, VersionJump,
+};
+pub use warnings::{WarningId, Warnings};
 
 use anyhow::Result;
 use clap::Parser;
 
+/// Exit code when at least one upgrade failed (also used for any other error).
+pub const EXIT_UPGRADE_FAILED: u8 = 1;
+/// Exit code when brew is missing or doesn't run.
+pub const EXIT_BREW_UNAVAILABLE: u8 = 2;
+
+/// The process exit code for an error returned by `run`.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    if error.downcast_ref::<brew::BrewUnavailable>().is_some() {
+        EXIT_BREW_UNAVAILABLE
+    } else {
+        EXIT_UPGRADE_FAILED
+    }
+}
+
 pub fn run() -> Result<()> {
     let cli = Cli::parse();
-    let executor = create_executor();
+    validate_config(&ResolvedConfig::from_cli(&cli))?;
 
-    executor.verify_installation()?;
+    let context = AppContext::new(
+        cli.config_dir.as_ref().map(std::path::PathBuf::from),
+        &cli.profile,
+        cli.log_level,
+        cli.log_format,
+    )?;
+    let executor = create_executor(&context, &cli);
+    let mut out: Box<dyn OutputSink> = if cli.json {
+        Box::new(ResultOnlySink)
+    } else {
+        Box::new(StdoutSink)
+    };
+    if cli.quiet {
+        out = Box::new(QuietSink::new(out));
+    }
+    if let Some(warning) = context.config_dir_warning() {
+        Warnings::new(out.as_ref(), &cli.suppress).emit(WarningId::ConfigDirFallback, &warning);
+    }
+
+    // doctor reports a missing brew itself; completions, list, paths and history never need it
+    if !matches!(
+        cli.command,
+        Commands::Doctor
+            | Commands::Completions { .. }
+            | Commands::List(_)
+            | Commands::Paths
+            | Commands::History(_)
+    ) {
+        executor
+            .verify_installation()
+            .map_err(brew::BrewUnavailable)?;
+    }
 
     match cli.command {
-        Commands::Dump => {
-            println!("Running dump command...");
+        Commands::Dump(ref args) => {
+            out.info("Running dump command...");
             if cli.dry_run {
-                println!("(dry run mode)");
+                out.info("(dry run mode)");
             }
-            commands::dump_command(&cli, &*executor)?;
+            commands::dump_command(&context, &cli, args, &*executor, &*out)?;
         }
-        Commands::Upgrade => {
-            println!("Running upgrade command...");
+        Commands::Upgrade(ref args) => {
+            out.info("Running upgrade command...");
             if cli.dry_run {
-                println!("(dry run mode)");
+                out.info("(dry run mode)");
             }
-            commands::upgrade_command(&cli, &*executor)?;
+            commands::upgrade_command(&context, &cli, args, &*executor, &*out)?;
+        }
+        Commands::Stats(ref args) => {
+            commands::stats_command(&context, &cli, args, &*executor, &*out)?;
+        }
+        Commands::List(ref args) => {
+            commands::list_command(&context, &cli, args, &*out)?;
+        }
+        Commands::Validate => {
+            commands::validate_command(&context, &cli, &*executor, &*out)?;
+        }
+        Commands::Clean => {
+            commands::clean_command(&context, &cli, &*executor, &*out)?;
+        }
+        Commands::Diff => {
+            commands::diff_command(&context, &cli, &*executor, &*out)?;
+        }
+        Commands::Drift => {
+            commands::drift_command(&context, &cli, &*executor, &*out)?;
+        }
+        Commands::Rollback => {
+            commands::rollback_command(&context, &*executor, &*out)?;
+        }
+        Commands::History(ref args) => {
+            commands::history_command(&context, args, &*out)?;
+        }
+        Commands::Export(ref args) => {
+            commands::export_command(&context, &cli, args, &*out)?;
+        }
+        Commands::Snapshot(ref args) => {
+            commands::snapshot_command(&cli, args, &*executor, &*out)?;
+        }
+        Commands::Doctor => {
+            commands::doctor_command(&context, &cli, &*executor, &*out)?;
+        }
+        Commands::Paths => {
+            commands::paths_command(&context, &cli, &*out)?;
+        }
+        Commands::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut <Cli as clap::CommandFactory>::command(),
+                "brew-update-helper",
+                &mut std::io::stdout(),
+            );
+        }
+        Commands::Selftest => {
+            out.info("Running self-test...");
+            commands::selftest_command(&context, &*executor, &*out)?;
         }
     }
 
     Ok(())
 }
 
-fn create_executor() -> Box<dyn BrewExecutor> {
-    // Use mock executor in CI environments or when explicitly requested
-    if std::env::var("CI").is_ok()
+/// `Sync` so `upgrade --parallel-fetch` can share the executor across threads.
+fn create_executor(context: &AppContext, cli: &Cli) -> Box<dyn BrewExecutor + Sync> {
+    // Use mock executor in CI environments, for the self-test, or when explicitly requested
+    if matches!(cli.command, Commands::Selftest)
+        || std::env::var("CI").is_ok()
         || std::env::var("GITHUB_ACTIONS").is_ok()
         || std::env::var("MOCK_BREW").is_ok()
     {
-        return Box::new(brew::MockBrewExecutor::new());
+        // Lets CLI tests exercise the failure path without a real brew
+        let failing = std::env::var("MOCK_BREW_FAIL_UPGRADES")
+            .map(|names| names.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        return Box::new(brew::MockBrewExecutor::new().with_failing_packages(failing));
     }
 
-    Box::new(brew::SystemBrewExecutor)
+    let (upgrade_timeout, no_sudo) = match &cli.command {
+        Commands::Upgrade(args) => (
+            args.timeout.map(std::time::Duration::from_secs),
+            args.no_sudo,
+        ),
+        _ => (None, false),
+    };
+
+    Box::new(
+        brew::SystemBrewExecutor::new(cli.brew_path.as_ref().map(std::path::PathBuf::from))
+            .with_no_auto_update(cli.no_update)
+            .with_greedy(commands::greedy_casks(context, cli))
+            .with_upgrade_timeout(upgrade_timeout)
+            .with_no_sudo(no_sudo),
+    )
 }
@@ -1,5 +1,11 @@
-use anyhow::Result;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    brew_update_helper::run()
+fn main() -> ExitCode {
+    match brew_update_helper::run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            ExitCode::from(brew_update_helper::exit_code(&error))
+        }
+    }
 }
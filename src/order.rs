@@ -0,0 +1,238 @@
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+
+use crate::brew::OutdatedPackage;
+use crate::version::{version_jump_kind, VersionJump};
+
+/// How the selection list is ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SelectionSort {
+    /// Formulae above casks, alphabetically within each
+    Type,
+    /// Alphabetically, formulae and casks mixed
+    #[default]
+    Name,
+    /// Biggest version jump first (major, then minor, then patch)
+    Age,
+}
+
+/// Rank of a version jump for `SelectionSort::Age`; lower sorts first, and
+/// versions we can't compare go last.
+fn jump_rank(pkg: &OutdatedPackage) -> u8 {
+    match version_jump_kind(&pkg.current_version, &pkg.available_version) {
+        VersionJump::Major => 0,
+        VersionJump::Minor => 1,
+        VersionJump::Patch => 2,
+        VersionJump::Unknown => 3,
+    }
+}
+
+/// Sort the selection list, so it doesn't depend on the order brew printed
+/// packages in. Ties always fall back to the name.
+pub fn sort_for_selection(packages: &mut [&OutdatedPackage], sort: SelectionSort) {
+    match sort {
+        SelectionSort::Type => {
+            packages.sort_by(|a, b| (&a.package_type, &a.name).cmp(&(&b.package_type, &b.name)))
+        }
+        SelectionSort::Name => packages.sort_by(|a, b| a.name.cmp(&b.name)),
+        SelectionSort::Age => {
+            packages.sort_by(|a, b| (jump_rank(a), &a.name).cmp(&(jump_rank(b), &b.name)))
+        }
+    }
+}
+
+/// Order in which selected packages are upgraded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum UpgradeOrder {
+    /// Upgrade in the order the packages were listed
+    #[default]
+    Listed,
+    /// Upgrade dependencies before the packages that depend on them
+    DepsFirst,
+}
+
+/// Topologically sort `packages` so that any package appearing in another's
+/// dependency list is upgraded first. Only dependencies within `packages`
+/// matter; the original order is kept wherever the graph allows. Packages
+/// caught in a dependency cycle are appended in their original order.
+pub fn order_by_dependencies(
+    packages: &[OutdatedPackage],
+    deps_map: &HashMap<String, Vec<String>>,
+) -> Vec<OutdatedPackage> {
+    let index_of: HashMap<&str, usize> = packages
+        .iter()
+        .enumerate()
+        .map(|(i, pkg)| (pkg.name.as_str(), i))
+        .collect();
+
+    // Number of distinct not-yet-placed dependencies for each package; a
+    // dependency listed twice is still only placed (and decremented) once
+    let mut pending: Vec<usize> = packages
+        .iter()
+        .map(|pkg| {
+            deps_map
+                .get(&pkg.name)
+                .map(|deps| {
+                    deps.iter()
+                        .map(String::as_str)
+                        .filter(|dep| *dep != pkg.name && index_of.contains_key(dep))
+                        .collect::<HashSet<_>>()
+                        .len()
+                })
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut placed = vec![false; packages.len()];
+    let mut ordered = Vec::with_capacity(packages.len());
+
+    // Always pick the earliest ready package to keep the result stable
+    while let Some(next) = (0..packages.len()).find(|&i| !placed[i] && pending[i] == 0) {
+        placed[next] = true;
+        ordered.push(packages[next].clone());
+
+        let name = &packages[next].name;
+        for (i, pkg) in packages.iter().enumerate() {
+            if placed[i] {
+                continue;
+            }
+            if let Some(deps) = deps_map.get(&pkg.name) {
+                if deps.iter().any(|dep| dep == name) {
+                    pending[i] -= 1;
+                }
+            }
+        }
+    }
+
+    // Cycle-safe fallback: whatever is left keeps its original order
+    for (i, pkg) in packages.iter().enumerate() {
+        if !placed[i] {
+            ordered.push(pkg.clone());
+        }
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brew::PackageType;
+
+    fn selection_packages() -> Vec<OutdatedPackage> {
+        let package = |name: &str, current: &str, available: &str, package_type| OutdatedPackage {
+            name: name.to_string(),
+            current_version: current.to_string(),
+            available_version: available.to_string(),
+            package_type,
+        };
+        vec![
+            package("zoom", "5.0.0", "6.0.0", PackageType::Cask),
+            package("wget", "1.21.3", "1.21.4", PackageType::Formula),
+            package("docker", "latest", "latest", PackageType::Cask),
+            package("git", "2.40.0", "2.41.0", PackageType::Formula),
+        ]
+    }
+
+    fn sorted_names(sort: SelectionSort) -> Vec<String> {
+        let owned = selection_packages();
+        let mut packages: Vec<&OutdatedPackage> = owned.iter().collect();
+        sort_for_selection(&mut packages, sort);
+        packages.iter().map(|pkg| pkg.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_sort_for_selection_by_type() {
+        assert_eq!(
+            sorted_names(SelectionSort::Type),
+            vec!["git", "wget", "docker", "zoom"]
+        );
+    }
+
+    #[test]
+    fn test_sort_for_selection_by_name() {
+        assert_eq!(
+            sorted_names(SelectionSort::Name),
+            vec!["docker", "git", "wget", "zoom"]
+        );
+    }
+
+    #[test]
+    fn test_sort_for_selection_by_age() {
+        assert_eq!(
+            sorted_names(SelectionSort::Age),
+            vec!["zoom", "git", "wget", "docker"]
+        );
+    }
+
+    fn formula(name: &str) -> OutdatedPackage {
+        OutdatedPackage {
+            name: name.to_string(),
+            current_version: "1.0".to_string(),
+            available_version: "1.1".to_string(),
+            package_type: PackageType::Formula,
+        }
+    }
+
+    fn names(packages: &[OutdatedPackage]) -> Vec<&str> {
+        packages.iter().map(|pkg| pkg.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_order_by_dependencies_puts_deps_first() {
+        let packages = vec![formula("node"), formula("git"), formula("openssl@3")];
+        let mut deps = HashMap::new();
+        deps.insert(
+            "node".to_string(),
+            vec!["openssl@3".to_string(), "icu4c".to_string()],
+        );
+        deps.insert("git".to_string(), vec!["pcre2".to_string()]);
+
+        let ordered = order_by_dependencies(&packages, &deps);
+        assert_eq!(names(&ordered), vec!["git", "openssl@3", "node"]);
+    }
+
+    #[test]
+    fn test_order_by_dependencies_transitive_chain() {
+        let packages = vec![formula("a"), formula("b"), formula("c")];
+        let mut deps = HashMap::new();
+        deps.insert("a".to_string(), vec!["b".to_string()]);
+        deps.insert("b".to_string(), vec!["c".to_string()]);
+
+        let ordered = order_by_dependencies(&packages, &deps);
+        assert_eq!(names(&ordered), vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_order_by_dependencies_duplicate_deps() {
+        let packages = vec![formula("yarn"), formula("node"), formula("openssl@3")];
+        let mut deps = HashMap::new();
+        deps.insert("yarn".to_string(), vec!["node".to_string()]);
+        deps.insert(
+            "node".to_string(),
+            vec!["openssl@3".to_string(), "openssl@3".to_string()],
+        );
+
+        // Counting openssl@3 twice would leave node stuck behind the cycle fallback
+        let ordered = order_by_dependencies(&packages, &deps);
+        assert_eq!(names(&ordered), vec!["openssl@3", "node", "yarn"]);
+    }
+
+    #[test]
+    fn test_order_by_dependencies_without_deps_keeps_order() {
+        let packages = vec![formula("b"), formula("a")];
+        let ordered = order_by_dependencies(&packages, &HashMap::new());
+        assert_eq!(names(&ordered), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_order_by_dependencies_cycle_falls_back_to_original_order() {
+        let packages = vec![formula("x"), formula("a"), formula("b")];
+        let mut deps = HashMap::new();
+        deps.insert("a".to_string(), vec!["b".to_string()]);
+        deps.insert("b".to_string(), vec!["a".to_string()]);
+
+        let ordered = order_by_dependencies(&packages, &deps);
+        assert_eq!(names(&ordered), vec!["x", "a", "b"]);
+    }
+}
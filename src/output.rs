@@ -0,0 +1,240 @@
+use indicatif::ProgressBar;
+use std::cell::RefCell;
+
+/// Destination for user-facing messages produced by the commands.
+pub trait OutputSink {
+    /// Progress and informational chatter.
+    fn info(&self, message: &str);
+    /// Something went wrong but the command can carry on.
+    fn warn(&self, message: &str);
+    /// A failure the user needs to see.
+    fn error(&self, message: &str);
+    /// The outcome of a command.
+    fn result(&self, message: &str);
+}
+
+/// Writes info and results to stdout, warnings and errors to stderr.
+pub struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn info(&self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn warn(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn error(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn result(&self, message: &str) {
+        println!("{}", message);
+    }
+}
+
+/// Keeps stdout for results only, so machine-readable output can be piped;
+/// everything else goes to stderr.
+pub struct ResultOnlySink;
+
+impl OutputSink for ResultOnlySink {
+    fn info(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn warn(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn error(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn result(&self, message: &str) {
+        println!("{}", message);
+    }
+}
+
+/// Hides a progress bar while writing, so messages don't tear through it.
+pub struct ProgressSink<'a> {
+    bar: &'a ProgressBar,
+    inner: &'a dyn OutputSink,
+}
+
+impl<'a> ProgressSink<'a> {
+    pub fn new(bar: &'a ProgressBar, inner: &'a dyn OutputSink) -> Self {
+        Self { bar, inner }
+    }
+}
+
+impl OutputSink for ProgressSink<'_> {
+    fn info(&self, message: &str) {
+        self.bar.suspend(|| self.inner.info(message));
+    }
+
+    fn warn(&self, message: &str) {
+        self.bar.suspend(|| self.inner.warn(message));
+    }
+
+    fn error(&self, message: &str) {
+        self.bar.suspend(|| self.inner.error(message));
+    }
+
+    fn result(&self, message: &str) {
+        self.bar.suspend(|| self.inner.result(message));
+    }
+}
+
+/// Drops informational chatter for `--quiet`, passing everything else on.
+pub struct QuietSink {
+    inner: Box<dyn OutputSink>,
+}
+
+impl QuietSink {
+    pub fn new(inner: Box<dyn OutputSink>) -> Self {
+        Self { inner }
+    }
+}
+
+impl OutputSink for QuietSink {
+    fn info(&self, _message: &str) {}
+
+    fn warn(&self, message: &str) {
+        self.inner.warn(message);
+    }
+
+    fn error(&self, message: &str) {
+        self.inner.error(message);
+    }
+
+    fn result(&self, message: &str) {
+        self.inner.result(message);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputLevel {
+    Info,
+    Warn,
+    Error,
+    Result,
+}
+
+/// Records every message in memory so callers can inspect them afterwards.
+#[derive(Default)]
+pub struct CapturingSink {
+    messages: RefCell<Vec<(OutputLevel, String)>>,
+}
+
+impl CapturingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages(&self) -> Vec<(OutputLevel, String)> {
+        self.messages.borrow().clone()
+    }
+
+    pub fn messages_at(&self, level: OutputLevel) -> Vec<String> {
+        self.messages
+            .borrow()
+            .iter()
+            .filter(|(l, _)| *l == level)
+            .map(|(_, message)| message.clone())
+            .collect()
+    }
+
+    pub fn contains(&self, level: OutputLevel, needle: &str) -> bool {
+        self.messages
+            .borrow()
+            .iter()
+            .any(|(l, message)| *l == level && message.contains(needle))
+    }
+
+    fn push(&self, level: OutputLevel, message: &str) {
+        self.messages
+            .borrow_mut()
+            .push((level, message.to_string()));
+    }
+}
+
+impl OutputSink for CapturingSink {
+    fn info(&self, message: &str) {
+        self.push(OutputLevel::Info, message);
+    }
+
+    fn warn(&self, message: &str) {
+        self.push(OutputLevel::Warn, message);
+    }
+
+    fn error(&self, message: &str) {
+        self.push(OutputLevel::Error, message);
+    }
+
+    fn result(&self, message: &str) {
+        self.push(OutputLevel::Result, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capturing_sink_records_levels() {
+        let sink = CapturingSink::new();
+        sink.info("checking");
+        sink.warn("careful");
+        sink.error("broken");
+        sink.result("done");
+
+        assert_eq!(sink.messages().len(), 4);
+        assert_eq!(sink.messages_at(OutputLevel::Warn), vec!["careful"]);
+        assert!(sink.contains(OutputLevel::Result, "done"));
+        assert!(!sink.contains(OutputLevel::Info, "done"));
+    }
+
+    #[test]
+    fn test_quiet_sink_drops_info_only() {
+        struct Shared(std::rc::Rc<CapturingSink>);
+        impl OutputSink for Shared {
+            fn info(&self, message: &str) {
+                self.0.info(message);
+            }
+            fn warn(&self, message: &str) {
+                self.0.warn(message);
+            }
+            fn error(&self, message: &str) {
+                self.0.error(message);
+            }
+            fn result(&self, message: &str) {
+                self.0.result(message);
+            }
+        }
+
+        let captured = std::rc::Rc::new(CapturingSink::new());
+        let sink = QuietSink::new(Box::new(Shared(captured.clone())));
+        sink.info("checking");
+        sink.warn("careful");
+        sink.error("broken");
+        sink.result("done");
+
+        assert!(captured.messages_at(OutputLevel::Info).is_empty());
+        assert_eq!(captured.messages().len(), 3);
+    }
+
+    #[test]
+    fn test_progress_sink_passes_everything_through() {
+        let captured = CapturingSink::new();
+        let bar = ProgressBar::hidden();
+        let sink = ProgressSink::new(&bar, &captured);
+        sink.info("checking");
+        sink.warn("careful");
+        sink.error("broken");
+        sink.result("done");
+
+        assert_eq!(captured.messages().len(), 4);
+        assert!(captured.contains(OutputLevel::Info, "checking"));
+    }
+}
@@ -1,9 +1,22 @@
 use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
 use std::collections::HashMap;
 
-use crate::brew::{BrewExecutor, PackageType};
+use crate::brew::{BrewExecutor, OutdatedPackage, PackageType};
+
+/// How `stats` renders its numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StatsFormat {
+    /// The same section `dump` writes into the settings file
+    #[default]
+    Markdown,
+    Json,
+    /// `key: value` lines, for grep and monitoring scripts
+    Plain,
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PackageStats {
     pub total_formulae: usize,
     pub total_casks: usize,
@@ -20,14 +33,25 @@ pub struct PackageStats {
     pub changes: PackageChanges,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SystemInfo {
     pub os_version: String,
     pub architecture: String,
     pub homebrew_prefix: String,
 }
 
-#[derive(Debug, Clone)]
+impl SystemInfo {
+    /// Placeholder for when the system can't be inspected at all.
+    pub fn unknown() -> Self {
+        Self {
+            os_version: "Unknown".to_string(),
+            architecture: "Unknown".to_string(),
+            homebrew_prefix: "Unknown".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct PackageChanges {
     pub added_formulae: usize,
     pub removed_formulae: usize,
@@ -35,12 +59,22 @@ pub struct PackageChanges {
     pub removed_casks: usize,
 }
 
+/// Package names added or removed since the last dump.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PackageDiff {
+    pub added_formulae: Vec<String>,
+    pub removed_formulae: Vec<String>,
+    pub added_casks: Vec<String>,
+    pub removed_casks: Vec<String>,
+}
+
 impl PackageStats {
     pub fn collect(
         executor: &dyn BrewExecutor,
         current_formulae: &[String],
         current_casks: &[String],
         existing_settings: &HashMap<String, bool>,
+        outdated_packages: &[OutdatedPackage],
         previous_formulae: Option<&[String]>,
         previous_casks: Option<&[String]>,
     ) -> Result<Self> {
@@ -55,20 +89,23 @@ impl PackageStats {
             count_enabled_disabled(current_casks, existing_settings);
 
         // Get outdated package counts
-        let outdated_packages = executor.get_outdated_packages().unwrap_or_default();
         let outdated_formulae = outdated_packages
             .iter()
-            .filter(|pkg| matches!(pkg.package_type, PackageType::Formula))
+            .filter(|pkg| pkg.package_type == PackageType::Formula)
             .count();
         let outdated_casks = outdated_packages
             .iter()
-            .filter(|pkg| matches!(pkg.package_type, PackageType::Cask))
+            .filter(|pkg| pkg.package_type == PackageType::Cask)
             .count();
         let total_outdated = outdated_formulae + outdated_casks;
 
-        // Collect system information
-        let homebrew_version = executor.get_version()?;
-        let system_info = executor.get_system_info()?;
+        // Collect system information; missing tools only blank out these fields
+        let homebrew_version = executor
+            .get_version()
+            .unwrap_or_else(|_| "Unknown".to_string());
+        let system_info = executor
+            .get_system_info()
+            .unwrap_or_else(|_| SystemInfo::unknown());
 
         // Calculate package changes
         let changes = calculate_package_changes(
@@ -168,9 +205,54 @@ impl PackageStats {
             content.push('\n');
         }
 
+        content.push_str(&format!("- **Next Step**: {}\n", recommend_action(self)));
+
         content.push('\n');
         content
     }
+
+    pub fn format_as_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// One `key: value` line per number, using the JSON field names.
+    pub fn format_as_plain(&self) -> String {
+        let lines: [(&str, String); 18] = [
+            ("total_packages", self.total_packages.to_string()),
+            ("total_formulae", self.total_formulae.to_string()),
+            ("total_casks", self.total_casks.to_string()),
+            ("enabled_formulae", self.enabled_formulae.to_string()),
+            ("enabled_casks", self.enabled_casks.to_string()),
+            ("disabled_formulae", self.disabled_formulae.to_string()),
+            ("disabled_casks", self.disabled_casks.to_string()),
+            ("total_outdated", self.total_outdated.to_string()),
+            ("outdated_formulae", self.outdated_formulae.to_string()),
+            ("outdated_casks", self.outdated_casks.to_string()),
+            ("homebrew_version", self.homebrew_version.clone()),
+            ("os_version", self.system_info.os_version.clone()),
+            ("architecture", self.system_info.architecture.clone()),
+            ("homebrew_prefix", self.system_info.homebrew_prefix.clone()),
+            ("added_formulae", self.changes.added_formulae.to_string()),
+            (
+                "removed_formulae",
+                self.changes.removed_formulae.to_string(),
+            ),
+            ("added_casks", self.changes.added_casks.to_string()),
+            ("removed_casks", self.changes.removed_casks.to_string()),
+        ];
+        lines
+            .iter()
+            .map(|(key, value)| format!("{}: {}\n", key, value))
+            .collect()
+    }
+
+    pub fn format(&self, format: StatsFormat) -> Result<String> {
+        match format {
+            StatsFormat::Markdown => Ok(self.format_as_markdown()),
+            StatsFormat::Json => self.format_as_json(),
+            StatsFormat::Plain => Ok(self.format_as_plain()),
+        }
+    }
 }
 
 impl PackageChanges {
@@ -182,6 +264,26 @@ impl PackageChanges {
     }
 }
 
+/// Suggest a single follow-up action based on the collected numbers.
+pub fn recommend_action(stats: &PackageStats) -> String {
+    let total_disabled = stats.disabled_formulae + stats.disabled_casks;
+
+    if stats.total_outdated > 0 {
+        format!(
+            "Run `brew-update-helper upgrade` to update {} package{}",
+            stats.total_outdated,
+            if stats.total_outdated == 1 { "" } else { "s" }
+        )
+    } else if stats.total_packages > 0 && total_disabled * 2 > stats.total_packages {
+        format!(
+            "{} of {} packages are disabled for auto-update; consider reviewing your selections",
+            total_disabled, stats.total_packages
+        )
+    } else {
+        "Everything is current, nice work!".to_string()
+    }
+}
+
 fn count_enabled_disabled(packages: &[String], settings: &HashMap<String, bool>) -> (usize, usize) {
     let mut enabled = 0;
     let mut disabled = 0;
@@ -197,42 +299,60 @@ fn count_enabled_disabled(packages: &[String], settings: &HashMap<String, bool>)
     (enabled, disabled)
 }
 
-fn calculate_package_changes(
+impl PackageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_formulae.is_empty()
+            && self.removed_formulae.is_empty()
+            && self.added_casks.is_empty()
+            && self.removed_casks.is_empty()
+    }
+}
+
+/// Names present in one list but not the other, sorted, per package type.
+pub fn diff_packages(
     current_formulae: &[String],
     current_casks: &[String],
-    previous_formulae: Option<&[String]>,
-    previous_casks: Option<&[String]>,
-) -> PackageChanges {
-    let mut changes = PackageChanges {
-        added_formulae: 0,
-        removed_formulae: 0,
-        added_casks: 0,
-        removed_casks: 0,
+    previous_formulae: &[String],
+    previous_casks: &[String],
+) -> PackageDiff {
+    let missing_from = |packages: &[String], other: &[String]| -> Vec<String> {
+        let mut names: Vec<String> = packages
+            .iter()
+            .filter(|pkg| !other.contains(pkg))
+            .cloned()
+            .collect();
+        names.sort();
+        names
     };
 
-    if let Some(prev_formulae) = previous_formulae {
-        changes.added_formulae = current_formulae
-            .iter()
-            .filter(|pkg| !prev_formulae.contains(pkg))
-            .count();
-        changes.removed_formulae = prev_formulae
-            .iter()
-            .filter(|pkg| !current_formulae.contains(pkg))
-            .count();
+    PackageDiff {
+        added_formulae: missing_from(current_formulae, previous_formulae),
+        removed_formulae: missing_from(previous_formulae, current_formulae),
+        added_casks: missing_from(current_casks, previous_casks),
+        removed_casks: missing_from(previous_casks, current_casks),
     }
+}
 
-    if let Some(prev_casks) = previous_casks {
-        changes.added_casks = current_casks
-            .iter()
-            .filter(|pkg| !prev_casks.contains(pkg))
-            .count();
-        changes.removed_casks = prev_casks
-            .iter()
-            .filter(|pkg| !current_casks.contains(pkg))
-            .count();
+fn calculate_package_changes(
+    current_formulae: &[String],
+    current_casks: &[String],
+    previous_formulae: Option<&[String]>,
+    previous_casks: Option<&[String]>,
+) -> PackageChanges {
+    // Without a previous list there is nothing to compare, so diff against the current one
+    let diff = diff_packages(
+        current_formulae,
+        current_casks,
+        previous_formulae.unwrap_or(current_formulae),
+        previous_casks.unwrap_or(current_casks),
+    );
+
+    PackageChanges {
+        added_formulae: diff.added_formulae.len(),
+        removed_formulae: diff.removed_formulae.len(),
+        added_casks: diff.added_casks.len(),
+        removed_casks: diff.removed_casks.len(),
     }
-
-    changes
 }
 
 #[cfg(test)]
@@ -273,6 +393,22 @@ mod tests {
         assert_eq!(changes.removed_casks, 1); // firefox
     }
 
+    #[test]
+    fn test_diff_packages_lists_names() {
+        let diff = diff_packages(
+            &["python".to_string(), "git".to_string(), "node".to_string()],
+            &["docker".to_string()],
+            &["git".to_string(), "vim".to_string()],
+            &["docker".to_string(), "firefox".to_string()],
+        );
+
+        assert_eq!(diff.added_formulae, vec!["node", "python"]);
+        assert_eq!(diff.removed_formulae, vec!["vim"]);
+        assert!(diff.added_casks.is_empty());
+        assert_eq!(diff.removed_casks, vec!["firefox"]);
+        assert!(!diff.is_empty());
+    }
+
     #[test]
     fn test_package_changes_has_changes() {
         let no_changes = PackageChanges {
@@ -292,6 +428,21 @@ mod tests {
         assert!(has_changes.has_changes());
     }
 
+    #[test]
+    fn test_package_stats_collect_without_system_tools() -> Result<()> {
+        let executor = MockBrewExecutor::new().with_missing_system_tools();
+        let formulae = vec!["git".to_string()];
+
+        let stats =
+            PackageStats::collect(&executor, &formulae, &[], &HashMap::new(), &[], None, None)?;
+
+        assert_eq!(stats.total_packages, 1);
+        assert_eq!(stats.homebrew_version, "Unknown");
+        assert_eq!(stats.system_info.os_version, "Unknown");
+        assert_eq!(stats.system_info.homebrew_prefix, "Unknown");
+        Ok(())
+    }
+
     #[test]
     fn test_package_stats_collect() -> Result<()> {
         let executor = MockBrewExecutor::new();
@@ -310,6 +461,7 @@ mod tests {
             &formulae,
             &casks,
             &existing_settings,
+            &executor.get_outdated_packages()?,
             Some(&previous_formulae),
             Some(&previous_casks),
         )?;
@@ -369,4 +521,84 @@ mod tests {
         assert!(markdown.contains("**Homebrew Version**: Homebrew 4.1.5"));
         assert!(markdown.contains("**Changes Since Last Dump**"));
     }
+
+    #[test]
+    fn test_every_format_includes_total_packages() -> Result<()> {
+        let stats = stats_with_counts(6, 2, 1);
+
+        assert!(stats
+            .format(StatsFormat::Markdown)?
+            .contains("**Total Packages**: 8"));
+
+        let json: serde_json::Value = serde_json::from_str(&stats.format(StatsFormat::Json)?)?;
+        assert_eq!(json["total_packages"], 8);
+        assert_eq!(json["system_info"]["architecture"], "Apple Silicon");
+
+        let plain = stats.format(StatsFormat::Plain)?;
+        assert!(plain.starts_with("total_packages: 8\n"));
+        assert!(plain.contains("homebrew_prefix: /opt/homebrew\n"));
+
+        Ok(())
+    }
+
+    fn stats_with_counts(enabled: usize, disabled: usize, outdated: usize) -> PackageStats {
+        PackageStats {
+            total_formulae: enabled + disabled,
+            total_casks: 0,
+            total_packages: enabled + disabled,
+            enabled_formulae: enabled,
+            enabled_casks: 0,
+            disabled_formulae: disabled,
+            disabled_casks: 0,
+            outdated_formulae: outdated,
+            outdated_casks: 0,
+            total_outdated: outdated,
+            homebrew_version: "Homebrew 4.1.5".to_string(),
+            system_info: SystemInfo {
+                os_version: "macOS 14.5".to_string(),
+                architecture: "Apple Silicon".to_string(),
+                homebrew_prefix: "/opt/homebrew".to_string(),
+            },
+            changes: PackageChanges {
+                added_formulae: 0,
+                removed_formulae: 0,
+                added_casks: 0,
+                removed_casks: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_recommend_action_outdated() {
+        let stats = stats_with_counts(5, 0, 3);
+        assert_eq!(
+            recommend_action(&stats),
+            "Run `brew-update-helper upgrade` to update 3 packages"
+        );
+
+        let stats = stats_with_counts(5, 0, 1);
+        assert_eq!(
+            recommend_action(&stats),
+            "Run `brew-update-helper upgrade` to update 1 package"
+        );
+    }
+
+    #[test]
+    fn test_recommend_action_high_disabled() {
+        let stats = stats_with_counts(2, 6, 0);
+        let action = recommend_action(&stats);
+        assert!(action.contains("6 of 8 packages are disabled"));
+    }
+
+    #[test]
+    fn test_recommend_action_all_current() {
+        let stats = stats_with_counts(6, 2, 0);
+        assert_eq!(
+            recommend_action(&stats),
+            "Everything is current, nice work!"
+        );
+
+        let markdown = stats.format_as_markdown();
+        assert!(markdown.contains("**Next Step**: Everything is current"));
+    }
 }
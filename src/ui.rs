@@ -1,7 +1,9 @@
 use anyhow::Result;
+use clap::ValueEnum;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
+    style::Stylize,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
@@ -12,39 +14,488 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
     Terminal,
 };
-use std::io::{self, Write};
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
 
-use crate::brew::{OutdatedPackage, PackageType};
+use crate::brew::{BrewExecutor, OutdatedPackage, PackageType};
+use crate::version::{version_jump_kind, VersionJump};
 
+/// Whether a `TerminalGuard` currently owns the terminal.
+static TERMINAL_TAKEN: AtomicBool = AtomicBool::new(false);
+static PANIC_HOOK: Once = Once::new();
+
+/// Puts the terminal into raw mode and restores it when dropped, including
+/// while unwinding from a panic.
 pub struct TerminalGuard;
 
 impl TerminalGuard {
     pub fn new() -> Result<Self> {
+        // The default hook prints the panic message before unwinding reaches
+        // our Drop, which would leave it on the alternate screen; restore first
+        PANIC_HOOK.call_once(|| {
+            let default_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore_terminal();
+                default_hook(info);
+            }));
+        });
         enable_raw_mode()?;
+        TERMINAL_TAKEN.store(true, Ordering::SeqCst);
         Ok(TerminalGuard)
     }
 }
 
 impl Drop for TerminalGuard {
     fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    if TERMINAL_TAKEN.swap(false, Ordering::SeqCst) {
         let _ = disable_raw_mode();
         let _ = execute!(io::stdout(), LeaveAlternateScreen);
         let _ = io::stdout().flush();
     }
 }
 
-pub fn show_interactive_selection(packages: &[&OutdatedPackage]) -> Result<Vec<OutdatedPackage>> {
-    // Skip TUI in test environments to avoid terminal state issues
-    if std::env::var("CI").is_ok()
+/// Raw mode turns Ctrl+C into a key press instead of SIGINT.
+fn is_interrupt(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// What the user chose in the selection UI.
+#[derive(Debug, Default)]
+pub struct SelectionOutcome {
+    /// Packages to upgrade in this run
+    pub selected: Vec<OutdatedPackage>,
+    /// Packages to permanently disable in the settings file
+    pub to_disable: Vec<String>,
+}
+
+/// What the event loop should do after a key press.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SelectionAction {
+    Continue,
+    Proceed,
+    Quit,
+}
+
+/// Selection state for the interactive list, kept apart from the terminal so
+/// key handling can be tested headlessly.
+pub struct SelectionState {
+    pub selected: Vec<bool>,
+    pub to_disable: Vec<bool>,
+    /// Position within the visible (filtered) entries
+    pub cursor: usize,
+    /// Substring the list is narrowed to; empty shows everything
+    pub filter: String,
+    /// Whether typed characters go to the filter instead of acting as keys
+    pub searching: bool,
+    /// How many entries PageUp/PageDown move by; updated from the rendered list height
+    pub page_size: usize,
+    /// Whether the detail panel for the entry under the cursor is open
+    pub show_details: bool,
+    names: Vec<String>,
+}
+
+impl SelectionState {
+    pub fn new(packages: &[&OutdatedPackage]) -> Self {
+        Self {
+            selected: vec![true; packages.len()],
+            to_disable: vec![false; packages.len()],
+            cursor: 0,
+            filter: String::new(),
+            searching: false,
+            page_size: 10,
+            show_details: false,
+            names: packages.iter().map(|pkg| pkg.name.clone()).collect(),
+        }
+    }
+
+    /// Indexes into the package list of the entries matching the filter.
+    pub fn visible(&self) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+        self.names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| name.to_lowercase().contains(&filter))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Index into the package list of the entry under the cursor.
+    pub fn current(&self) -> Option<usize> {
+        self.visible().get(self.cursor).copied()
+    }
+
+    fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+        self.cursor = 0;
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> SelectionAction {
+        if self.searching {
+            match code {
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.set_filter(String::new());
+                }
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Backspace => {
+                    let mut filter = self.filter.clone();
+                    filter.pop();
+                    self.set_filter(filter);
+                }
+                KeyCode::Char(c) => self.set_filter(format!("{}{}", self.filter, c)),
+                KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::PageUp
+                | KeyCode::PageDown
+                | KeyCode::Home
+                | KeyCode::End => self.navigate(code),
+                _ => {}
+            }
+            return SelectionAction::Continue;
+        }
+
+        match code {
+            KeyCode::Char('q') => return SelectionAction::Quit,
+            KeyCode::Char('c') => return SelectionAction::Proceed,
+            KeyCode::Enter => self.show_details = !self.show_details,
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Esc => self.set_filter(String::new()),
+            KeyCode::Up
+            | KeyCode::Down
+            | KeyCode::PageUp
+            | KeyCode::PageDown
+            | KeyCode::Home
+            | KeyCode::End => self.navigate(code),
+            KeyCode::Char(' ') => {
+                if let Some(i) = self.current() {
+                    self.selected[i] = !self.selected[i];
+                }
+            }
+            KeyCode::Char('a') => self.set_all(|_| true),
+            KeyCode::Char('n') => self.set_all(|_| false),
+            KeyCode::Char('i') => self.set_all(|selected| !selected),
+            KeyCode::Char('d') => {
+                if let Some(i) = self.current() {
+                    self.to_disable[i] = !self.to_disable[i];
+                    // A package marked "never upgrade" isn't upgraded now either
+                    if self.to_disable[i] {
+                        self.selected[i] = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+        SelectionAction::Continue
+    }
+
+    /// Update every entry, visible or not; packages marked "never upgrade" stay unselected.
+    fn set_all(&mut self, update: impl Fn(bool) -> bool) {
+        for (selected, disabled) in self.selected.iter_mut().zip(&self.to_disable) {
+            *selected = !disabled && update(*selected);
+        }
+    }
+
+    fn navigate(&mut self, code: KeyCode) {
+        // The cursor only ever points at packages, so header rows can't be landed on
+        let last = self.visible().len().saturating_sub(1);
+        let page = self.page_size.max(1);
+        self.cursor = match code {
+            KeyCode::Up => self.cursor.saturating_sub(1),
+            KeyCode::Down => (self.cursor + 1).min(last),
+            KeyCode::PageUp => self.cursor.saturating_sub(page),
+            KeyCode::PageDown => (self.cursor + page).min(last),
+            KeyCode::Home => 0,
+            KeyCode::End => last,
+            _ => self.cursor,
+        };
+    }
+
+    pub fn outcome(&self, packages: &[&OutdatedPackage]) -> SelectionOutcome {
+        SelectionOutcome {
+            selected: packages
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| self.selected[*i])
+                .map(|(_, pkg)| (*pkg).clone())
+                .collect(),
+            to_disable: packages
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| self.to_disable[*i])
+                .map(|(_, pkg)| pkg.name.clone())
+                .collect(),
+        }
+    }
+}
+
+/// `(+N deps)` marker for formulae that pull in installed dependencies.
+pub fn format_deps(count: Option<usize>) -> String {
+    match count {
+        Some(0) | None => String::new(),
+        Some(1) => "  (+1 dep)".to_string(),
+        Some(n) => format!("  (+{} deps)", n),
+    }
+}
+
+/// Human-readable size, or `—` when unknown.
+pub fn format_size(bytes: Option<u64>) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    let Some(bytes) = bytes else {
+        return "—".to_string();
+    };
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// A row in the interactive list: a group header or a package, by index.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ListRow {
+    Header(&'static str),
+    Package(usize),
+}
+
+/// Lay out the visible packages with a header above each type group. Headers
+/// only exist on screen; the selection state keeps indexing packages.
+pub fn list_rows(packages: &[&OutdatedPackage], visible: &[usize]) -> Vec<ListRow> {
+    // Headers only make sense while each type forms one block (`--sort type`)
+    let type_changes = visible
+        .windows(2)
+        .filter(|pair| packages[pair[0]].package_type != packages[pair[1]].package_type)
+        .count();
+    if type_changes > 1 {
+        return visible.iter().map(|&i| ListRow::Package(i)).collect();
+    }
+
+    let mut rows = Vec::new();
+    let mut current_type = None;
+    for &i in visible {
+        let package_type = &packages[i].package_type;
+        if current_type != Some(package_type) {
+            rows.push(ListRow::Header(match package_type {
+                PackageType::Formula => "── Formulae ──",
+                PackageType::Cask => "── Casks ──",
+            }));
+            current_type = Some(package_type);
+        }
+        rows.push(ListRow::Package(i));
+    }
+    rows
+}
+
+/// `current → available`, with the arrow and new version coloured by how big
+/// the jump is (red major, yellow minor, green patch) when `color` is set.
+pub fn format_version_change(pkg: &OutdatedPackage, color: bool) -> String {
+    let change = format!("→ {}", pkg.available_version);
+    if !color {
+        return format!("{} {}", pkg.current_version, change);
+    }
+
+    let change = match version_jump_kind(&pkg.current_version, &pkg.available_version) {
+        VersionJump::Major => change.red().to_string(),
+        VersionJump::Minor => change.yellow().to_string(),
+        VersionJump::Patch => change.green().to_string(),
+        VersionJump::Unknown => change,
+    };
+    format!("{} {}", pkg.current_version, change)
+}
+
+/// Table rows for `upgrade --list-outdated`, padded so the version arrows line up.
+pub fn outdated_table(packages: &[&OutdatedPackage]) -> Vec<String> {
+    let type_name = |pkg: &OutdatedPackage| match pkg.package_type {
+        PackageType::Formula => "formula",
+        PackageType::Cask => "cask",
+    };
+    let width = |header: &str, column: &dyn Fn(&OutdatedPackage) -> usize| {
+        packages
+            .iter()
+            .map(|pkg| column(pkg))
+            .chain([header.chars().count()])
+            .max()
+            .unwrap_or(0)
+    };
+    let name_width = width("PACKAGE", &|pkg| pkg.name.chars().count());
+    let type_width = width("TYPE", &|pkg| type_name(pkg).len());
+    let current_width = width("CURRENT", &|pkg| pkg.current_version.chars().count());
+
+    let row = |name: &str, package_type: &str, current: &str, available: &str| {
+        format!(
+            "{:<name_width$}  {:<type_width$}  {:>current_width$} → {}",
+            name, package_type, current, available
+        )
+    };
+
+    let mut rows = vec![row("PACKAGE", "TYPE", "CURRENT", "AVAILABLE")];
+    rows.extend(packages.iter().map(|pkg| {
+        row(
+            &pkg.name,
+            type_name(pkg),
+            &pkg.current_version,
+            &pkg.available_version,
+        )
+    }));
+    rows
+}
+
+/// Package descriptions fetched on demand, so only packages the cursor
+/// actually lands on cost a `brew desc` call.
+pub struct DescriptionCache<'a> {
+    executor: &'a dyn BrewExecutor,
+    descriptions: HashMap<String, Option<String>>,
+}
+
+impl<'a> DescriptionCache<'a> {
+    pub fn new(executor: &'a dyn BrewExecutor) -> Self {
+        Self {
+            executor,
+            descriptions: HashMap::new(),
+        }
+    }
+
+    /// `name`'s description; lookup failures are cached as "no description".
+    pub fn get(&mut self, name: &str) -> Option<&str> {
+        let executor = self.executor;
+        self.descriptions
+            .entry(name.to_string())
+            .or_insert_with(|| executor.get_description(name).ok().flatten())
+            .as_deref()
+    }
+}
+
+/// What the detail panel shows for one package.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageDetails {
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    /// Number of dependencies; casks and failed lookups leave it unknown
+    pub dependencies: Option<usize>,
+    /// Disk space of the installed (old) version; unknown for casks
+    pub installed_size: Option<u64>,
+}
+
+impl PackageDetails {
+    /// The panel's lines, with placeholders for anything brew didn't report.
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!(
+                "Description:  {}",
+                self.description.as_deref().unwrap_or("(none)")
+            ),
+            format!(
+                "Homepage:     {}",
+                self.homepage.as_deref().unwrap_or("(none)")
+            ),
+            format!(
+                "Dependencies: {}",
+                self.dependencies
+                    .map_or_else(|| "unknown".to_string(), |count| count.to_string())
+            ),
+            format!("Installed:    {}", format_size(self.installed_size)),
+        ]
+    }
+
+    /// What the panel shows while a package's details are being looked up.
+    pub fn loading_lines() -> Vec<String> {
+        vec!["Loading details...".to_string()]
+    }
+}
+
+/// Package details fetched the first time the detail panel shows a package.
+pub struct DetailsCache<'a> {
+    executor: &'a dyn BrewExecutor,
+    details: HashMap<String, PackageDetails>,
+}
+
+impl<'a> DetailsCache<'a> {
+    pub fn new(executor: &'a dyn BrewExecutor) -> Self {
+        Self {
+            executor,
+            details: HashMap::new(),
+        }
+    }
+
+    /// `pkg`'s details if they've been looked up already.
+    pub fn cached(&self, pkg: &OutdatedPackage) -> Option<&PackageDetails> {
+        self.details.get(&pkg.name)
+    }
+
+    /// `pkg`'s details from `brew info`, `brew desc` (through `descriptions`,
+    /// which the description line shares), `brew deps` and the installed keg;
+    /// failed lookups just leave their field empty.
+    pub fn get(
+        &mut self,
+        pkg: &OutdatedPackage,
+        descriptions: &mut DescriptionCache,
+    ) -> &PackageDetails {
+        let executor = self.executor;
+        self.details.entry(pkg.name.clone()).or_insert_with(|| {
+            let info = executor
+                .get_package_info(&pkg.name, &pkg.package_type)
+                .unwrap_or_default();
+            let description = info
+                .description
+                .or_else(|| descriptions.get(&pkg.name).map(str::to_string));
+            let dependencies = match pkg.package_type {
+                PackageType::Formula => executor.get_deps(&pkg.name).ok().map(|deps| deps.len()),
+                PackageType::Cask => None,
+            };
+            PackageDetails {
+                description,
+                homepage: info.homepage,
+                dependencies,
+                installed_size: executor.get_package_size(pkg).ok().flatten(),
+            }
+        })
+    }
+}
+
+/// CI and test runs, where drawing to the terminal (the TUI, progress bars)
+/// would only garble the output.
+pub fn is_test_environment() -> bool {
+    std::env::var("CI").is_ok()
         || std::env::var("GITHUB_ACTIONS").is_ok()
         || std::env::var("CARGO_TEST").is_ok()
         || cfg!(test)
-    {
-        return show_simple_selection(packages);
-    }
+}
+
+/// Which selection UI `upgrade` shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SelectionMode {
+    /// Full-screen list, except in CI and tests
+    #[default]
+    Auto,
+    /// Always the full-screen list
+    Tui,
+    /// Always the plain-text prompt
+    Simple,
+}
 
+/// `dep_counts` runs parallel to `packages`.
+pub fn show_interactive_selection(
+    executor: &dyn BrewExecutor,
+    packages: &[&OutdatedPackage],
+    dep_counts: &[Option<usize>],
+) -> Result<SelectionOutcome> {
     // Track selection state
-    let mut selected: Vec<bool> = vec![true; packages.len()];
+    let mut state = SelectionState::new(packages);
+    let mut descriptions = DescriptionCache::new(executor);
+    let mut details = DetailsCache::new(executor);
     let mut list_state = ListState::default();
     list_state.select(Some(0));
 
@@ -56,12 +507,40 @@ pub fn show_interactive_selection(packages: &[&OutdatedPackage]) -> Result<Vec<O
     let mut terminal = Terminal::new(backend)?;
 
     loop {
+        let visible = state.visible();
+        let rows = list_rows(packages, &visible);
+        // Highlight the row of the package under the cursor, stepping over headers
+        let cursor_row = visible
+            .get(state.cursor)
+            .and_then(|&i| rows.iter().position(|row| *row == ListRow::Package(i)));
+        list_state.select(cursor_row);
+        let description = state
+            .current()
+            .and_then(|i| descriptions.get(&packages[i].name))
+            .unwrap_or("")
+            .to_string();
+        let (detail_lines, pending_details) = match state.current() {
+            Some(i) if state.show_details => match details.cached(packages[i]) {
+                Some(cached) => (cached.lines(), None),
+                // Draw the panel before the brew calls so a slow lookup doesn't look like a hang
+                None => (PackageDetails::loading_lines(), Some(i)),
+            },
+            _ => (Vec::new(), None),
+        };
+        let mut list_height = state.page_size;
+
         terminal.draw(|f| {
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([
                     Constraint::Length(3),
                     Constraint::Min(0),
+                    // The detail panel takes over the description line while open
+                    Constraint::Length(if detail_lines.is_empty() {
+                        1
+                    } else {
+                        detail_lines.len() as u16 + 2
+                    }),
                     Constraint::Length(2),
                 ])
                 .split(f.size());
@@ -72,11 +551,22 @@ pub fn show_interactive_selection(packages: &[&OutdatedPackage]) -> Result<Vec<O
             f.render_widget(header, chunks[0]);
 
             // Package list
-            let items: Vec<ListItem> = packages
+            let items: Vec<ListItem> = rows
                 .iter()
-                .enumerate()
-                .map(|(i, pkg)| {
-                    let checkbox = if selected[i] { "[x]" } else { "[ ]" };
+                .map(|row| {
+                    let i = match *row {
+                        ListRow::Header(title) => {
+                            return ListItem::new(Line::from(Span::styled(
+                                title,
+                                Style::default()
+                                    .fg(Color::Cyan)
+                                    .add_modifier(Modifier::BOLD),
+                            )));
+                        }
+                        ListRow::Package(i) => i,
+                    };
+                    let pkg = packages[i];
+                    let checkbox = if state.selected[i] { "[x]" } else { "[ ]" };
                     let type_str = match pkg.package_type {
                         PackageType::Formula => "Formula",
                         PackageType::Cask => "Cask",
@@ -86,16 +576,32 @@ pub fn show_interactive_selection(packages: &[&OutdatedPackage]) -> Result<Vec<O
                     let version_text =
                         format!("{} → {}", pkg.current_version, pkg.available_version);
 
-                    let content = Line::from(vec![
+                    let mut spans = vec![
                         Span::styled(checkbox, Style::default().fg(Color::Green)),
                         Span::raw(" "),
                         Span::styled(&pkg.name, Style::default().add_modifier(Modifier::BOLD)),
                         Span::raw(" "),
                         Span::styled(type_text, Style::default().fg(Color::Blue)),
                         Span::raw(version_text),
-                    ]);
+                        Span::styled(
+                            format_deps(dep_counts.get(i).copied().flatten()),
+                            Style::default().fg(Color::Magenta),
+                        ),
+                    ];
+                    if pkg.is_downgrade() {
+                        spans.push(Span::styled(
+                            "  downgrade",
+                            Style::default().fg(Color::Yellow),
+                        ));
+                    }
+                    if state.to_disable[i] {
+                        spans.push(Span::styled(
+                            "  never upgrade",
+                            Style::default().fg(Color::Red),
+                        ));
+                    }
 
-                    ListItem::new(content)
+                    ListItem::new(Line::from(spans))
                 })
                 .collect();
 
@@ -104,74 +610,113 @@ pub fn show_interactive_selection(packages: &[&OutdatedPackage]) -> Result<Vec<O
                 .highlight_style(Style::default().bg(Color::DarkGray));
 
             f.render_stateful_widget(list, chunks[1], &mut list_state);
+            // Inside the borders
+            list_height = usize::from(chunks[1].height.saturating_sub(2));
+
+            if detail_lines.is_empty() {
+                // Description of the highlighted package
+                let description = Paragraph::new(Span::styled(
+                    format!(" {}", description),
+                    Style::default().fg(Color::DarkGray),
+                ));
+                f.render_widget(description, chunks[2]);
+            } else {
+                let lines: Vec<Line> = detail_lines.iter().map(|line| Line::from(line.as_str())).collect();
+                let panel = Paragraph::new(lines)
+                    .block(Block::default().borders(Borders::ALL).title("Details"));
+                f.render_widget(panel, chunks[2]);
+            }
 
             // Footer
-            let footer = Paragraph::new("↑↓: Navigate, SPACE: Toggle, ENTER: Proceed, q: Quit")
-                .block(Block::default().borders(Borders::ALL));
-            f.render_widget(footer, chunks[2]);
+            let footer_text = if state.searching {
+                format!("Search: {}_  (ENTER: Done, ESC: Clear)", state.filter)
+            } else if !state.filter.is_empty() {
+                format!(
+                    "Filter: {}  ↑↓: Navigate, SPACE: Toggle, a/n/i: All/None/Invert, d: Never upgrade, ENTER: Details, /: Search, ESC: Clear, c: Confirm, q: Quit",
+                    state.filter
+                )
+            } else {
+                "↑↓: Navigate, SPACE: Toggle, a/n/i: All/None/Invert, d: Never upgrade, ENTER: Details, /: Search, c: Confirm, q: Quit"
+                    .to_string()
+            };
+            let footer = Paragraph::new(footer_text).block(Block::default().borders(Borders::ALL));
+            f.render_widget(footer, chunks[3]);
         })?;
+        state.page_size = list_height;
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                match key.code {
-                    KeyCode::Char('q') => {
+        if let Some(i) = pending_details {
+            details.get(packages[i], &mut descriptions);
+            continue;
+        }
+
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                let action = if is_interrupt(&key) {
+                    SelectionAction::Quit
+                } else {
+                    state.handle_key(key.code)
+                };
+                match action {
+                    SelectionAction::Continue => {}
+                    SelectionAction::Quit => {
                         execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-                        return Ok(vec![]);
-                    }
-                    KeyCode::Up => {
-                        let i = list_state.selected().unwrap_or(0);
-                        if i > 0 {
-                            list_state.select(Some(i - 1));
-                        }
+                        return Ok(SelectionOutcome::default());
                     }
-                    KeyCode::Down => {
-                        let i = list_state.selected().unwrap_or(0);
-                        if i < packages.len() - 1 {
-                            list_state.select(Some(i + 1));
-                        }
-                    }
-                    KeyCode::Char(' ') => {
-                        if let Some(i) = list_state.selected() {
-                            selected[i] = !selected[i];
-                        }
-                    }
-                    KeyCode::Enter => {
+                    SelectionAction::Proceed => {
                         execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-                        let result = packages
-                            .iter()
-                            .enumerate()
-                            .filter(|(i, _)| selected[*i])
-                            .map(|(_, pkg)| (*pkg).clone())
-                            .collect();
-                        return Ok(result);
+                        return Ok(state.outcome(packages));
                     }
-                    _ => {}
                 }
             }
+            // draw() picks up the new size from the terminal on the next pass
+            Event::Resize(_, _) => {}
+            _ => {}
         }
     }
 }
 
-pub fn show_simple_selection(packages: &[&OutdatedPackage]) -> Result<Vec<OutdatedPackage>> {
-    println!("\nOutdated packages found:");
+/// Ask a yes/no question on stdin; anything but an answer starting with `y` is no.
+/// Prompts go to stderr so they never mix into output meant for pipes.
+pub fn confirm(question: &str) -> Result<bool> {
+    eprint!("{}", question);
+    io::stderr().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+
+    Ok(input.trim().to_lowercase().starts_with('y'))
+}
+
+/// Plain-text fallback for the TUI, printed to stderr like [`confirm`].
+pub fn show_simple_selection(
+    packages: &[&OutdatedPackage],
+    dep_counts: &[Option<usize>],
+) -> Result<SelectionOutcome> {
+    eprintln!("\nOutdated packages found:");
+    let color = io::stderr().is_terminal();
 
     for (i, pkg) in packages.iter().enumerate() {
         let type_str = match pkg.package_type {
             PackageType::Formula => "Formula",
             PackageType::Cask => "Cask",
         };
-        println!(
-            "{}. [x] {} ({}) {} → {}",
+        eprintln!(
+            "{}. [x] {} ({}) {}{}{}",
             i + 1,
             pkg.name,
             type_str,
-            pkg.current_version,
-            pkg.available_version
+            format_version_change(pkg, color),
+            format_deps(dep_counts.get(i).copied().flatten()),
+            if pkg.is_downgrade() {
+                " (downgrade)"
+            } else {
+                ""
+            }
         );
     }
 
-    println!("\nAll packages are selected by default.");
-    println!(
+    eprintln!("\nAll packages are selected by default.");
+    eprintln!(
         "Do you want to proceed with upgrading all {} packages? (y/n): ",
         packages.len()
     );
@@ -182,8 +727,406 @@ pub fn show_simple_selection(packages: &[&OutdatedPackage]) -> Result<Vec<Outdat
     io::stdin().read_line(&mut input)?;
 
     if input.trim().to_lowercase().starts_with('y') {
-        Ok(packages.iter().map(|pkg| (*pkg).clone()).collect())
+        Ok(SelectionOutcome {
+            selected: packages.iter().map(|pkg| (*pkg).clone()).collect(),
+            to_disable: Vec::new(),
+        })
     } else {
-        Ok(vec![])
+        Ok(SelectionOutcome::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packages() -> Vec<OutdatedPackage> {
+        ["git", "node", "docker"]
+            .iter()
+            .map(|name| OutdatedPackage {
+                name: name.to_string(),
+                current_version: "1.0".to_string(),
+                available_version: "1.1".to_string(),
+                package_type: PackageType::Formula,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_selection_state_toggle_and_navigation() {
+        let owned = packages();
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+        let mut state = SelectionState::new(&refs);
+
+        assert_eq!(state.handle_key(KeyCode::Up), SelectionAction::Continue);
+        assert_eq!(state.cursor, 0);
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Char(' '));
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Down);
+        assert_eq!(state.cursor, 2);
+
+        assert_eq!(
+            state.handle_key(KeyCode::Char('c')),
+            SelectionAction::Proceed
+        );
+        let outcome = state.outcome(&refs);
+        let names: Vec<&str> = outcome.selected.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["git", "docker"]);
+        assert!(outcome.to_disable.is_empty());
+    }
+
+    #[test]
+    fn test_selection_state_d_marks_package_to_disable() {
+        let owned = packages();
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+        let mut state = SelectionState::new(&refs);
+
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Char('d'));
+
+        let outcome = state.outcome(&refs);
+        assert_eq!(outcome.to_disable, vec!["node".to_string()]);
+        // Marked packages are also dropped from this run
+        assert!(!outcome.selected.iter().any(|p| p.name == "node"));
+
+        // Pressing d again clears the mark
+        state.handle_key(KeyCode::Char('d'));
+        assert!(state.outcome(&refs).to_disable.is_empty());
+    }
+
+    #[test]
+    fn test_selection_state_search_toggles_underlying_package() {
+        let owned = packages();
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+        let mut state = SelectionState::new(&refs);
+
+        state.handle_key(KeyCode::Char('/'));
+        for c in "ock".chars() {
+            state.handle_key(KeyCode::Char(c));
+        }
+        assert_eq!(state.visible(), vec![2]);
+
+        // While searching, keys are typed into the filter rather than acted on
+        assert_eq!(
+            state.handle_key(KeyCode::Char('q')),
+            SelectionAction::Continue
+        );
+        state.handle_key(KeyCode::Backspace);
+        assert_eq!(state.filter, "ock");
+
+        state.handle_key(KeyCode::Enter);
+        assert!(!state.searching);
+        state.handle_key(KeyCode::Char(' '));
+
+        let outcome = state.outcome(&refs);
+        let names: Vec<&str> = outcome.selected.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["git", "node"]);
+
+        // Esc clears the filter and shows everything again
+        state.handle_key(KeyCode::Esc);
+        assert_eq!(state.visible(), vec![0, 1, 2]);
+        assert_eq!(state.cursor, 0);
+    }
+
+    #[test]
+    fn test_selection_state_navigation_stays_within_filter() {
+        let owned = packages();
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+        let mut state = SelectionState::new(&refs);
+
+        state.handle_key(KeyCode::Char('/'));
+        state.handle_key(KeyCode::Char('O'));
+        assert_eq!(state.visible(), vec![1, 2]);
+
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Down);
+        assert_eq!(state.cursor, 1);
+        state.handle_key(KeyCode::Enter);
+        state.handle_key(KeyCode::Char('d'));
+
+        assert_eq!(state.outcome(&refs).to_disable, vec!["docker".to_string()]);
+    }
+
+    #[test]
+    fn test_selection_state_select_all_none_invert() {
+        let owned = packages();
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+        let mut state = SelectionState::new(&refs);
+
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Char('n'));
+        assert_eq!(state.selected, vec![false, false, false]);
+
+        state.handle_key(KeyCode::Up);
+        state.handle_key(KeyCode::Char(' '));
+        state.handle_key(KeyCode::Char('i'));
+        assert_eq!(state.selected, vec![true, false, true]);
+
+        // Packages marked "never upgrade" stay out of bulk selections
+        state.handle_key(KeyCode::Char('d'));
+        state.handle_key(KeyCode::Char('a'));
+        assert_eq!(state.selected, vec![true, false, true]);
+        state.handle_key(KeyCode::Char('i'));
+        assert_eq!(state.selected, vec![false, false, false]);
+    }
+
+    #[test]
+    fn test_format_size() {
+        let executor = crate::brew::MockBrewExecutor::new();
+        let owned = packages();
+        let git_size = crate::brew::BrewExecutor::get_package_size(&executor, &owned[0]).unwrap();
+
+        assert_eq!(format_size(git_size), "12.3 MB");
+        assert_eq!(format_size(Some(512)), "512 B");
+        assert_eq!(format_size(Some(2048)), "2.0 KB");
+        assert_eq!(format_size(Some(1_610_612_736)), "1.5 GB");
+        assert_eq!(format_size(None), "—");
+    }
+
+    #[test]
+    fn test_format_version_change_colors_by_jump() {
+        let pkg = |available: &str| OutdatedPackage {
+            name: "git".to_string(),
+            current_version: "2.40.0".to_string(),
+            available_version: available.to_string(),
+            package_type: PackageType::Formula,
+        };
+
+        assert_eq!(
+            format_version_change(&pkg("3.0.0"), false),
+            "2.40.0 → 3.0.0"
+        );
+        assert_eq!(
+            format_version_change(&pkg("3.0.0"), true),
+            format!("2.40.0 {}", "→ 3.0.0".red())
+        );
+        assert_eq!(
+            format_version_change(&pkg("2.41.0"), true),
+            format!("2.40.0 {}", "→ 2.41.0".yellow())
+        );
+        assert_eq!(
+            format_version_change(&pkg("2.40.1"), true),
+            format!("2.40.0 {}", "→ 2.40.1".green())
+        );
+        assert_eq!(
+            format_version_change(&pkg("latest"), true),
+            "2.40.0 → latest"
+        );
+    }
+
+    #[test]
+    fn test_outdated_table_aligns_arrows() {
+        let owned = [
+            OutdatedPackage {
+                name: "git".to_string(),
+                current_version: "2.40.0".to_string(),
+                available_version: "2.41.0".to_string(),
+                package_type: PackageType::Formula,
+            },
+            OutdatedPackage {
+                name: "visual-studio-code".to_string(),
+                current_version: "1.80".to_string(),
+                available_version: "1.81.0".to_string(),
+                package_type: PackageType::Cask,
+            },
+        ];
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+
+        assert_eq!(
+            outdated_table(&refs),
+            vec![
+                "PACKAGE             TYPE     CURRENT → AVAILABLE",
+                "git                 formula   2.40.0 → 2.41.0",
+                "visual-studio-code  cask        1.80 → 1.81.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_description_cache() {
+        let executor = crate::brew::MockBrewExecutor::new().with_description("git", "Fast VCS");
+        let mut descriptions = DescriptionCache::new(&executor);
+
+        assert_eq!(descriptions.get("git"), Some("Fast VCS"));
+        assert_eq!(descriptions.get("not-installed"), None);
+        assert_eq!(descriptions.descriptions.len(), 2);
+    }
+
+    #[test]
+    fn test_selection_state_enter_toggles_details() {
+        let owned = packages();
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+        let mut state = SelectionState::new(&refs);
+
+        assert_eq!(state.handle_key(KeyCode::Enter), SelectionAction::Continue);
+        assert!(state.show_details);
+        // The panel follows the cursor instead of closing
+        state.handle_key(KeyCode::Down);
+        assert!(state.show_details);
+        state.handle_key(KeyCode::Enter);
+        assert!(!state.show_details);
+
+        // While searching, ENTER still just keeps the filter
+        state.handle_key(KeyCode::Char('/'));
+        state.handle_key(KeyCode::Enter);
+        assert!(!state.searching);
+        assert!(!state.show_details);
+    }
+
+    #[test]
+    fn test_details_cache() {
+        let executor = crate::brew::MockBrewExecutor::new()
+            .with_package_info(
+                "git",
+                crate::brew::PackageInfo {
+                    homepage: Some("https://git-scm.com".to_string()),
+                    description: None,
+                },
+            )
+            .with_description("git", "Fast VCS");
+        let mut details = DetailsCache::new(&executor);
+        let mut descriptions = DescriptionCache::new(&executor);
+        let owned = packages();
+        let git = owned.iter().find(|pkg| pkg.name == "git").unwrap();
+
+        assert!(details.cached(git).is_none());
+        let git_details = details.get(git, &mut descriptions).clone();
+        assert_eq!(details.cached(git), Some(&git_details));
+        // The description line reuses the lookup
+        assert!(descriptions.descriptions.contains_key("git"));
+        assert_eq!(
+            git_details,
+            PackageDetails {
+                description: Some("Fast VCS".to_string()),
+                homepage: Some("https://git-scm.com".to_string()),
+                dependencies: Some(2),
+                installed_size: Some(12_897_485),
+            }
+        );
+        assert_eq!(
+            git_details.lines(),
+            vec![
+                "Description:  Fast VCS",
+                "Homepage:     https://git-scm.com",
+                "Dependencies: 2",
+                "Installed:    12.3 MB",
+            ]
+        );
+
+        let docker = OutdatedPackage {
+            package_type: PackageType::Cask,
+            ..owned[2].clone()
+        };
+        assert_eq!(details.get(&docker, &mut descriptions).dependencies, None);
+        assert_eq!(details.details.len(), 2);
+        assert_eq!(
+            PackageDetails::default().lines(),
+            vec![
+                "Description:  (none)",
+                "Homepage:     (none)",
+                "Dependencies: unknown",
+                "Installed:    —",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_deps() {
+        let executor = crate::brew::MockBrewExecutor::new();
+        let git_deps = crate::brew::BrewExecutor::get_deps(&executor, "git").unwrap();
+
+        assert_eq!(format_deps(Some(git_deps.len())), "  (+2 deps)");
+        assert_eq!(format_deps(Some(1)), "  (+1 dep)");
+        assert_eq!(format_deps(Some(0)), "");
+        assert_eq!(format_deps(None), "");
+    }
+
+    #[test]
+    fn test_list_rows_adds_group_headers() {
+        let mut owned = packages();
+        owned.push(OutdatedPackage {
+            name: "firefox".to_string(),
+            current_version: "1.0".to_string(),
+            available_version: "1.1".to_string(),
+            package_type: PackageType::Cask,
+        });
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+
+        assert_eq!(
+            list_rows(&refs, &[0, 2, 3]),
+            vec![
+                ListRow::Header("── Formulae ──"),
+                ListRow::Package(0),
+                ListRow::Package(2),
+                ListRow::Header("── Casks ──"),
+                ListRow::Package(3),
+            ]
+        );
+
+        // Groups with nothing visible get no header
+        assert_eq!(
+            list_rows(&refs, &[3]),
+            vec![ListRow::Header("── Casks ──"), ListRow::Package(3)]
+        );
+
+        // Mixed orders (`--sort name`) aren't grouped
+        assert_eq!(
+            list_rows(&refs, &[0, 3, 2]),
+            vec![
+                ListRow::Package(0),
+                ListRow::Package(3),
+                ListRow::Package(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection_state_quit() {
+        let owned = packages();
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+        let mut state = SelectionState::new(&refs);
+        assert_eq!(state.handle_key(KeyCode::Char('q')), SelectionAction::Quit);
+    }
+
+    #[test]
+    fn test_ctrl_c_is_an_interrupt() {
+        assert!(is_interrupt(&KeyEvent::new(
+            KeyCode::Char('c'),
+            KeyModifiers::CONTROL
+        )));
+        // A plain `c` still goes to the search filter
+        assert!(!is_interrupt(&KeyEvent::new(
+            KeyCode::Char('c'),
+            KeyModifiers::NONE
+        )));
+    }
+
+    #[test]
+    fn test_selection_state_page_and_home_end() {
+        let owned = packages();
+        let refs: Vec<&OutdatedPackage> = owned.iter().collect();
+        let mut state = SelectionState::new(&refs);
+        state.page_size = 2;
+
+        state.handle_key(KeyCode::PageDown);
+        assert_eq!(state.cursor, 2);
+        // Never runs past the last package
+        state.handle_key(KeyCode::PageDown);
+        state.handle_key(KeyCode::PageDown);
+        assert_eq!(state.cursor, refs.len() - 1);
+        state.handle_key(KeyCode::PageUp);
+        assert_eq!(state.cursor, refs.len() - 3);
+        state.handle_key(KeyCode::Home);
+        assert_eq!(state.cursor, 0);
+        state.handle_key(KeyCode::End);
+        assert_eq!(state.cursor, refs.len() - 1);
+
+        // End lands on the last match while filtering
+        state.handle_key(KeyCode::Char('/'));
+        state.handle_key(KeyCode::Char('o'));
+        state.handle_key(KeyCode::End);
+        assert_eq!(state.cursor, state.visible().len() - 1);
     }
 }
@@ -1,24 +1,217 @@
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::fs::{self, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-pub fn log_operation(message: &str) -> Result<()> {
-    let log_path = get_log_path()?;
+use crate::brew::OutdatedPackage;
+
+/// Severity of a log entry; ordered so more severe levels compare greater.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum LogLevel {
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    fn tag(self) -> &'static str {
+        match self {
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
+
+/// How each log entry is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// `[timestamp] [LEVEL] message`
+    #[default]
+    Text,
+    /// One `{"timestamp", "level", "message"}` object per line
+    Json,
+}
+
+/// A log line in `--log-format json`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct JsonLogEntry {
+    pub timestamp: String,
+    pub level: String,
+    pub message: String,
+}
+
+impl LogFormat {
+    /// Render one log line, without the trailing newline.
+    pub fn entry(self, timestamp: DateTime<Utc>, level: LogLevel, message: &str) -> String {
+        match self {
+            LogFormat::Text => format!(
+                "[{}] [{}] {}",
+                timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+                level.tag(),
+                message
+            ),
+            LogFormat::Json => serde_json::to_string(&JsonLogEntry {
+                timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+                level: level.tag().to_string(),
+                message: message.to_string(),
+            })
+            .expect("log entries serialize"),
+        }
+    }
+}
+
+/// Size at which the log is rotated before the next append.
+pub const LOG_ROTATE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Number of rotated logs kept (`upgrade.log.1` through `upgrade.log.3`).
+pub const LOG_ROTATE_KEEP: usize = 3;
+
+/// Files the tool keeps in its config directory.
+#[derive(Debug, Clone, Copy)]
+pub enum AppFile {
+    Settings,
+    Log,
+}
+
+impl AppFile {
+    fn file_name(self) -> &'static str {
+        match self {
+            AppFile::Settings => "settings.md",
+            AppFile::Log => "upgrade.log",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            AppFile::Settings => "md",
+            AppFile::Log => "log",
+        }
+    }
+
+    fn development_path(self) -> &'static str {
+        match self {
+            AppFile::Settings => "./brew-settings.md",
+            AppFile::Log => "./brew-update-helper.log",
+        }
+    }
+}
+
+/// Where the tool's files live and how the upgrade log is written, resolved
+/// once from the global flags in `run()` and handed to each command.
+#[derive(Debug, Clone, Default)]
+pub struct AppContext {
+    /// Relocates every file the tool manages (`--config-dir`)
+    pub config_dir: Option<PathBuf>,
+    /// Profile whose settings and log are used instead of the default ones
+    /// (`--profile`); `None` keeps the usual files
+    pub profile: Option<String>,
+    /// Only log entries at this level or above (`--log-level`)
+    pub log_level: LogLevel,
+    /// Text or JSON lines (`--log-format`)
+    pub log_format: LogFormat,
+}
+
+impl AppContext {
+    pub fn new(
+        config_dir: Option<PathBuf>,
+        profile: &str,
+        log_level: LogLevel,
+        log_format: LogFormat,
+    ) -> Result<Self> {
+        if profile.is_empty() || profile.contains(['/', '\\']) || profile.starts_with('.') {
+            anyhow::bail!(
+                "Invalid profile name '{}': use a plain name like 'work'",
+                profile
+            );
+        }
+
+        Ok(Self {
+            config_dir,
+            profile: (profile != DEFAULT_PROFILE).then(|| profile.to_string()),
+            log_level,
+            log_format,
+        })
+    }
+
+    /// Resolve where one of the tool's files lives, honouring `--config-dir` and `--profile`.
+    pub fn file_path(&self, file: AppFile) -> Result<PathBuf> {
+        resolve_app_file(
+            file,
+            self.config_dir.as_deref(),
+            self.profile.as_deref(),
+            std::env::var("CARGO_MANIFEST_DIR").is_ok(),
+        )
+    }
+
+    /// The warning to report when there's nowhere to keep the tool's files
+    /// but the current directory; `None` when a config directory was found
+    /// or isn't needed.
+    pub fn config_dir_warning(&self) -> Option<String> {
+        if self.config_dir.is_some() || std::env::var("CARGO_MANIFEST_DIR").is_ok() {
+            return None;
+        }
+        config_base_dir().ok()?.1
+    }
+
+    pub fn log_path(&self) -> Result<PathBuf> {
+        self.file_path(AppFile::Log)
+    }
+
+    pub fn log(&self, level: LogLevel, message: &str) -> Result<()> {
+        append_log(
+            &self.log_path()?,
+            self.log_level,
+            self.log_format,
+            level,
+            message,
+        )
+    }
+}
+
+/// Entries that `history`, `stats` and `rollback` rebuild sessions from.
+const SESSION_RECORDS: [&str; 4] = [
+    "Starting upgrade of ",
+    "SUCCESS: ",
+    "FAILED: ",
+    "Upgrade session completed",
+];
+
+/// Append an entry to `log_path` in `format`, unless `level` is below
+/// `min_level`. Session records are always written, whatever the level.
+pub fn append_log(
+    log_path: &Path,
+    min_level: LogLevel,
+    format: LogFormat,
+    level: LogLevel,
+    message: &str,
+) -> Result<()> {
+    let session_record = SESSION_RECORDS
+        .iter()
+        .any(|prefix| message.starts_with(prefix));
+    if level < min_level && !session_record {
+        return Ok(());
+    }
 
     // Ensure log directory exists
     if let Some(parent) = log_path.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S UTC");
-    let log_entry = format!("[{}] {}\n", timestamp, message);
+    rotate_log_if_needed(log_path)?;
+
+    let log_entry = format!("{}\n", format.entry(Utc::now(), level, message));
 
     let mut file = OpenOptions::new()
         .create(true)
         .append(true)
-        .open(&log_path)?;
+        .open(log_path)?;
 
     file.write_all(log_entry.as_bytes())?;
     file.flush()?;
@@ -26,16 +219,951 @@ pub fn log_operation(message: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn get_log_path() -> Result<PathBuf> {
+/// Shift `log`, `log.1`, ... up by one once `log` reaches `LOG_ROTATE_BYTES`,
+/// dropping the oldest, so the next append starts a fresh file.
+pub fn rotate_log_if_needed(path: &Path) -> Result<()> {
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Ok(()),
+    };
+    if size < LOG_ROTATE_BYTES {
+        return Ok(());
+    }
+
+    let oldest = rotated_log_path(path, LOG_ROTATE_KEEP);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for n in (1..LOG_ROTATE_KEEP).rev() {
+        let from = rotated_log_path(path, n);
+        if from.exists() {
+            fs::rename(&from, rotated_log_path(path, n + 1))?;
+        }
+    }
+    fs::rename(path, rotated_log_path(path, 1))?;
+
+    Ok(())
+}
+
+/// The log with its rotated files in front, oldest first, so a session that
+/// straddles a rotation reads as one.
+pub fn read_log_with_rotations(path: &Path) -> Result<String> {
+    let mut log = String::new();
+    for n in (1..=LOG_ROTATE_KEEP).rev() {
+        let rotated = rotated_log_path(path, n);
+        if rotated.exists() {
+            log.push_str(&fs::read_to_string(rotated)?);
+        }
+    }
+    if path.exists() {
+        log.push_str(&fs::read_to_string(path)?);
+    }
+    Ok(log)
+}
+
+fn rotated_log_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Successful upgrades from the most recent session in the log, as
+/// `(name, old_version, new_version)` in the order they ran.
+pub fn get_last_session(log: &str) -> Vec<(String, String, String)> {
+    let messages: Vec<Cow<str>> = log.lines().map(log_message).collect();
+    let start = messages
+        .iter()
+        .rposition(|message| message.starts_with("Starting upgrade of "))
+        .map(|pos| pos + 1)
+        .unwrap_or(0);
+
+    messages[start..]
+        .iter()
+        .filter_map(|message| {
+            let entry = message.strip_prefix("SUCCESS: ")?;
+            let (name_and_old, new) = entry.split_once(" → ")?;
+            let (name, old) = name_and_old.split_once(' ')?;
+            // Newer logs follow the version with the upgrade's duration
+            let new = new.split_whitespace().next()?;
+            Some((name.to_string(), old.to_string(), new.to_string()))
+        })
+        .collect()
+}
+
+/// One `Starting upgrade of N packages` run from the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// Timestamp of the session's first line, as written in the log
+    pub started: String,
+    /// Packages the session set out to upgrade
+    pub planned: usize,
+    /// Names of the packages upgraded successfully, in order
+    pub upgraded: Vec<String>,
+    pub failed: usize,
+    /// Whether the `Upgrade session completed` line was reached
+    pub completed: bool,
+}
+
+impl Session {
+    /// When the session started, if its timestamp can be read.
+    pub fn started_at(&self) -> Option<DateTime<Utc>> {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(&self.started) {
+            return Some(timestamp.with_timezone(&Utc));
+        }
+        let naive = self.started.trim().trim_end_matches("UTC").trim();
+        NaiveDateTime::parse_from_str(naive, "%Y-%m-%d %H:%M:%S")
+            .ok()
+            .map(|naive| naive.and_utc())
+    }
+}
+
+/// Every upgrade session in the log, oldest first. Lines outside a session
+/// (e.g. from before sessions were logged) are ignored.
+pub fn parse_log_sessions(log: &str) -> Vec<Session> {
+    let mut sessions: Vec<Session> = Vec::new();
+    for line in log.lines() {
+        let message = log_message(line);
+        if let Some(count) = message
+            .strip_prefix("Starting upgrade of ")
+            .and_then(|rest| rest.strip_suffix(" packages"))
+        {
+            sessions.push(Session {
+                started: log_timestamp(line).unwrap_or_default(),
+                planned: count.parse().unwrap_or(0),
+                upgraded: Vec::new(),
+                failed: 0,
+                completed: false,
+            });
+            continue;
+        }
+        let Some(session) = sessions.last_mut() else {
+            continue;
+        };
+        if let Some(entry) = message.strip_prefix("SUCCESS: ") {
+            if let Some(name) = entry.split_whitespace().next() {
+                session.upgraded.push(name.to_string());
+            }
+        } else if message.starts_with("FAILED: ") {
+            session.failed += 1;
+        } else if message.starts_with("Upgrade session completed") {
+            session.completed = true;
+        }
+    }
+    sessions
+}
+
+/// Parse a `--since` value into a cutoff: a duration back from now (`30m`,
+/// `24h`, `7d`, `2w`) or a date (`2024-01-01`, midnight UTC).
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>> {
+    parse_since_at(value, Utc::now())
+}
+
+/// `parse_since` with durations counted back from `now`.
+pub fn parse_since_at(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(NaiveTime::MIN).and_utc());
+    }
+
+    let invalid = || {
+        anyhow::anyhow!(
+            "invalid --since value '{}': expected a duration like 7d or 24h, or a date like 2024-01-01",
+            value
+        )
+    };
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        "w" => chrono::Duration::try_weeks(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    now.checked_sub_signed(duration).ok_or_else(invalid)
+}
+
+/// The timestamp a log line was written at, text or JSON.
+fn log_timestamp(line: &str) -> Option<String> {
+    if line.starts_with('{') {
+        if let Ok(entry) = serde_json::from_str::<JsonLogEntry>(line) {
+            return Some(entry.timestamp);
+        }
+    }
+    let (stamp, _) = line.strip_prefix('[')?.split_once("] ")?;
+    Some(stamp.to_string())
+}
+
+/// The part of a log line after its `[timestamp] ` prefix and, in logs
+/// written since levels were added, its `[LEVEL] ` tag; or the `message` of a
+/// JSON line.
+fn log_message(line: &str) -> Cow<'_, str> {
+    if line.starts_with('{') {
+        if let Ok(entry) = serde_json::from_str::<JsonLogEntry>(line) {
+            return Cow::Owned(entry.message);
+        }
+    }
+    let message = match line.split_once("] ") {
+        Some((stamp, message)) if stamp.starts_with('[') => message,
+        _ => return Cow::Borrowed(line),
+    };
+    Cow::Borrowed(
+        [LogLevel::Info, LogLevel::Warn, LogLevel::Error]
+            .iter()
+            .find_map(|level| message.strip_prefix(&format!("[{}] ", level.tag())))
+            .unwrap_or(message),
+    )
+}
+
+/// `brew outdated` results saved by `upgrade --cache-ttl`.
+#[derive(Debug, Serialize, Deserialize)]
+struct OutdatedCache {
+    /// Unix timestamp of when brew was asked
+    fetched_at: i64,
+    packages: Vec<OutdatedPackage>,
+}
+
+/// Where the outdated cache for a settings file lives: a hidden file beside it.
+pub fn outdated_cache_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(".brew-outdated-cache.json")
+}
+
+/// Cached outdated packages, if the cache exists, parses, and was written less
+/// than `ttl` before `now`. A cache from the future (clock changes) is stale.
+pub fn read_outdated_cache(
+    path: &Path,
+    ttl: Duration,
+    now: DateTime<Utc>,
+) -> Option<Vec<OutdatedPackage>> {
+    let content = fs::read_to_string(path).ok()?;
+    let cache: OutdatedCache = serde_json::from_str(&content).ok()?;
+    let age = now.timestamp().checked_sub(cache.fetched_at)?;
+    if age < 0 || age as u64 >= ttl.as_secs() {
+        return None;
+    }
+    Some(cache.packages)
+}
+
+pub fn write_outdated_cache(
+    path: &Path,
+    packages: &[OutdatedPackage],
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let cache = OutdatedCache {
+        fetched_at: now.timestamp(),
+        packages: packages.to_vec(),
+    };
+    write_files_atomically(&[(path, &serde_json::to_string(&cache)?)])
+}
+
+/// Bytes reported by `brew cleanup`'s "This operation has freed approximately
+/// 1.2GB of disk space." line. Brew's sizes are binary (1KB = 1024 bytes).
+pub fn parse_cleanup_freed(output: &str) -> Option<u64> {
+    const UNITS: [(&str, f64); 4] = [
+        ("GB", 1024.0 * 1024.0 * 1024.0),
+        ("MB", 1024.0 * 1024.0),
+        ("KB", 1024.0),
+        ("B", 1.0),
+    ];
+
+    let size = output.lines().find_map(|line| {
+        let rest = line.split_once("freed approximately ")?.1;
+        rest.split_whitespace().next()
+    })?;
+    let (number, multiplier) = UNITS
+        .iter()
+        .find_map(|(unit, multiplier)| Some((size.strip_suffix(unit)?, *multiplier)))?;
+    let number: f64 = number.parse().ok()?;
+    Some((number * multiplier).round() as u64)
+}
+
+/// The profile that uses the plain `settings.md` and `upgrade.log`.
+pub const DEFAULT_PROFILE: &str = "default";
+
+fn resolve_app_file(
+    file: AppFile,
+    config_dir: Option<&Path>,
+    profile: Option<&str>,
+    development: bool,
+) -> Result<PathBuf> {
+    // Profiles live in their own folder next to where the default files would be
+    if let Some(profile) = profile {
+        let base = match config_dir {
+            Some(dir) => dir.to_path_buf(),
+            None if development => PathBuf::from("."),
+            None => config_base_dir()?.0.join("brew-update-helper"),
+        };
+        return Ok(base
+            .join("profiles")
+            .join(format!("{}.{}", profile, file.extension())));
+    }
+
+    if let Some(dir) = config_dir {
+        return Ok(dir.join(file.file_name()));
+    }
+
     // For testing, use current directory
-    if std::env::var("CARGO_MANIFEST_DIR").is_ok() {
-        return Ok(PathBuf::from("./brew-update-helper.log"));
+    if development {
+        return Ok(PathBuf::from(file.development_path()));
     }
 
-    // Production: use ~/.config/brew-update-helper/upgrade.log
-    let config_dir = dirs::config_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
-        .join("brew-update-helper");
+    // Production: use ~/.config/brew-update-helper/
+    Ok(config_base_dir()?
+        .0
+        .join("brew-update-helper")
+        .join(file.file_name()))
+}
+
+/// Resolve the directory that holds our config folder.
+///
+/// Falls back from the platform config dir to `$HOME/.config`, and finally to
+/// the current directory on minimal setups where neither is available. That
+/// last fallback comes with a warning for the caller to report.
+pub fn config_base_dir() -> Result<(PathBuf, Option<String>)> {
+    resolve_config_base_dir(dirs::config_dir, || {
+        std::env::var_os("HOME").filter(|home| !home.is_empty())
+    })
+}
 
-    Ok(config_dir.join("upgrade.log"))
+fn resolve_config_base_dir(
+    config_dir: impl Fn() -> Option<PathBuf>,
+    home_dir: impl Fn() -> Option<std::ffi::OsString>,
+) -> Result<(PathBuf, Option<String>)> {
+    if let Some(dir) = config_dir() {
+        return Ok((dir, None));
+    }
+
+    if let Some(home) = home_dir() {
+        return Ok((PathBuf::from(home).join(".config"), None));
+    }
+
+    let current_dir = std::env::current_dir().map_err(|e| {
+        anyhow::anyhow!(
+            "Could not determine config directory: no platform config dir, no $HOME, and the current directory is unavailable ({})",
+            e
+        )
+    })?;
+    let warning = format!(
+        "could not determine a config directory; using {}",
+        current_dir.display()
+    );
+
+    Ok((current_dir, Some(warning)))
+}
+
+/// Write several files so that either all of them are updated or none are.
+///
+/// Every file is first written to a temporary sibling; only once all writes
+/// succeed are they renamed into place. The files being replaced are moved
+/// aside first, so a failed rename puts back the ones already swapped in.
+pub fn write_files_atomically(files: &[(&Path, &str)]) -> Result<()> {
+    let mut staged: Vec<(PathBuf, &Path)> = Vec::new();
+
+    for (path, content) in files {
+        let tmp_path = sibling(path, ".tmp");
+        if let Err(e) = stage_file(path, &tmp_path, content) {
+            let _ = fs::remove_file(&tmp_path);
+            for (tmp, _) in &staged {
+                let _ = fs::remove_file(tmp);
+            }
+            return Err(e);
+        }
+        staged.push((tmp_path, path));
+    }
+
+    let mut committed: Vec<(&Path, Option<PathBuf>)> = Vec::new();
+    for (i, (tmp_path, path)) in staged.iter().enumerate() {
+        match swap_in(tmp_path, path) {
+            Ok(previous) => committed.push((path, previous)),
+            Err(e) => {
+                for (path, previous) in committed.iter().rev() {
+                    let _ = match previous {
+                        Some(previous) => fs::rename(previous, path),
+                        None => fs::remove_file(path),
+                    };
+                }
+                for (tmp, _) in &staged[i..] {
+                    let _ = fs::remove_file(tmp);
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    for previous in committed.into_iter().filter_map(|(_, previous)| previous) {
+        let _ = fs::remove_file(previous);
+    }
+
+    Ok(())
+}
+
+/// Rename `tmp_path` over `path`, returning where the old file was moved to.
+fn swap_in(tmp_path: &Path, path: &Path) -> Result<Option<PathBuf>> {
+    let previous = if path.exists() {
+        let previous = sibling(path, ".old");
+        fs::rename(path, &previous)?;
+        Some(previous)
+    } else {
+        None
+    };
+
+    if let Err(e) = fs::rename(tmp_path, path) {
+        if let Some(previous) = &previous {
+            let _ = fs::rename(previous, path);
+        }
+        return Err(e.into());
+    }
+
+    Ok(previous)
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn stage_file(path: &Path, tmp_path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(tmp_path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_cleanup_freed() {
+        let output = "\
+Removing: /opt/homebrew/Cellar/git/2.40.0... (1,633 files, 48.2MB)
+Removing: /Users/me/Library/Caches/Homebrew/node--20.5.0... (19.9MB)
+==> This operation has freed approximately 68.1MB of disk space.
+";
+        assert_eq!(parse_cleanup_freed(output), Some(71_408_026));
+        assert_eq!(
+            parse_cleanup_freed("==> This operation has freed approximately 1.2GB of disk space."),
+            Some(1_288_490_189)
+        );
+        assert_eq!(
+            parse_cleanup_freed("This operation has freed approximately 512B of disk space."),
+            Some(512)
+        );
+        assert_eq!(
+            parse_cleanup_freed("This operation has freed approximately 3KB of disk space."),
+            Some(3072)
+        );
+        // Nothing to clean prints no summary line
+        assert_eq!(parse_cleanup_freed(""), None);
+        assert_eq!(
+            parse_cleanup_freed("This operation has freed approximately lots of disk space."),
+            None
+        );
+    }
+
+    #[test]
+    fn test_outdated_cache_expiry() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache_path = outdated_cache_path(&temp_dir.path().join("settings.md"));
+        let written = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let packages = vec![OutdatedPackage {
+            name: "git".to_string(),
+            current_version: "2.40.0".to_string(),
+            available_version: "2.41.0".to_string(),
+            package_type: crate::brew::PackageType::Formula,
+        }];
+        write_outdated_cache(&cache_path, &packages, written)?;
+
+        let ttl = Duration::from_secs(300);
+        let at = |secs: i64| written + chrono::Duration::seconds(secs);
+
+        let fresh = read_outdated_cache(&cache_path, ttl, at(299)).unwrap();
+        assert_eq!(fresh.len(), 1);
+        assert_eq!(fresh[0].name, "git");
+        assert!(read_outdated_cache(&cache_path, ttl, at(0)).is_some());
+        assert!(read_outdated_cache(&cache_path, ttl, at(300)).is_none());
+        assert!(read_outdated_cache(&cache_path, ttl, at(-1)).is_none());
+        assert!(read_outdated_cache(&cache_path, Duration::ZERO, at(0)).is_none());
+
+        fs::write(&cache_path, "not json")?;
+        assert!(read_outdated_cache(&cache_path, ttl, at(0)).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_log_filters_below_min_level() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let log_path = temp_dir.path().join("upgrade.log");
+
+        append_log(
+            &log_path,
+            LogLevel::Error,
+            LogFormat::Text,
+            LogLevel::Info,
+            "Starting upgrade of 1 packages",
+        )?;
+        append_log(
+            &log_path,
+            LogLevel::Error,
+            LogFormat::Text,
+            LogLevel::Warn,
+            "RETRY: git attempt 1/1 - boom",
+        )?;
+        append_log(
+            &log_path,
+            LogLevel::Error,
+            LogFormat::Text,
+            LogLevel::Info,
+            "SUCCESS: git 1.0 → 1.1 (1.0s)",
+        )?;
+        append_log(
+            &log_path,
+            LogLevel::Error,
+            LogFormat::Text,
+            LogLevel::Info,
+            "Upgrade session completed: 1 successful, 0 failed",
+        )?;
+
+        // The retry is dropped, but history still sees the whole session
+        let log = fs::read_to_string(&log_path)?;
+        assert!(!log.contains("RETRY"));
+        let sessions = parse_log_sessions(&log);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].upgraded, vec!["git".to_string()]);
+        assert!(sessions[0].completed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_log_format() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let log_path = temp_dir.path().join("upgrade.log");
+
+        append_log(
+            &log_path,
+            LogLevel::Info,
+            LogFormat::Json,
+            LogLevel::Info,
+            "Starting upgrade of 1 packages",
+        )?;
+        append_log(
+            &log_path,
+            LogLevel::Info,
+            LogFormat::Json,
+            LogLevel::Info,
+            "SUCCESS: git 2.40.0 → 2.41.0 (1.2s)",
+        )?;
+
+        let log = fs::read_to_string(&log_path)?;
+        let entry: JsonLogEntry = serde_json::from_str(log.lines().nth(1).unwrap())?;
+        assert_eq!(entry.level, "INFO");
+        assert_eq!(entry.message, "SUCCESS: git 2.40.0 → 2.41.0 (1.2s)");
+        assert!(DateTime::parse_from_rfc3339(&entry.timestamp).is_ok());
+
+        // rollback reads JSON logs too
+        assert_eq!(
+            get_last_session(&log),
+            vec![(
+                "git".to_string(),
+                "2.40.0".to_string(),
+                "2.41.0".to_string()
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_log_format_is_unchanged() {
+        let timestamp = DateTime::parse_from_rfc3339("2024-08-22T10:00:10Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            LogFormat::Text.entry(timestamp, LogLevel::Warn, "RETRY: git attempt 1/1 - boom"),
+            "[2024-08-22 10:00:10 UTC] [WARN] RETRY: git attempt 1/1 - boom"
+        );
+    }
+
+    #[test]
+    fn test_parse_log_sessions() {
+        let log = "\
+[2024-08-01 08:59:00 UTC] Upgraded before sessions were logged
+[2024-08-01 09:00:00 UTC] Starting upgrade of 2 packages
+[2024-08-01 09:00:10 UTC] SUCCESS: git 2.39.0 → 2.40.0
+[2024-08-01 09:00:20 UTC] SUCCESS: node 18.0.0 → 18.16.0
+[2024-08-01 09:00:21 UTC] Upgrade session completed: 2 successful, 0 failed
+[2024-08-22 10:00:00 UTC] [INFO] Starting upgrade of 3 packages
+[2024-08-22 10:00:10 UTC] [INFO] SUCCESS: git 2.40.0 → 2.41.0 (12.4s)
+[2024-08-22 10:00:15 UTC] [WARN] RETRY: docker attempt 1/1 - download interrupted
+[2024-08-22 10:00:20 UTC] [ERROR] FAILED: docker 4.18.0 → 4.19.0 (600.0s) - timed out after 600s
+[2024-08-22 10:00:30 UTC] [INFO] SUCCESS: python@3.11 3.11.4 → 3.11.5
+[2024-08-22 10:00:31 UTC] [INFO] Upgrade session completed: 2 successful, 1 failed
+{\"timestamp\":\"2024-09-01T07:00:00Z\",\"level\":\"INFO\",\"message\":\"Starting upgrade of 1 packages\"}
+";
+
+        let sessions = parse_log_sessions(log);
+        assert_eq!(sessions.len(), 3);
+        assert_eq!(
+            sessions[0],
+            Session {
+                started: "2024-08-01 09:00:00 UTC".to_string(),
+                planned: 2,
+                upgraded: vec!["git".to_string(), "node".to_string()],
+                failed: 0,
+                completed: true,
+            }
+        );
+        assert_eq!(sessions[1].planned, 3);
+        assert_eq!(sessions[1].upgraded, vec!["git", "python@3.11"]);
+        assert_eq!(sessions[1].failed, 1);
+        // Interrupted before it finished
+        assert_eq!(sessions[2].started, "2024-09-01T07:00:00Z");
+        assert!(sessions[2].upgraded.is_empty());
+        assert!(!sessions[2].completed);
+
+        assert!(parse_log_sessions("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_since() -> Result<()> {
+        let now = DateTime::parse_from_rfc3339("2024-08-22T10:00:00Z")?.with_timezone(&Utc);
+        let at = |value: &str| {
+            DateTime::parse_from_rfc3339(value)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+
+        assert_eq!(parse_since_at("7d", now)?, at("2024-08-15T10:00:00Z"));
+        assert_eq!(parse_since_at("24h", now)?, at("2024-08-21T10:00:00Z"));
+        assert_eq!(parse_since_at("30m", now)?, at("2024-08-22T09:30:00Z"));
+        assert_eq!(parse_since_at("2w", now)?, at("2024-08-08T10:00:00Z"));
+        assert_eq!(parse_since_at(" 0d ", now)?, now);
+        assert_eq!(
+            parse_since_at("2024-01-01", now)?,
+            at("2024-01-01T00:00:00Z")
+        );
+
+        for invalid in [
+            "",
+            "7",
+            "d",
+            "-7d",
+            "7 d",
+            "7y",
+            "1.5d",
+            "last week",
+            "2024-13-01",
+            "99999999999999999999d",
+            "9999999999w",
+        ] {
+            let error = parse_since_at(invalid, now).unwrap_err();
+            assert!(
+                error.to_string().contains("invalid --since value"),
+                "{:?}: {}",
+                invalid,
+                error
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_started_at() {
+        let session = |started: &str| Session {
+            started: started.to_string(),
+            planned: 0,
+            upgraded: Vec::new(),
+            failed: 0,
+            completed: true,
+        };
+        let expected = DateTime::parse_from_rfc3339("2024-08-22T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(
+            session("2024-08-22 10:00:00 UTC").started_at(),
+            Some(expected)
+        );
+        assert_eq!(session("2024-08-22T10:00:00Z").started_at(), Some(expected));
+        assert_eq!(session("").started_at(), None);
+    }
+
+    #[test]
+    fn test_get_last_session() {
+        let log = "\
+[2024-08-01 09:00:00 UTC] Starting upgrade of 2 packages
+[2024-08-01 09:00:10 UTC] SUCCESS: git 2.39.0 → 2.40.0
+[2024-08-01 09:00:20 UTC] SUCCESS: node 18.0.0 → 18.16.0
+[2024-08-01 09:00:21 UTC] Upgrade session completed: 2 successful, 0 failed
+[2024-08-22 10:00:00 UTC] [INFO] Starting upgrade of 3 packages
+[2024-08-22 10:00:10 UTC] [INFO] SUCCESS: git 2.40.0 → 2.41.0 (12.4s)
+[2024-08-22 10:00:20 UTC] [ERROR] FAILED: docker 4.18.0 → 4.19.0 (600.0s) - timed out after 600s
+[2024-08-22 10:00:30 UTC] [INFO] SUCCESS: python@3.11 3.11.4 → 3.11.5
+[2024-08-22 10:00:31 UTC] [INFO] Upgrade session completed: 2 successful, 1 failed
+";
+
+        assert_eq!(
+            get_last_session(log),
+            vec![
+                (
+                    "git".to_string(),
+                    "2.40.0".to_string(),
+                    "2.41.0".to_string()
+                ),
+                (
+                    "python@3.11".to_string(),
+                    "3.11.4".to_string(),
+                    "3.11.5".to_string()
+                ),
+            ]
+        );
+        assert!(get_last_session("").is_empty());
+        assert!(
+            get_last_session("[2024-08-22 10:00:00 UTC] Starting upgrade of 1 packages\n")
+                .is_empty()
+        );
+    }
+
+    fn write_oversized_log(path: &Path, marker: &str) -> Result<()> {
+        let mut content = marker.as_bytes().to_vec();
+        content.resize(LOG_ROTATE_BYTES as usize, b'.');
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotate_log_if_needed_rotates_oversized_log() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let log_path = temp_dir.path().join("upgrade.log");
+        write_oversized_log(&log_path, "first")?;
+
+        rotate_log_if_needed(&log_path)?;
+
+        assert!(!log_path.exists());
+        let rotated = fs::read(temp_dir.path().join("upgrade.log.1"))?;
+        assert!(rotated.starts_with(b"first"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotate_log_if_needed_keeps_at_most_three() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let log_path = temp_dir.path().join("upgrade.log");
+
+        for marker in ["one", "two", "three", "four"] {
+            write_oversized_log(&log_path, marker)?;
+            rotate_log_if_needed(&log_path)?;
+        }
+
+        let starts_with = |n: usize, marker: &str| -> Result<bool> {
+            let content = fs::read(temp_dir.path().join(format!("upgrade.log.{}", n)))?;
+            Ok(content.starts_with(marker.as_bytes()))
+        };
+        assert!(starts_with(1, "four")?);
+        assert!(starts_with(2, "three")?);
+        assert!(starts_with(3, "two")?);
+        assert!(!temp_dir.path().join("upgrade.log.4").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_log_with_rotations_reads_oldest_first() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let log_path = temp_dir.path().join("upgrade.log");
+        fs::write(
+            temp_dir.path().join("upgrade.log.2"),
+            "[2024-08-01 09:00:00 UTC] Starting upgrade of 1 packages\n",
+        )?;
+        fs::write(
+            temp_dir.path().join("upgrade.log.1"),
+            "[2024-08-22 10:00:00 UTC] Starting upgrade of 1 packages\n",
+        )?;
+        fs::write(
+            &log_path,
+            "[2024-08-22 10:00:10 UTC] SUCCESS: git 2.40.0 → 2.41.0\n",
+        )?;
+
+        let log = read_log_with_rotations(&log_path)?;
+        assert!(log.starts_with("[2024-08-01"));
+        // The last session began before the rotation and ended after it
+        assert_eq!(
+            get_last_session(&log),
+            vec![(
+                "git".to_string(),
+                "2.40.0".to_string(),
+                "2.41.0".to_string()
+            )]
+        );
+        assert_eq!(parse_log_sessions(&log).len(), 2);
+        assert!(read_log_with_rotations(&temp_dir.path().join("missing.log"))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotate_log_if_needed_leaves_small_log() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let log_path = temp_dir.path().join("upgrade.log");
+        fs::write(&log_path, "[2024-01-01 00:00:00 UTC] SUCCESS: git\n")?;
+
+        rotate_log_if_needed(&log_path)?;
+        // A missing log is fine too
+        rotate_log_if_needed(&temp_dir.path().join("missing.log"))?;
+
+        assert!(log_path.exists());
+        assert!(!temp_dir.path().join("upgrade.log.1").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_app_file_under_config_dir() -> Result<()> {
+        let dir = Path::new("/custom/dir");
+
+        for file in [AppFile::Settings, AppFile::Log] {
+            // The override wins even in development mode
+            let path = resolve_app_file(file, Some(dir), None, true)?;
+            assert!(path.starts_with(dir), "{:?} not under {:?}", path, dir);
+        }
+
+        assert_eq!(
+            resolve_app_file(AppFile::Log, Some(dir), None, false)?,
+            PathBuf::from("/custom/dir/upgrade.log")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_app_file_development() -> Result<()> {
+        assert_eq!(
+            resolve_app_file(AppFile::Settings, None, None, true)?,
+            PathBuf::from("./brew-settings.md")
+        );
+        assert_eq!(
+            resolve_app_file(AppFile::Log, None, None, true)?,
+            PathBuf::from("./brew-update-helper.log")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_app_file_for_profile() -> Result<()> {
+        let dir = Path::new("/custom/dir");
+        assert_eq!(
+            resolve_app_file(AppFile::Settings, Some(dir), Some("work"), false)?,
+            PathBuf::from("/custom/dir/profiles/work.md")
+        );
+        assert_eq!(
+            resolve_app_file(AppFile::Log, Some(dir), Some("work"), false)?,
+            PathBuf::from("/custom/dir/profiles/work.log")
+        );
+        assert_eq!(
+            resolve_app_file(AppFile::Settings, None, Some("home"), true)?,
+            PathBuf::from("./profiles/home.md")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_app_context_rejects_bad_profiles() -> Result<()> {
+        let context = |profile| AppContext::new(None, profile, LogLevel::Info, LogFormat::Text);
+        assert!(context("../work").is_err());
+        assert!(context("a/b").is_err());
+        assert!(context("").is_err());
+        // `default` is always accepted and leaves the usual files in place
+        assert_eq!(context(DEFAULT_PROFILE)?.profile, None);
+        assert_eq!(context("work")?.profile.as_deref(), Some("work"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_config_base_dir_prefers_platform_dir() -> Result<()> {
+        let (dir, warning) = resolve_config_base_dir(
+            || Some(PathBuf::from("/platform/config")),
+            || Some("/home/user".into()),
+        )?;
+        assert_eq!(dir, PathBuf::from("/platform/config"));
+        assert_eq!(warning, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_config_base_dir_falls_back_to_home() -> Result<()> {
+        let (dir, warning) = resolve_config_base_dir(|| None, || Some("/home/user".into()))?;
+        assert_eq!(dir, PathBuf::from("/home/user/.config"));
+        assert_eq!(warning, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_config_base_dir_falls_back_to_current_dir() -> Result<()> {
+        let (dir, warning) = resolve_config_base_dir(|| None, || None)?;
+        assert_eq!(dir, std::env::current_dir()?);
+        let warning = warning.expect("falling back to the current dir is reported");
+        assert!(warning.contains(&dir.display().to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_files_atomically() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let first = temp_dir.path().join("a.md");
+        let second = temp_dir.path().join("nested").join("b.json");
+
+        write_files_atomically(&[(&first, "first"), (&second, "second")])?;
+
+        assert_eq!(fs::read_to_string(&first)?, "first");
+        assert_eq!(fs::read_to_string(&second)?, "second");
+        assert!(!temp_dir.path().join("a.md.tmp").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_files_atomically_leaves_nothing_on_failure() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let good = temp_dir.path().join("good.md");
+        // A directory in place of the parent makes the second write fail
+        let blocker = temp_dir.path().join("blocker");
+        fs::write(&blocker, "not a directory")?;
+        let bad = blocker.join("bad.json");
+
+        assert!(write_files_atomically(&[(&good, "content"), (&bad, "content")]).is_err());
+        assert!(!good.exists());
+        assert!(!temp_dir.path().join("good.md.tmp").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_files_atomically_restores_files_when_a_rename_fails() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let first = temp_dir.path().join("a.md");
+        let second = temp_dir.path().join("b.json");
+        fs::write(&first, "old first")?;
+        fs::write(&second, "old second")?;
+        // A non-empty directory where the second file would be moved aside
+        let blocker = temp_dir.path().join("b.json.old");
+        fs::create_dir(&blocker)?;
+        fs::write(blocker.join("keep"), "")?;
+
+        assert!(write_files_atomically(&[(&first, "new first"), (&second, "new second")]).is_err());
+        assert_eq!(fs::read_to_string(&first)?, "old first");
+        assert_eq!(fs::read_to_string(&second)?, "old second");
+        assert!(!temp_dir.path().join("a.md.old").exists());
+        assert!(!temp_dir.path().join("a.md.tmp").exists());
+        assert!(!temp_dir.path().join("b.json.tmp").exists());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,256 @@
+use anyhow::Result;
+
+use crate::cli::{Cli, Commands};
+use crate::config::CONFIG_ENV_VAR;
+
+/// Environment variables that swap in the mock executor.
+const MOCK_ENV_VARS: [&str; 3] = ["CI", "GITHUB_ACTIONS", "MOCK_BREW"];
+
+/// Where a setting came from, for naming it in error messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Flag(&'static str),
+    Env(&'static str),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Flag(flag) => write!(f, "the {} flag", flag),
+            Source::Env(var) => write!(f, "the {} environment variable", var),
+        }
+    }
+}
+
+/// The settings that matter for conflict checks, with where each came from.
+#[derive(Debug, Default)]
+pub struct ResolvedConfig {
+    pub selftest: bool,
+    pub mock_executor: Option<Source>,
+    pub brew_path: Option<Source>,
+    pub config_file: Option<Source>,
+    pub config_dir: Option<Source>,
+    pub json: Option<Source>,
+    pub also_json: Option<Source>,
+    pub final_json: Option<Source>,
+    pub dry_run: bool,
+    pub show_deps: Option<Source>,
+}
+
+impl ResolvedConfig {
+    pub fn from_cli(cli: &Cli) -> Self {
+        let brew_path = cli.brew_path.as_ref().map(|path| {
+            // clap fills the field from either source; the env var wins only if it matches
+            if std::env::var("BUH_BREW_PATH").ok().as_ref() == Some(path) {
+                Source::Env("BUH_BREW_PATH")
+            } else {
+                Source::Flag("--brew-path")
+            }
+        });
+
+        Self {
+            selftest: matches!(cli.command, Commands::Selftest),
+            mock_executor: MOCK_ENV_VARS
+                .iter()
+                .find(|var| std::env::var(var).is_ok())
+                .map(|var| Source::Env(var)),
+            brew_path,
+            config_file: match &cli.config {
+                Some(_) => Some(Source::Flag("--config")),
+                None => std::env::var(CONFIG_ENV_VAR)
+                    .ok()
+                    .filter(|path| !path.is_empty())
+                    .map(|_| Source::Env(CONFIG_ENV_VAR)),
+            },
+            config_dir: cli
+                .config_dir
+                .as_ref()
+                .map(|_| Source::Flag("--config-dir")),
+            json: cli.json.then_some(Source::Flag("--json")),
+            also_json: match &cli.command {
+                Commands::Dump(args) => {
+                    args.also_json.as_ref().map(|_| Source::Flag("--also-json"))
+                }
+                _ => None,
+            },
+            final_json: match &cli.command {
+                Commands::Upgrade(args) => args.final_json.then_some(Source::Flag("--final-json")),
+                _ => None,
+            },
+            dry_run: cli.dry_run,
+            show_deps: match &cli.command {
+                Commands::Upgrade(args) => args.show_deps.then_some(Source::Flag("--show-deps")),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Reject combinations of settings that contradict each other, before any
+/// brew work happens.
+pub fn validate_config(resolved: &ResolvedConfig) -> Result<()> {
+    if resolved.selftest {
+        // Exported variables are meant for the real commands, so only flags are rejected
+        let ignored = [
+            &resolved.brew_path,
+            &resolved.config_file,
+            &resolved.config_dir,
+        ];
+        if let Some(source) = ignored
+            .into_iter()
+            .flatten()
+            .find(|source| matches!(source, Source::Flag(_)))
+        {
+            anyhow::bail!(
+                "Conflicting configuration: {} has no effect with the selftest subcommand, which always uses the mock executor and a scratch settings file",
+                source
+            );
+        }
+        return Ok(());
+    }
+
+    if let (Some(brew_path), Some(mock)) = (&resolved.brew_path, &resolved.mock_executor) {
+        anyhow::bail!(
+            "Conflicting configuration: {} is ignored because {} switches to the mock executor, which never runs brew",
+            brew_path,
+            mock
+        );
+    }
+
+    if let (Some(json), Some(also_json)) = (&resolved.json, &resolved.also_json) {
+        anyhow::bail!(
+            "Conflicting configuration: {} prints to stdout without writing files, so {} would never be written",
+            json,
+            also_json
+        );
+    }
+
+    if let (Some(json), Some(final_json)) = (&resolved.json, &resolved.final_json) {
+        anyhow::bail!(
+            "Conflicting configuration: {} already prints the results as JSON, so {} would add a second document to stdout",
+            json,
+            final_json
+        );
+    }
+
+    if let (Some(show_deps), false) = (&resolved.show_deps, resolved.dry_run) {
+        anyhow::bail!(
+            "Conflicting configuration: {} only previews a dry run; add --dry-run",
+            show_deps
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_config_brew_path_with_mock_env() {
+        let resolved = ResolvedConfig {
+            brew_path: Some(Source::Flag("--brew-path")),
+            mock_executor: Some(Source::Env("MOCK_BREW")),
+            ..Default::default()
+        };
+
+        let error = validate_config(&resolved).unwrap_err().to_string();
+        assert!(error.contains("the --brew-path flag"));
+        assert!(error.contains("the MOCK_BREW environment variable"));
+    }
+
+    #[test]
+    fn test_validate_config_selftest_with_config_file() {
+        let resolved = ResolvedConfig {
+            selftest: true,
+            config_file: Some(Source::Flag("--config")),
+            ..Default::default()
+        };
+
+        let error = validate_config(&resolved).unwrap_err().to_string();
+        assert!(error.contains("the --config flag"));
+        assert!(error.contains("selftest"));
+
+        let resolved = ResolvedConfig {
+            config_file: Some(Source::Env(CONFIG_ENV_VAR)),
+            ..resolved
+        };
+        assert!(validate_config(&resolved).is_ok());
+    }
+
+    #[test]
+    fn test_validate_config_selftest_with_brew_path() {
+        let resolved = ResolvedConfig {
+            selftest: true,
+            brew_path: Some(Source::Flag("--brew-path")),
+            ..Default::default()
+        };
+
+        let error = validate_config(&resolved).unwrap_err().to_string();
+        assert!(error.contains("the --brew-path flag"));
+        assert!(error.contains("selftest"));
+
+        // An exported variable is left for the other commands
+        let resolved = ResolvedConfig {
+            brew_path: Some(Source::Env("BUH_BREW_PATH")),
+            ..resolved
+        };
+        assert!(validate_config(&resolved).is_ok());
+    }
+
+    #[test]
+    fn test_validate_config_json_with_also_json() {
+        let resolved = ResolvedConfig {
+            json: Some(Source::Flag("--json")),
+            also_json: Some(Source::Flag("--also-json")),
+            ..Default::default()
+        };
+
+        let error = validate_config(&resolved).unwrap_err().to_string();
+        assert!(error.contains("the --json flag"));
+        assert!(error.contains("the --also-json flag"));
+    }
+
+    #[test]
+    fn test_validate_config_json_with_final_json() {
+        let resolved = ResolvedConfig {
+            json: Some(Source::Flag("--json")),
+            final_json: Some(Source::Flag("--final-json")),
+            ..Default::default()
+        };
+
+        let error = validate_config(&resolved).unwrap_err().to_string();
+        assert!(error.contains("the --final-json flag"));
+    }
+
+    #[test]
+    fn test_validate_config_show_deps_needs_dry_run() {
+        let resolved = ResolvedConfig {
+            show_deps: Some(Source::Flag("--show-deps")),
+            ..Default::default()
+        };
+
+        let error = validate_config(&resolved).unwrap_err().to_string();
+        assert!(error.contains("the --show-deps flag"));
+        assert!(error.contains("--dry-run"));
+
+        let resolved = ResolvedConfig {
+            dry_run: true,
+            ..resolved
+        };
+        assert!(validate_config(&resolved).is_ok());
+    }
+
+    #[test]
+    fn test_validate_config_valid_combination() {
+        let resolved = ResolvedConfig {
+            brew_path: Some(Source::Flag("--brew-path")),
+            config_file: Some(Source::Flag("--config")),
+            config_dir: Some(Source::Flag("--config-dir")),
+            ..Default::default()
+        };
+
+        assert!(validate_config(&resolved).is_ok());
+    }
+}
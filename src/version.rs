@@ -0,0 +1,212 @@
+use clap::ValueEnum;
+use std::cmp::Ordering;
+
+/// How big a step an upgrade is, judged from the first three version components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionJump {
+    Major,
+    Minor,
+    Patch,
+    /// The versions can't be compared (e.g. a cask reporting `latest`)
+    Unknown,
+}
+
+/// Smallest version jump `upgrade --min-bump` still offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MinBump {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl MinBump {
+    /// Whether a jump is at least this big. Jumps that can't be classified
+    /// only pass `patch`, the "anything" threshold.
+    pub fn allows(self, jump: VersionJump) -> bool {
+        matches!(
+            (self, jump),
+            (MinBump::Patch, _)
+                | (MinBump::Minor, VersionJump::Major | VersionJump::Minor)
+                | (MinBump::Major, VersionJump::Major)
+        )
+    }
+}
+
+/// Casks without a pinned version report the literal `latest`.
+fn is_unversioned(version: &str) -> bool {
+    version.trim().eq_ignore_ascii_case("latest")
+}
+
+fn components(version: &str) -> Vec<&str> {
+    version
+        .trim()
+        .trim_start_matches(['v', 'V'])
+        .split(['.', '-', '_', ',', '+'])
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Compare two version strings component by component, numerically where
+/// both components are numbers and lexically otherwise.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let a_parts = components(a);
+    let b_parts = components(b);
+
+    for i in 0..a_parts.len().max(b_parts.len()) {
+        let ordering = match (a_parts.get(i), b_parts.get(i)) {
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => x.cmp(y),
+            },
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Whether `available` is newer than `current`. When either side is `latest`
+/// the answer is unknown, so we err on the side of including the package.
+pub fn is_newer(current: &str, available: &str) -> bool {
+    if is_unversioned(current) || is_unversioned(available) {
+        return true;
+    }
+
+    compare_versions(available, current) == Ordering::Greater
+}
+
+/// Whether `available` is the same as or older than `current`. Two `latest`
+/// versions are a re-fetch rather than a downgrade; when only one side is
+/// `latest` there's no numeric order, so fall back to comparing the strings.
+pub fn is_downgrade(current: &str, available: &str) -> bool {
+    match (is_unversioned(current), is_unversioned(available)) {
+        (false, false) => compare_versions(available, current) != Ordering::Greater,
+        (true, true) => false,
+        _ => available.trim() < current.trim(),
+    }
+}
+
+pub fn version_jump_kind(current: &str, available: &str) -> VersionJump {
+    if is_unversioned(current) || is_unversioned(available) {
+        return VersionJump::Unknown;
+    }
+
+    let numeric = |version: &str| -> Option<Vec<u64>> {
+        let parts: Vec<u64> = components(version)
+            .iter()
+            .take(3)
+            .map_while(|part| part.parse().ok())
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts)
+        }
+    };
+
+    let (Some(current), Some(available)) = (numeric(current), numeric(available)) else {
+        return VersionJump::Unknown;
+    };
+
+    let part = |parts: &[u64], i: usize| parts.get(i).copied().unwrap_or(0);
+    if part(&current, 0) != part(&available, 0) {
+        VersionJump::Major
+    } else if part(&current, 1) != part(&available, 1) {
+        VersionJump::Minor
+    } else {
+        VersionJump::Patch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("2.41.0", "2.40.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+        assert_eq!(
+            compare_versions("1.2.3,456", "1.2.3,455"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("2.40.0", "2.41.0"));
+        assert!(!is_newer("2.41.0", "2.41.0"));
+        assert!(!is_newer("2.41.0", "2.40.0"));
+    }
+
+    #[test]
+    fn test_is_newer_treats_latest_as_unknown_and_includes_it() {
+        assert!(is_newer("1.0", "latest"));
+        assert!(is_newer("latest", "latest"));
+        assert!(is_newer("latest", "1.0"));
+    }
+
+    #[test]
+    fn test_is_downgrade() {
+        assert!(is_downgrade("2.41.0", "2.40.0"));
+        assert!(is_downgrade("2.41.0", "2.41.0"));
+        assert!(!is_downgrade("2.40.0", "2.41.0"));
+    }
+
+    #[test]
+    fn test_is_downgrade_falls_back_to_strings_for_latest() {
+        assert!(!is_downgrade("latest", "latest"));
+        assert!(is_downgrade("latest", "1.0"));
+        assert!(!is_downgrade("1.0", "latest"));
+    }
+
+    #[test]
+    fn test_version_jump_kind() {
+        assert_eq!(version_jump_kind("1.2.3", "2.0.0"), VersionJump::Major);
+        assert_eq!(version_jump_kind("1.2.3", "1.3.0"), VersionJump::Minor);
+        assert_eq!(version_jump_kind("1.2.3", "1.2.4"), VersionJump::Patch);
+        assert_eq!(version_jump_kind("2024a", "2024b"), VersionJump::Unknown);
+    }
+
+    #[test]
+    fn test_version_jump_kind_from_same_base() {
+        assert_eq!(version_jump_kind("2.40.0", "3.0.0"), VersionJump::Major);
+        assert_eq!(version_jump_kind("2.40.0", "2.41.0"), VersionJump::Minor);
+        assert_eq!(version_jump_kind("2.40.0", "2.40.1"), VersionJump::Patch);
+        // Non-numeric schemes (commit hashes, dates with letters) can't be classified
+        assert_eq!(version_jump_kind("abc123", "def456"), VersionJump::Unknown);
+    }
+
+    #[test]
+    fn test_min_bump_allows() {
+        let jumps = [
+            VersionJump::Major,
+            VersionJump::Minor,
+            VersionJump::Patch,
+            VersionJump::Unknown,
+        ];
+        let allowed = |min: MinBump| -> Vec<VersionJump> {
+            jumps.into_iter().filter(|&jump| min.allows(jump)).collect()
+        };
+
+        assert_eq!(allowed(MinBump::Major), vec![VersionJump::Major]);
+        assert_eq!(
+            allowed(MinBump::Minor),
+            vec![VersionJump::Major, VersionJump::Minor]
+        );
+        assert_eq!(allowed(MinBump::Patch), jumps.to_vec());
+    }
+
+    #[test]
+    fn test_version_jump_kind_latest_is_unknown() {
+        assert_eq!(version_jump_kind("1.0", "latest"), VersionJump::Unknown);
+        assert_eq!(version_jump_kind("latest", "1.0"), VersionJump::Unknown);
+    }
+}
@@ -0,0 +1,85 @@
+use clap::ValueEnum;
+
+use crate::output::OutputSink;
+
+/// Stable identifiers for the warnings and hints the tool can emit, usable
+/// with `--suppress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum WarningId {
+    /// Casks are checked with `--greedy`, which includes self-updating apps
+    GreedyCasks,
+    /// The settings file lists packages that are no longer installed
+    StaleSettings,
+    /// Outdated packages that aren't tracked in the settings file
+    UntrackedPackages,
+    /// A package named with `--only` has nothing to upgrade
+    NotOutdated,
+    /// The settings file hasn't been regenerated in a while
+    OldSettings,
+    /// No config directory could be found, so the current one is used
+    ConfigDirFallback,
+}
+
+impl WarningId {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WarningId::GreedyCasks => "greedy-casks",
+            WarningId::StaleSettings => "stale-settings",
+            WarningId::UntrackedPackages => "untracked-packages",
+            WarningId::NotOutdated => "not-outdated",
+            WarningId::OldSettings => "old-settings",
+            WarningId::ConfigDirFallback => "config-dir-fallback",
+        }
+    }
+}
+
+/// Emits warnings through an [`OutputSink`], skipping the suppressed ones.
+pub struct Warnings<'a> {
+    out: &'a dyn OutputSink,
+    suppressed: &'a [WarningId],
+}
+
+impl<'a> Warnings<'a> {
+    pub fn new(out: &'a dyn OutputSink, suppressed: &'a [WarningId]) -> Self {
+        Self { out, suppressed }
+    }
+
+    pub fn is_suppressed(&self, id: WarningId) -> bool {
+        self.suppressed.contains(&id)
+    }
+
+    pub fn emit(&self, id: WarningId, message: &str) {
+        if !self.is_suppressed(id) {
+            self.out
+                .warn(&format!("Warning [{}]: {}", id.as_str(), message));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{CapturingSink, OutputLevel};
+
+    #[test]
+    fn test_suppressed_warning_is_hidden() {
+        let sink = CapturingSink::new();
+        let suppressed = [WarningId::StaleSettings];
+        let warnings = Warnings::new(&sink, &suppressed);
+
+        warnings.emit(WarningId::StaleSettings, "settings are stale");
+        warnings.emit(WarningId::GreedyCasks, "casks are greedy");
+
+        let emitted = sink.messages_at(OutputLevel::Warn);
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0], "Warning [greedy-casks]: casks are greedy");
+    }
+
+    #[test]
+    fn test_warning_ids_match_cli_names() {
+        for id in WarningId::value_variants() {
+            let cli_name = id.to_possible_value().unwrap();
+            assert_eq!(cli_name.get_name(), id.as_str());
+        }
+    }
+}
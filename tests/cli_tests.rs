@@ -104,6 +104,1229 @@ Generated on: 2024-08-22 10:30:00 UTC
     std::env::remove_var("CI");
 }
 
+#[test]
+fn test_cli_upgrade_yes_selects_everything_without_prompting() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--dry-run")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--yes")
+        .arg("--formula-only")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would upgrade git"))
+        .stdout(predicate::str::contains("Would upgrade docker").not())
+        .stdout(predicate::str::contains("Outdated packages found").not());
+}
+
+#[test]
+fn test_cli_quiet_keeps_only_results() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--dry-run")
+        .arg("--quiet")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--yes")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Running upgrade command").not())
+        .stdout(predicate::str::contains("Checking for outdated packages").not())
+        .stdout(predicate::str::contains("Dry run completed"));
+}
+
+#[test]
+fn test_cli_upgrade_skip_auto_updates() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--dry-run")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--yes")
+        .arg("--skip-auto-updates")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Skipping docker: the app updates itself",
+        ))
+        .stdout(predicate::str::contains("Would upgrade git"))
+        .stdout(predicate::str::contains("Would upgrade docker").not());
+}
+
+#[test]
+fn test_cli_upgrade_filter_installed() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(&config_path, "## Formulae\n\n- [x] git\n- [x] wget\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--dry-run")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--yes")
+        .arg("--filter-installed")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would upgrade git"));
+}
+
+#[test]
+fn test_cli_upgrade_retries_is_bounded() {
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .args(["upgrade", "--retries", "100"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--retries"));
+}
+
+#[test]
+fn test_cli_upgrade_failure_exits_with_code_1() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .env("MOCK_BREW_FAIL_UPGRADES", "docker")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--yes")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("1 successful, 1 failed"))
+        .stderr(predicate::str::contains("1 upgrade(s) failed"));
+}
+
+#[test]
+fn test_cli_missing_brew_exits_with_code_2() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env_remove("CI")
+        .env_remove("GITHUB_ACTIONS")
+        .env_remove("MOCK_BREW")
+        .arg("--brew-path")
+        .arg(
+            temp_dir
+                .path()
+                .join("no-brew")
+                .to_string_lossy()
+                .to_string(),
+        )
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("upgrade")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_cli_upgrade_dry_run_explain() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+
+    let settings_content = r#"# Brew Auto-Update Settings
+
+## Formulae
+
+- [x] git
+- [x] node
+
+## Casks
+
+- [ ] docker"#;
+
+    fs::write(&config_path, settings_content).unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--dry-run")
+        .arg("--explain")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "git: enabled ✓, outdated ✓ → will upgrade",
+        ))
+        .stdout(predicate::str::contains(
+            "node: enabled ✓, outdated ✗ → skipped (up to date)",
+        ))
+        .stdout(predicate::str::contains(
+            "docker: enabled ✗ → skipped (disabled in settings)",
+        ));
+}
+
+#[test]
+fn test_cli_upgrade_explain_names_deselected_packages() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(&config_path, "## Formulae\n\n- [x] git\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--dry-run")
+        .arg("--explain")
+        .arg("--interactive")
+        .arg("simple")
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "git: enabled ✓, outdated ✓, selected ✗ → skipped (not selected)",
+        ));
+}
+
+#[test]
+fn test_cli_upgrade_final_json_is_last_line() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    let output = cmd
+        .env("CI", "true")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--final-json")
+        .write_stdin("y\n")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Upgrade completed!"));
+
+    let last_line = stdout.trim_end_matches('\n').lines().last().unwrap();
+    let summary: serde_json::Value = serde_json::from_str(last_line).unwrap();
+    assert_eq!(summary["candidates"], 2);
+    assert_eq!(summary["selected"], 2);
+    assert_eq!(summary["successful"], 2);
+    assert_eq!(summary["failed"], 0);
+    assert_eq!(summary["dry_run"], false);
+    assert!(summary["total_duration_ms"].is_u64());
+}
+
+#[test]
+fn test_cli_upgrade_json_results() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    let output = cmd
+        .env("CI", "true")
+        .env("MOCK_BREW_FAIL_UPGRADES", "docker")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("--json")
+        .arg("upgrade")
+        .arg("--yes")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        results,
+        serde_json::json!([
+            {"name": "docker", "from": "4.18.0", "to": "4.19.0", "status": "failed"},
+            {"name": "git", "from": "2.40.0", "to": "2.41.0", "status": "upgraded"}
+        ])
+    );
+}
+
+#[test]
+fn test_cli_dump_with_config_dir() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("dump")
+        .assert()
+        .success();
+
+    assert!(temp_dir.path().join("settings.md").exists());
+}
+
+#[test]
+fn test_cli_dump_preserves_user_notes() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [ ] git\n  > held back until the work laptop updates\n\n## Casks\n\n- [x] docker\n\n## Notes\n\nReview casks monthly.\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("dump")
+        .assert()
+        .success();
+
+    let content = fs::read_to_string(&config_path).unwrap();
+    let git_line = content
+        .lines()
+        .position(|l| l.starts_with("- [ ] git"))
+        .unwrap();
+    assert_eq!(
+        content.lines().nth(git_line + 1),
+        Some("  > held back until the work laptop updates")
+    );
+    assert!(content.contains("## Notes\n\nReview casks monthly.\n"));
+}
+
+#[test]
+fn test_cli_toml_settings_backend() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.toml");
+    fs::write(
+        &config_path,
+        "[formulae]\ngit = false\n\n[casks]\ndocker = true\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("dump")
+        .assert()
+        .success();
+
+    let content = fs::read_to_string(&config_path).unwrap();
+    assert!(content.contains("[formulae]\ngit = false\n"));
+    assert!(content.contains("docker = true"));
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--dry-run")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--yes")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would upgrade docker"))
+        .stdout(predicate::str::contains("Would upgrade git").not());
+}
+
+#[test]
+fn test_cli_dump_default_disabled_keeps_existing_choices() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(&config_path, "## Formulae\n\n- [x] git\n\n## Casks\n\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("dump")
+        .arg("--default-disabled")
+        .assert()
+        .success();
+
+    let content = fs::read_to_string(&config_path).unwrap();
+    assert!(content.contains("- [x] git"));
+    assert!(content.contains("- [ ] node\n"));
+    assert!(content.contains("- [ ] firefox\n"));
+}
+
+#[test]
+fn test_cli_dump_json_stdout_is_pure_json() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    let output = cmd
+        .env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("dump")
+        .arg("--json")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(report["formulae"].is_array());
+    assert!(!config_path.exists());
+}
+
+#[test]
+fn test_cli_export_brewfile_to_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    let brewfile_path = temp_dir.path().join("Brewfile");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n- [ ] node\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("export")
+        .arg("--format")
+        .arg("brewfile")
+        .arg("--output")
+        .arg(brewfile_path.to_string_lossy().to_string())
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&brewfile_path).unwrap(),
+        "brew \"git\"\ncask \"docker\"\n"
+    );
+}
+
+#[test]
+fn test_cli_snapshot_writes_versions() {
+    let temp_dir = TempDir::new().unwrap();
+    let snapshot_path = temp_dir.path().join("snapshot.json");
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("snapshot")
+        .arg("--output")
+        .arg(snapshot_path.to_string_lossy().to_string())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Snapshot of 6 package(s) written to",
+        ));
+
+    let snapshot: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&snapshot_path).unwrap()).unwrap();
+    assert!(snapshot["generated_on"].as_str().unwrap().ends_with(" UTC"));
+    let packages = snapshot["packages"].as_array().unwrap();
+    assert_eq!(packages.len(), 6);
+    assert!(packages.contains(&serde_json::json!({
+        "name": "git",
+        "version": "2.40.0",
+        "type": "formula"
+    })));
+    assert!(packages.contains(&serde_json::json!({
+        "name": "docker",
+        "version": "4.18.0",
+        "type": "cask"
+    })));
+}
+
+#[test]
+fn test_cli_stats_output_formats() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+
+    let stats = |format: &str| {
+        let output = Command::cargo_bin("brew-update-helper")
+            .unwrap()
+            .env("CI", "true")
+            .arg("--config")
+            .arg(config_path.to_string_lossy().to_string())
+            .arg("stats")
+            .arg("--output-format")
+            .arg(format)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert!(stats("markdown").contains("**Total Packages**: 6"));
+    assert!(stats("plain").contains("total_packages: 6\n"));
+    let json: serde_json::Value = serde_json::from_str(&stats("json")).unwrap();
+    assert_eq!(json["total_packages"], 6);
+}
+
+#[test]
+fn test_cli_stats_without_settings() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("stats")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("## Statistics"))
+        .stdout(predicate::str::contains("Changes Since Last Dump").not());
+
+    assert!(!config_path.exists());
+}
+
+#[test]
+fn test_cli_diff_lists_added_and_removed_names() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n- [x] vim\n\n## Casks\n\n- [x] docker\n- [x] firefox\n- [x] visual-studio-code\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("diff")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Formulae:\n  + node\n  + python\n  - vim",
+        ))
+        .stdout(predicate::str::contains("Casks:").not());
+}
+
+#[test]
+fn test_cli_rollback_prints_commands_for_last_session() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("upgrade.log"),
+        "[2024-08-01 09:00:00 UTC] Starting upgrade of 1 packages\n\
+         [2024-08-01 09:00:10 UTC] SUCCESS: node 18.0.0 → 18.16.0\n\
+         [2024-08-22 10:00:00 UTC] Starting upgrade of 1 packages\n\
+         [2024-08-22 10:00:10 UTC] SUCCESS: git 2.40.0 → 2.41.0\n\
+         [2024-08-22 10:00:20 UTC] SUCCESS: docker 4.17.0 → 4.18.0\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("rollback")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "brew extract --version=2.40.0 git brew-update-helper/rollback",
+        ))
+        .stdout(predicate::str::contains(
+            "brew install brew-update-helper/rollback/git@2.40.0",
+        ))
+        .stdout(predicate::str::contains(
+            "# docker is a cask: install 4.17.0 from the vendor's old release",
+        ))
+        .stdout(predicate::str::contains("brew extract --version=4.17.0 docker").not())
+        .stdout(predicate::str::contains("node").not());
+}
+
+#[test]
+fn test_cli_history_reads_rotated_logs() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("upgrade.log.1"),
+        "[2024-08-01 09:00:00 UTC] Starting upgrade of 1 packages\n\
+         [2024-08-01 09:00:10 UTC] SUCCESS: node 18.0.0 → 18.16.0\n",
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("upgrade.log"),
+        "[2024-08-22 10:00:00 UTC] Starting upgrade of 1 packages\n\
+         [2024-08-22 10:00:10 UTC] SUCCESS: git 2.40.0 → 2.41.0\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2024-08-01 09:00:00 UTC"))
+        .stdout(predicate::str::contains("2024-08-22 10:00:00 UTC"));
+}
+
+#[test]
+fn test_cli_history_summarises_sessions() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("upgrade.log"),
+        "[2024-08-01 09:00:00 UTC] Starting upgrade of 2 packages\n\
+         [2024-08-01 09:00:10 UTC] SUCCESS: node 18.0.0 → 18.16.0\n\
+         [2024-08-01 09:00:20 UTC] SUCCESS: git 2.39.0 → 2.40.0\n\
+         [2024-08-01 09:00:21 UTC] Upgrade session completed: 2 successful, 0 failed\n\
+         [2024-08-22 10:00:00 UTC] [INFO] Starting upgrade of 2 packages\n\
+         [2024-08-22 10:00:10 UTC] [INFO] SUCCESS: git 2.40.0 → 2.41.0 (12.4s)\n\
+         [2024-08-22 10:00:20 UTC] [ERROR] FAILED: docker 4.18.0 → 4.19.0 (3.0s) - boom\n\
+         [2024-08-22 10:00:21 UTC] [INFO] Upgrade session completed: 1 successful, 1 failed\n",
+    )
+    .unwrap();
+    let config_dir = temp_dir.path().to_string_lossy().to_string();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(&config_dir)
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "2024-08-01 09:00:00 UTC  2 planned, 2 succeeded, 0 failed",
+        ))
+        .stdout(predicate::str::contains(
+            "2024-08-22 10:00:00 UTC  2 planned, 1 succeeded, 1 failed",
+        ))
+        .stdout(predicate::str::contains("git (2×)"));
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(&config_dir)
+        .args(["history", "--limit", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Upgrade sessions (1 of 2):"))
+        .stdout(predicate::str::contains("2024-08-01").not());
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(&config_dir)
+        .args(["history", "--since", "2024-08-15"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Upgrade sessions (1 of 1):"))
+        .stdout(predicate::str::contains("2024-08-01").not());
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(&config_dir)
+        .args(["history", "--since", "last week"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid --since value"));
+}
+
+#[test]
+fn test_cli_diff_rejects_since() {
+    // The dump records a single timestamp, so there is nothing for --since to filter
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .args(["diff", "--since", "7d"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--since"));
+}
+
+#[test]
+fn test_cli_no_greedy_skips_greedy_casks_warning() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [ ] git\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+    let config = config_path.to_string_lossy().to_string();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .args(["--config", &config, "--dry-run", "upgrade", "--yes"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("--greedy"));
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .args([
+            "--config",
+            &config,
+            "--no-greedy",
+            "--dry-run",
+            "upgrade",
+            "--yes",
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("--greedy").not());
+
+    // The same choice can live in the settings header
+    fs::write(
+        &config_path,
+        "Greedy casks: no\n\n## Formulae\n\n- [ ] git\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .args(["--config", &config, "--dry-run", "upgrade", "--yes"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("--greedy").not());
+}
+
+#[test]
+fn test_cli_drift_reports_changed_versions() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git @2.39.0\n- [x] node @20.5.0\n- [x] vim @9.0\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("drift")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("git: 2.39.0 → 2.40.0"))
+        .stdout(predicate::str::contains("vim: 9.0 → not installed"))
+        .stdout(predicate::str::contains("node").not());
+}
+
+#[test]
+fn test_cli_list_without_brew() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n- [ ] node\n\n## Casks\n\n- [ ] docker\n",
+    )
+    .unwrap();
+
+    // A brew path that doesn't exist proves list never calls brew
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env_remove("CI")
+        .env_remove("GITHUB_ACTIONS")
+        .env_remove("MOCK_BREW")
+        .arg("--brew-path")
+        .arg(
+            temp_dir
+                .path()
+                .join("no-brew")
+                .to_string_lossy()
+                .to_string(),
+        )
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("list")
+        .arg("--disabled-only")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Formulae:\n  [ ] node\n\nCasks:\n  [ ] docker\n",
+        ))
+        .stdout(predicate::str::contains("git").not());
+}
+
+#[test]
+fn test_cli_validate_reports_problems() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n- [ ] git\n- [x] vim\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("validate")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("❌ git is listed more than once"))
+        .stdout(predicate::str::contains("❌ vim is no longer installed"))
+        .stderr(predicate::str::contains("2 problem(s)"));
+}
+
+#[test]
+fn test_cli_upgrade_list_outdated() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("dump")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--list-outdated")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PACKAGE"))
+        .stdout(predicate::str::contains(
+            "git      formula   2.40.0 → 2.41.0",
+        ))
+        .stdout(predicate::str::contains("Select packages").not())
+        .stdout(predicate::str::contains("Upgrading").not());
+}
+
+#[test]
+fn test_cli_upgrade_cache_ttl_reuses_outdated_results() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    let cache_path = temp_dir.path().join(".brew-outdated-cache.json");
+
+    let run = |extra: &[&str]| {
+        let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+        cmd.env("CI", "true")
+            .arg("--config")
+            .arg(config_path.to_string_lossy().to_string())
+            .arg("--dry-run")
+            .arg("upgrade")
+            .arg("--list-outdated")
+            .args(extra)
+            .assert()
+            .success()
+    };
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("dump")
+        .assert()
+        .success();
+
+    run(&["--cache-ttl", "600"])
+        .stdout(predicate::str::contains("Using cached").not())
+        .stdout(predicate::str::contains("git"));
+    assert!(cache_path.exists());
+
+    run(&["--cache-ttl", "600"])
+        .stdout(predicate::str::contains("Using cached outdated packages"))
+        .stdout(predicate::str::contains("git"));
+
+    run(&["--cache-ttl", "0"]).stdout(predicate::str::contains("Using cached").not());
+    run(&["--no-cache"]).stdout(predicate::str::contains("Using cached").not());
+}
+
+#[test]
+fn test_cli_config_env_var() {
+    let temp_dir = TempDir::new().unwrap();
+    let env_path = temp_dir.path().join("from-env.md");
+    let flag_path = temp_dir.path().join("from-flag.md");
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .env("BREW_UPDATE_HELPER_CONFIG", &env_path)
+        .arg("dump")
+        .assert()
+        .success();
+    assert!(env_path.exists());
+
+    // --config still wins over the variable
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .env("BREW_UPDATE_HELPER_CONFIG", &env_path)
+        .arg("--config")
+        .arg(flag_path.to_string_lossy().to_string())
+        .arg("dump")
+        .assert()
+        .success();
+    assert!(flag_path.exists());
+
+    // selftest works on a scratch file and leaves the configured one alone
+    let before = fs::read_to_string(&env_path).unwrap();
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .env("BREW_UPDATE_HELPER_CONFIG", &env_path)
+        .arg("selftest")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Self-test passed"));
+    assert_eq!(fs::read_to_string(&env_path).unwrap(), before);
+}
+
+#[test]
+fn test_cli_clean_removes_uninstalled_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n- [ ] vim\n\n## Casks\n\n- [x] docker\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("--dry-run")
+        .arg("clean")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would remove vim"));
+    assert!(fs::read_to_string(&config_path).unwrap().contains("vim"));
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("clean")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed vim"));
+    let content = fs::read_to_string(&config_path).unwrap();
+    assert!(!content.contains("vim"));
+    assert!(content.contains("- [x] git"));
+}
+
+#[test]
+fn test_cli_upgrade_interactive_simple() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("dump")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--interactive")
+        .arg("simple")
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Outdated packages found:"))
+        .stderr(predicate::str::contains("Do you want to proceed"));
+
+    // Prompts stay off stdout, so --json output remains parseable
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    let output = cmd
+        .env("CI", "true")
+        .arg("--dry-run")
+        .arg("--json")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .write_stdin("y\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(!results.as_array().unwrap().is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Do you want to proceed"));
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("upgrade")
+        .arg("--interactive")
+        .arg("fancy")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "possible values: auto, tui, simple",
+        ));
+}
+
+#[test]
+fn test_cli_upgrade_shows_per_package_args() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(
+        &config_path,
+        "## Formulae\n\n- [x] git\n\n## Casks\n\n- [x] docker {args: --no-quarantine}\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("--dry-run")
+        .arg("upgrade")
+        .arg("--yes")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Would upgrade docker 4.18.0 → 4.19.0 (with --no-quarantine)",
+        ))
+        .stdout(predicate::str::contains(
+            "Would upgrade git 2.40.0 → 2.41.0\n",
+        ));
+}
+
+#[test]
+fn test_cli_upgrade_cleanup_reports_freed_space() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("settings.md");
+    fs::write(&config_path, "## Formulae\n\n- [x] git\n\n## Casks\n\n").unwrap();
+
+    // A real (mock) upgrade writes the log, so keep it in the temp dir
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--yes")
+        .arg("--cleanup")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Cleanup freed approximately 48.2 MB",
+        ));
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("--dry-run")
+        .arg("upgrade")
+        .arg("--yes")
+        .arg("--cleanup")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("cleanup").not());
+}
+
+#[test]
+fn test_cli_doctor() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("doctor")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("✅ brew installed"))
+        .stdout(predicate::str::contains("✅ config directory writable"))
+        .stdout(predicate::str::contains("❌ settings file parses"))
+        .stdout(predicate::str::contains("✅ log file writable"));
+}
+
+#[test]
+fn test_cli_paths_without_brew() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("custom.md");
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env_remove("CI")
+        .env_remove("GITHUB_ACTIONS")
+        .env_remove("MOCK_BREW")
+        .arg("--brew-path")
+        .arg(
+            temp_dir
+                .path()
+                .join("no-brew")
+                .to_string_lossy()
+                .to_string(),
+        )
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("--config")
+        .arg(config_path.to_string_lossy().to_string())
+        .arg("paths")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Settings: {}",
+            config_path.display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "Log:      {}",
+            temp_dir.path().join("upgrade.log").display()
+        )));
+}
+
+#[test]
+fn test_cli_profile_paths() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .env_remove("BREW_UPDATE_HELPER_CONFIG")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("--profile")
+        .arg("work")
+        .arg("paths")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Settings: {}",
+            temp_dir.path().join("profiles").join("work.md").display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "Log:      {}",
+            temp_dir.path().join("profiles").join("work.log").display()
+        )));
+}
+
+#[test]
+fn test_cli_upgrade_unknown_profile_needs_dump() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("settings.md"),
+        "## Formulae\n\n- [x] git\n",
+    )
+    .unwrap();
+
+    // The default profile's settings don't leak into another profile
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .env_remove("BREW_UPDATE_HELPER_CONFIG")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("--profile")
+        .arg("personal")
+        .arg("upgrade")
+        .arg("--yes")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Run 'dump' command first"));
+}
+
+#[test]
+fn test_cli_upgrade_without_settings_fails_fast() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // Without a terminal (and with --no-wizard) there's no setup prompt to hang on
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .env_remove("BREW_UPDATE_HELPER_CONFIG")
+        .arg("--config-dir")
+        .arg(temp_dir.path().to_string_lossy().to_string())
+        .arg("upgrade")
+        .arg("--no-wizard")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Settings file not found"));
+    assert!(!temp_dir.path().join("settings.md").exists());
+}
+
+#[test]
+fn test_cli_selftest() {
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.arg("selftest")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PASS dump settings"))
+        .stdout(predicate::str::contains("PASS parse settings"))
+        .stdout(predicate::str::contains("PASS upgrade (dry run)"))
+        .stdout(predicate::str::contains("FAIL").not());
+}
+
+#[test]
+fn test_cli_selftest_ignores_brew_path_env() {
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("BUH_BREW_PATH", "/opt/homebrew/bin/brew")
+        .arg("selftest")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Self-test passed"));
+
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.arg("--brew-path")
+        .arg("/opt/homebrew/bin/brew")
+        .arg("selftest")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("the --brew-path flag"));
+}
+
+#[test]
+fn test_cli_upgrade_formula_only_conflicts_with_cask_only() {
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.env("CI", "true")
+        .arg("upgrade")
+        .arg("--formula-only")
+        .arg("--cask-only")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_cli_completions_zsh() {
+    let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();
+    cmd.arg("completions")
+        .arg("zsh")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("_brew-update-helper"))
+        .stdout(predicate::str::contains("--dry-run"));
+}
+
 #[test]
 fn test_cli_help() {
     let mut cmd = Command::cargo_bin("brew-update-helper").unwrap();